@@ -1,6 +1,7 @@
 use proto::generated::client_namenode::ChunkMeta;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt, copy};
 use utilities::{
+    checksum::{VerifyingReader, is_checksum_mismatch},
     logger::{instrument, trace, tracing},
     result::Result,
 };
@@ -34,6 +35,7 @@ impl ChunkJoiner {
         &self,
         chunk_details: &ChunkMeta,
         reader: &mut (impl tokio::io::AsyncRead + Unpin),
+        checksums: Option<Vec<u32>>,
     ) -> Result<()> {
         // this function create diffrent file descripter every time
         let mut file = tokio::fs::OpenOptions::new()
@@ -44,9 +46,14 @@ impl ChunkJoiner {
         file.seek(tokio::io::SeekFrom::Start(chunk_details.start_offset))
             .await
             .map_err(|e| format!("Error while seeking to start offset of chunk in file {e:?}"))?;
-        copy(reader, &mut file)
-            .await
-            .map_err(|e| format!("Error while copying chunk from reader to file {e:?}"))?;
+        // corrupted replica fails here instead of ending up in the file
+        if let Err(e) = copy(&mut VerifyingReader::new(reader, checksums), &mut file).await {
+            // checksum mismatch is kept as io error so caller can tell a bad replica apart
+            if is_checksum_mismatch(&e) {
+                return Err(e.into());
+            }
+            return Err(format!("Error while copying chunk from reader to file {e:?}").into());
+        }
         Ok(())
     }
     #[instrument(name = "abort_join_chunk", skip(self))]
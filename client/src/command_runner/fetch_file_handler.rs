@@ -1,4 +1,5 @@
 use utilities::{
+    checksum::is_checksum_mismatch,
     logger::{Instrument, error, info, instrument, trace, tracing},
     result::Result,
     retry_policy::retry_with_backoff,
@@ -48,33 +49,69 @@ impl FetchFileHandler {
             let chunk_joiner = chunk_joiner.clone();
             let datanode = self.datanode.clone();
             let chunk_detail = chunk_detail.clone();
-            let server_ticket = self
-                .ticket_decrypter
-                .decrypt_client_ticket(&chunk_detail.ticket)?
-                .encrypted_server_ticket;
+            // namenode sends replicas closest first, older namenodes only send a single ticket
+            let tickets = if chunk_detail.location_tickets.is_empty() {
+                vec![chunk_detail.ticket.clone()]
+            } else {
+                chunk_detail.location_tickets.clone()
+            };
+            let mut replicas = vec![];
+            for (datanode_meta, ticket) in chunk_detail.location.iter().zip(tickets) {
+                let server_ticket = self
+                    .ticket_decrypter
+                    .decrypt_client_ticket(&ticket)?
+                    .encrypted_server_ticket;
+                replicas.push((datanode_meta.clone(), server_ticket));
+            }
+            let namenode = self.namenode.clone();
             handles.push(tokio::spawn(
                 async move {
                     retry_with_backoff(
                         || async {
-                            let fetch_chunk_result = datanode
-                                .fetch_chunk(
-                                    chunk_detail.id.clone(),
-                                    chunk_detail.location[0].addrs.clone(),
-                                    server_ticket.clone(),
-                                )
-                                .await;
-                            match fetch_chunk_result {
-                                Ok(mut read_stream) => {
-                                    let _ = chunk_joiner
-                                        .join_chunk(&chunk_detail, &mut read_stream)
+                            let mut last_error = None;
+                            for (datanode_meta, server_ticket) in &replicas {
+                                let fetch_chunk_result = match datanode
+                                    .fetch_chunk(
+                                        chunk_detail.id.clone(),
+                                        datanode_meta.clone(),
+                                        server_ticket.clone(),
+                                    )
+                                    .await
+                                {
+                                    Ok((mut read_stream, checksums)) => {
+                                        chunk_joiner
+                                            .join_chunk(&chunk_detail, &mut read_stream, checksums)
+                                            .await
+                                    }
+                                    Err(e) => Err(e),
+                                };
+                                let e = match fetch_chunk_result {
+                                    Ok(()) => return Ok(()),
+                                    Err(e) => e,
+                                };
+                                error!(error = %e, datanode_id = %datanode_meta.id, "Error during chunk fetching, trying next replica");
+                                // only a block not matching its checksum says the replica is
+                                // bad, a transfer cut short doesn't
+                                let corrupt = e
+                                    .downcast_ref::<std::io::Error>()
+                                    .is_some_and(is_checksum_mismatch);
+                                if corrupt {
+                                    let report_result = namenode
+                                        .clone()
+                                        .report_bad_chunk(
+                                            chunk_detail.id.clone(),
+                                            datanode_meta.id.clone(),
+                                            e.to_string(),
+                                        )
                                         .await;
+                                    if let Err(report_error) = report_result {
+                                        error!(error = %report_error, "Error while reporting bad replica");
+                                    }
                                 }
-                                Err(e) => {
-                                    error!(error = %e,"Error during chunk fetching");
-                                    return Err(e);
-                                }
+                                last_error = Some(e);
                             }
-                            Ok(())
+                            Err(last_error
+                                .unwrap_or_else(|| "No replica available for chunk".into()))
                         },
                         3,
                     )
@@ -85,8 +122,12 @@ impl FetchFileHandler {
         }
 
         for handle in handles {
-            match handle.await {
-                Ok(_) => {}
+            let result = match handle.await {
+                Ok(result) => result,
+                Err(e) => Err(format!("{e:?}").into()),
+            };
+            match result {
+                Ok(()) => {}
                 Err(e) => {
                     error!("Error during fetching chunk {e:?}");
                     info!("Freeing the reserverd space");
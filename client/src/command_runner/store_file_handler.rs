@@ -1,12 +1,14 @@
+use proto::generated::client_namenode::ChunkMeta;
 use utilities::{
-    logger::{Instrument, error, info, instrument, trace, tracing},
+    logger::{Instrument, error, info, instrument, trace, tracing, warn},
     result::Result,
     retry_policy::retry_with_backoff,
     ticket::ticket_decrypter::TicketDecrypter,
 };
 
 use crate::{
-    datanode_service::DatanodeService, file_chunker::FileChunker,
+    datanode_service::DatanodeService,
+    file_chunker::{FileChunk, FileChunker},
     namenode::service::NamenodeService,
 };
 use std::sync::Arc;
@@ -56,57 +58,111 @@ impl StoreFileHandler {
         // send each data node to setup pilepline
         let mut handles = vec![];
         for chunk_detail in &chunk_details {
+            let namenode = self.namenode.clone();
             let datanode = self.datanode.clone();
+            let ticket_decrypter = self.ticket_decrypter.clone();
             let file_chunk = file_chunker.next_chunk().unwrap();
             let chunk_detail = chunk_detail.clone();
-            let server_ticket = self
-                .ticket_decrypter
-                .decrypt_client_ticket(&chunk_detail.ticket)?
-                .encrypted_server_ticket;
-
             handles.push(tokio::spawn(
-                async move {
-                    retry_with_backoff(
-                        || async {
-                            trace!(id = %chunk_detail.id,"working on chunk");
-                            let read_stream = file_chunk.get_read_stream().await?;
-                            let res = datanode
-                                .store_chunk(
-                                    chunk_detail.id.clone(),
-                                    chunk_detail.end_offset - chunk_detail.start_offset,
-                                    chunk_detail.location.clone(),
-                                    server_ticket.clone(),
-                                    read_stream,
-                                )
-                                .await;
-                            match res {
-                                Ok(_) => Ok(()),
-                                Err(e) => {
-                                    error!("{}", e);
-                                    Err(e)
-                                }
-                            }
-                        },
-                        3,
-                    )
-                    .await
-                }
+                Self::store_chunk(
+                    namenode,
+                    datanode,
+                    ticket_decrypter,
+                    file_chunk,
+                    chunk_detail,
+                )
                 .in_current_span(),
             ));
         }
 
         for handle in handles {
-            match handle.await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!(error=%e,"aborting the store file operation");
-                    // we will implement something to tell namenode that this file store has been
-                    // aborted
-                    return Err(e.into());
-                }
+            let result = match handle.await {
+                Ok(result) => result,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = result {
+                error!(error=%e,"aborting the store file operation");
+                // we will implement something to tell namenode that this file store has been
+                // aborted
+                return Err(e);
             }
         }
         // if all things go well we will tell namenode to commit
         Ok("File stored successfully".to_owned())
     }
+    // datanodes which fail while writing are dropped from the pipeline and the write resumes on
+    // the rest, namenode brings the chunk back to full replication once it is committed
+    #[instrument(skip(namenode, datanode, ticket_decrypter, file_chunk, chunk_detail), fields(chunk_id = %chunk_detail.id))]
+    async fn store_chunk(
+        mut namenode: NamenodeService,
+        datanode: DatanodeService,
+        ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+        file_chunk: FileChunk,
+        chunk_detail: ChunkMeta,
+    ) -> Result<()> {
+        let chunk_size = chunk_detail.end_offset - chunk_detail.start_offset;
+        let mut location = chunk_detail.location.clone();
+        let mut generation = chunk_detail.generation;
+        let mut server_ticket = ticket_decrypter
+            .decrypt_client_ticket(&chunk_detail.ticket)?
+            .encrypted_server_ticket;
+        loop {
+            trace!(?location, "working on chunk");
+            let result = retry_with_backoff(
+                || async {
+                    let read_stream = file_chunk.get_read_stream().await?;
+                    datanode
+                        .store_chunk(
+                            chunk_detail.id.clone(),
+                            chunk_size,
+                            location.clone(),
+                            server_ticket.clone(),
+                            read_stream,
+                        )
+                        .await
+                },
+                3,
+            )
+            .await;
+            let (surviving, failed): (Vec<_>, Vec<_>) = match result {
+                Ok(stored) => location
+                    .iter()
+                    .cloned()
+                    .partition(|datanode_meta| stored.contains(&datanode_meta.id)),
+                // nothing came back from the first datanode so it is taken as the failed one
+                Err(e) => {
+                    error!("{}", e);
+                    let (first, rest) = location.split_at(1);
+                    (rest.to_vec(), first.to_vec())
+                }
+            };
+            if failed.is_empty() {
+                break;
+            }
+            if surviving.is_empty() {
+                return Err(format!("No datanode left to store chunk {}", chunk_detail.id).into());
+            }
+            warn!(?failed, "Recovering pipeline without failed datanodes");
+            let recovery = namenode
+                .recover_pipeline(
+                    chunk_detail.id.clone(),
+                    surviving.iter().map(|meta| meta.id.clone()).collect(),
+                    failed.iter().map(|meta| meta.id.clone()).collect(),
+                )
+                .await?;
+            location = recovery.location;
+            generation = recovery.generation;
+            server_ticket = ticket_decrypter
+                .decrypt_client_ticket(&recovery.ticket)?
+                .encrypted_server_ticket;
+        }
+        datanode
+            .commit_chunk(
+                chunk_detail.id.clone(),
+                generation,
+                location[0].clone(),
+                server_ticket,
+            )
+            .await
+    }
 }
@@ -5,6 +5,10 @@ use figment::{
 use once_cell::sync::Lazy;
 use serde::Deserialize;
 
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub client_id: String,
@@ -14,6 +18,15 @@ pub struct Config {
     pub apm_endpoint: String,
     pub namenode_cert: String,
     pub secret_key: String,
+    // private key of namenode_cert and certificate of the namenode CA, both from /cert/issue.
+    // client presents namenode_cert on grpc and checks the servers against the CA
+    #[serde(default)]
+    pub cert_key: String,
+    #[serde(default)]
+    pub root_cert: String,
+    // has to match the datanodes, plaintext is only meant for benchmarks
+    #[serde(default = "default_true")]
+    pub data_plane_tls: bool,
 }
 
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
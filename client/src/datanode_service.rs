@@ -4,14 +4,17 @@ use proto::generated::{
         client_data_node_client::ClientDataNodeClient,
     },
     client_namenode::DataNodeMeta,
+    data_transfer::{TransferMode, TransferRequest},
 };
 use std::str::FromStr;
-use tokio::io::AsyncRead;
+use tokio::io::{AsyncRead, AsyncReadExt, sink};
 use tonic::{metadata::MetadataValue, transport::Channel};
 use utilities::{
-    data_packet::DataPacket,
+    checksum::ChecksumReader,
+    data_transfer,
     grpc_channel_pool::GRPC_CHANNEL_POOL,
-    logger::{error, instrument, trace, tracing},
+    logger::{instrument, trace, tracing},
+    packet::PacketSender,
     result::Result,
     tcp_pool::TCP_CONNECTION_POOL,
 };
@@ -22,10 +25,19 @@ impl DatanodeService {
     pub fn new() -> Self {
         Self {}
     }
-    async fn get_grpc_connection(&self, addrs: &str) -> Result<ClientDataNodeClient<Channel>> {
-        let channel = GRPC_CHANNEL_POOL.get_channel(addrs).await.unwrap();
+    async fn get_grpc_connection(
+        &self,
+        addrs: &str,
+        datanode_id: &str,
+    ) -> Result<ClientDataNodeClient<Channel>> {
+        let channel = GRPC_CHANNEL_POOL
+            .get_channel(addrs, datanode_id)
+            .await
+            .unwrap();
         Ok(ClientDataNodeClient::new(channel))
     }
+    // writes the chunk through the pipeline of replica set resuming from what the pipeline has
+    // staged already, returns ids of replicas which stored it
     #[instrument(name = "datanode_service_store_chunk", skip(self, read_stream))]
     pub async fn store_chunk(
         &self,
@@ -34,7 +46,7 @@ impl DatanodeService {
         replica_set: Vec<DataNodeMeta>,
         ticket: String,
         mut read_stream: (impl AsyncRead + Unpin),
-    ) -> Result<()> {
+    ) -> Result<Vec<String>> {
         if replica_set.is_empty() {
             return Err("Empty replica set".into());
         }
@@ -45,43 +57,76 @@ impl DatanodeService {
         store_chunk_request
             .metadata_mut()
             .insert("ticket", MetadataValue::from_str(&ticket)?);
-        let mut data_node_grpc_client = self.get_grpc_connection(&replica_set[0].addrs).await?;
+        let mut data_node_grpc_client = self
+            .get_grpc_connection(&replica_set[0].addrs, &replica_set[0].id)
+            .await?;
         // getting tcp address for the first replica set to which we will stream the read stream
         trace!("Sending store chunk request");
         let store_chunk_response = data_node_grpc_client
             .store_chunk(store_chunk_request)
-            .await?;
-        let tcp_addrs = &store_chunk_response.get_ref().address;
-        trace!(%tcp_addrs,"Got tcp address");
-        // we will first create the headers for tcp stream
-        let mut tcp_headers = DataPacket::new();
-        tcp_headers.insert("mode".to_string(), "Write".to_string());
-        tcp_headers.insert("chunk_id".to_string(), chunk_id.clone());
-        tcp_headers.insert("chunk_size".to_string(), chunk_size.to_string());
-        tcp_headers.insert("ticket".to_string(), ticket.clone());
-        let mut tcp_header_stream = tcp_headers.encode();
+            .await?
+            .into_inner();
+        let tcp_addrs = &store_chunk_response.address;
+        let offset = std::cmp::min(store_chunk_response.staged_bytes, chunk_size);
+        trace!(%tcp_addrs, offset, pipeline = ?store_chunk_response.pipeline, "Got tcp address");
+        let transfer_request = TransferRequest {
+            mode: TransferMode::Write as i32,
+            chunk_id: chunk_id.clone(),
+            ticket: ticket.clone(),
+            chunk_size,
+            offset,
+        };
         // connect to tcp stream
-        let mut tcp_stream = TCP_CONNECTION_POOL.get_connection(tcp_addrs).await?;
-        trace!("Writing tcp headers to stream");
-        tokio::io::copy(&mut tcp_header_stream, &mut tcp_stream).await?;
-        trace!("tcp headers written to stream");
-        let bytes_written = tokio::io::copy(&mut read_stream, &mut tcp_stream).await?;
+        let mut tcp_stream = TCP_CONNECTION_POOL
+            .get_connection(tcp_addrs, &replica_set[0].id)
+            .await?;
+        trace!("Sending transfer request");
+        let transfer_response =
+            data_transfer::open_transfer(&mut tcp_stream, &transfer_request).await?;
+        trace!(version = transfer_response.version, "Transfer accepted");
+        // checksums are computed while streaming and sent right after the last packet, they
+        // cover the whole chunk so the part which is already staged is read as well
+        let mut checksum_stream = ChecksumReader::new(&mut read_stream);
+        tokio::io::copy(&mut (&mut checksum_stream).take(offset), &mut sink()).await?;
+        let mut packet_sender = PacketSender::new(tcp_stream, offset);
+        let bytes_written = packet_sender.send(&mut checksum_stream).await?;
         trace!("{bytes_written} Bytes written");
-        // we will check for the number of writen bytes to stream
-        let reply_packet = DataPacket::decode(&mut tcp_stream).await?;
-        trace!("reply packet from the {:?}", reply_packet);
-        let bytes_recieved_by_datanode: u64 = reply_packet.get("bytes_received")?.parse()?;
-        if bytes_written != bytes_recieved_by_datanode {
-            error!(
-                "Bytes written to stream and recieved by stream are diffrent BytesWritten: {bytes_written}, BytesRecieved: {bytes_recieved_by_datanode}"
-            );
-            return Err("Bytes recieved are diffrent from bytes written".into());
-        }
+        // ack of the last packet has status of every datanode of the pipeline in order
+        let ack = packet_sender
+            .finish(
+                transfer_response.version as u16,
+                checksum_stream.into_checksums(),
+            )
+            .await?;
+        trace!(?ack, "Got ack of last packet");
+        let stored = store_chunk_response
+            .pipeline
+            .into_iter()
+            .zip(ack.statuses)
+            .filter(|(_, stored)| *stored)
+            .map(|(datanode_id, _)| datanode_id)
+            .collect();
+        Ok(stored)
+    }
+    #[instrument(name = "datanode_service_commit_chunk", skip(self, ticket))]
+    pub async fn commit_chunk(
+        &self,
+        chunk_id: String,
+        generation: u64,
+        datanode: DataNodeMeta,
+        ticket: String,
+    ) -> Result<()> {
         trace!("Sending commit message");
-        let mut commit_chunk_request = tonic::Request::new(CommitChunkRequest { chunk_id });
+        let mut commit_chunk_request = tonic::Request::new(CommitChunkRequest {
+            chunk_id,
+            generation,
+        });
         commit_chunk_request
             .metadata_mut()
             .insert("ticket", MetadataValue::from_str(&ticket)?);
+        let mut data_node_grpc_client = self
+            .get_grpc_connection(&datanode.addrs, &datanode.id)
+            .await?;
         data_node_grpc_client
             .commit_chunk(commit_chunk_request)
             .await?;
@@ -92,16 +137,18 @@ impl DatanodeService {
     pub async fn fetch_chunk(
         &self,
         chunk_id: String,
-        datanode_addrs: String,
+        datanode: DataNodeMeta,
         ticket: String,
-    ) -> Result<impl AsyncRead + Unpin + Send + Sync> {
+    ) -> Result<(impl AsyncRead + Unpin + Send + Sync, Option<Vec<u32>>)> {
         let mut fetch_chunk_request = tonic::Request::new(FetchChunkRequest {
             chunk_id: chunk_id.clone(),
         });
         fetch_chunk_request
             .metadata_mut()
             .insert("ticket", MetadataValue::from_str(&ticket)?);
-        let mut data_node_grpc_client = self.get_grpc_connection(&datanode_addrs).await?;
+        let mut data_node_grpc_client = self
+            .get_grpc_connection(&datanode.addrs, &datanode.id)
+            .await?;
         // getting tcp address for the first replica set to which we will stream the read stream
         trace!("Sending fetch chunk request");
         let fetch_chunk_response = data_node_grpc_client
@@ -109,17 +156,23 @@ impl DatanodeService {
             .await?
             .into_inner();
         trace!(tcp_addrs = %fetch_chunk_response.address,"Got tcp stream addres for datanode");
-        let mut tcp_headers = DataPacket::new();
-        tcp_headers.insert("chunk_id".to_string(), chunk_id.clone());
-        tcp_headers.insert("ticket".to_string(), ticket.clone());
-        tcp_headers.insert("mode".to_string(), "Read".to_string());
-        let mut tcp_header_stream = tcp_headers.encode();
+        let transfer_request = TransferRequest {
+            mode: TransferMode::Read as i32,
+            chunk_id: chunk_id.clone(),
+            ticket: ticket.clone(),
+            ..Default::default()
+        };
         let mut tcp_stream = TCP_CONNECTION_POOL
-            .get_connection(&fetch_chunk_response.address)
+            .get_connection(&fetch_chunk_response.address, &datanode.id)
             .await?;
-        trace!("writing headers");
-        tokio::io::copy(&mut tcp_header_stream, &mut tcp_stream).await?;
+        trace!("Sending transfer request");
+        // datanode sends block checksums before the content, none for chunks stored without them
+        let transfer_response =
+            data_transfer::open_transfer(&mut tcp_stream, &transfer_request).await?;
+        let checksums = transfer_response
+            .checksums
+            .map(|checksums| checksums.checksums);
         // returning tcp stream as reader since data node will push file content to tcp stream now
-        Ok(tcp_stream)
+        Ok((tcp_stream, checksums))
     }
 }
@@ -1,15 +1,14 @@
 use std::{error::Error, io, sync::Arc};
 
-use crate::{
-    config::CONFIG,
-    namenode::{auth_intercepter::NamenodeAuthIntercepter, service::NamenodeService},
-};
+use crate::{config::CONFIG, namenode::service::NamenodeService};
 use command_runner::CommandRunner;
 use proto::generated::client_namenode::client_name_node_client::ClientNameNodeClient;
 use utilities::{
     grpc_channel_pool::GRPC_CHANNEL_POOL,
-    logger::{self, error, info},
+    logger::{self, error, info, warn},
+    tcp_pool::TCP_CONNECTION_POOL,
     ticket::ticket_decrypter::{self, DefaultTicketDecrypter, TicketDecrypter},
+    tls::NAMENODE_SERVER_NAME,
 };
 mod chunk_joiner;
 mod command_runner;
@@ -27,18 +26,29 @@ async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         &CONFIG.log_base,
     );
     info!("Starting the Client");
+    // namenode and datanodes know who we are from our certificate
+    GRPC_CHANNEL_POOL
+        .enable_tls(&CONFIG.namenode_cert, &CONFIG.cert_key, &CONFIG.root_cert)
+        .map_err(|e| format!("Error while reading certificates for grpc TLS {e}"))?;
     info!(namenode_addrs = %CONFIG.namenode_addrs,"Connecting to Namenode");
-    let namenode_channel = match GRPC_CHANNEL_POOL.get_channel(&CONFIG.namenode_addrs).await {
+    let namenode_channel = match GRPC_CHANNEL_POOL
+        .get_channel(&CONFIG.namenode_addrs, NAMENODE_SERVER_NAME)
+        .await
+    {
         Ok(v) => v,
         Err(e) => {
             error!(error = %e,"Error while creating namnode channel so shutting down");
             return Err(e);
         }
     };
-    let namenode = NamenodeService::new(ClientNameNodeClient::with_interceptor(
-        namenode_channel,
-        NamenodeAuthIntercepter,
-    ));
+    let namenode = NamenodeService::new(ClientNameNodeClient::new(namenode_channel));
+    if CONFIG.data_plane_tls {
+        TCP_CONNECTION_POOL
+            .enable_tls(&CONFIG.root_cert)
+            .map_err(|e| format!("Error while reading root_cert for data plane TLS {e}"))?;
+    } else {
+        warn!("Data plane TLS is turned off, chunks are sent in plaintext");
+    }
     // ticket decrypter
     let ticket_decrypter: Arc<Box<dyn TicketDecrypter>> =
         Arc::new(Box::new(DefaultTicketDecrypter::new(&CONFIG.secret_key)?));
@@ -1,2 +1 @@
-pub mod auth_intercepter;
 pub mod service;
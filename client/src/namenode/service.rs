@@ -1,9 +1,9 @@
-use crate::namenode::auth_intercepter::NamenodeAuthIntercepter;
 use proto::generated::client_namenode::{
-    ChunkMeta, DeleteFileRequest, FetchFileRequest, FetchFileResponse, StoreFileRequest,
+    ChunkMeta, DeleteFileRequest, FetchFileRequest, FetchFileResponse, RecoverPipelineRequest,
+    RecoverPipelineResponse, ReportBadChunkRequest, StoreFileRequest,
     client_name_node_client::ClientNameNodeClient,
 };
-use tonic::{service::interceptor::InterceptedService, transport::Channel};
+use tonic::transport::Channel;
 use utilities::{
     logger::{debug, instrument, tracing},
     result::Result,
@@ -11,13 +11,11 @@ use utilities::{
 
 #[derive(Clone, Debug)]
 pub struct NamenodeService {
-    connection: ClientNameNodeClient<InterceptedService<Channel, NamenodeAuthIntercepter>>,
+    connection: ClientNameNodeClient<Channel>,
 }
 
 impl NamenodeService {
-    pub fn new(
-        connection: ClientNameNodeClient<InterceptedService<Channel, NamenodeAuthIntercepter>>,
-    ) -> Self {
+    pub fn new(connection: ClientNameNodeClient<Channel>) -> Self {
         Self { connection }
     }
     #[instrument(name = "namenode_store_file", skip(self))]
@@ -72,4 +70,50 @@ impl NamenodeService {
             .into_inner();
         Ok(delete_file_response.file_present)
     }
+    // returns true when namenode marked the replica corrupt
+    #[instrument(name = "namenode_report_bad_chunk", skip(self))]
+    pub async fn report_bad_chunk(
+        &mut self,
+        chunk_id: String,
+        datanode_id: String,
+        reason: String,
+    ) -> Result<bool> {
+        let report_bad_chunk_request = ReportBadChunkRequest {
+            chunk_id: chunk_id.clone(),
+            datanode_id,
+            reason,
+        };
+        let tonic_request = tonic::Request::new(report_bad_chunk_request);
+        let report_bad_chunk_response = self
+            .connection
+            .report_bad_chunk(tonic_request)
+            .await
+            .map_err(|e| format!("error while reporting bad chunk {chunk_id} to namenode {e:?}"))?
+            .into_inner();
+        Ok(report_bad_chunk_response.marked_corrupt)
+    }
+    // new generation, location and ticket for writing the chunk to surviving datanodes
+    #[instrument(name = "namenode_recover_pipeline", skip(self))]
+    pub async fn recover_pipeline(
+        &mut self,
+        chunk_id: String,
+        surviving: Vec<String>,
+        failed: Vec<String>,
+    ) -> Result<RecoverPipelineResponse> {
+        let recover_pipeline_request = RecoverPipelineRequest {
+            chunk_id: chunk_id.clone(),
+            surviving,
+            failed,
+        };
+        let tonic_request = tonic::Request::new(recover_pipeline_request);
+        let recover_pipeline_response = self
+            .connection
+            .recover_pipeline(tonic_request)
+            .await
+            .map_err(|e| {
+                format!("error while recovering pipeline of chunk {chunk_id} with namenode {e:?}")
+            })?
+            .into_inner();
+        Ok(recover_pipeline_response)
+    }
 }
@@ -0,0 +1,138 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use proto::generated::datanode_namenode::ScanProgress;
+use storage::{storage::Storage, volume_set::VolumeSet};
+use tokio::{
+    io::AsyncReadExt,
+    sync::Mutex,
+    time::{Instant, interval_at, sleep},
+};
+use utilities::{
+    checksum::{CHECKSUM_BLOCK_SIZE, VerifyingReader},
+    logger::{Instrument, Level, error, info, span, trace},
+    result::Result,
+};
+
+use crate::datanode_state::DatanodeState;
+
+// first pass waits for state mantainer to pick up chunks on disk
+const FIRST_PASS_DELAY: Duration = Duration::from_secs(60);
+
+enum ScanResult {
+    Verified,
+    Corrupt(String),
+    // chunk was stored before checksums existed
+    NoChecksums,
+}
+
+/// Walks committed chunks and verifies them against stored checksums so bit rot on replicas
+/// nobody reads is found, corrupt replicas go to namenode with the next chunk report
+pub struct BlockScanner {
+    store: VolumeSet,
+    state: Arc<Mutex<DatanodeState>>,
+}
+impl BlockScanner {
+    pub fn new(store: VolumeSet, state: Arc<Mutex<DatanodeState>>) -> Self {
+        Self { store, state }
+    }
+    pub fn start_scan_loop(self, scan_interval: Duration, bytes_per_sec: u64) {
+        // zero interval or rate would panic, smallest ones are used instead
+        let scan_interval = scan_interval.max(Duration::from_secs(1));
+        let bytes_per_sec = bytes_per_sec.max(1);
+        tokio::spawn(async move {
+            let mut ticker = interval_at(Instant::now() + FIRST_PASS_DELAY, scan_interval);
+            loop {
+                ticker.tick().await;
+                let span = span!(Level::INFO, "datanode_block_scan");
+                self.scan_pass(bytes_per_sec).instrument(span).await;
+            }
+        });
+    }
+    async fn scan_pass(&self, bytes_per_sec: u64) {
+        let chunks: Vec<String> = {
+            let mut state = self.state.lock().await;
+            let chunks: Vec<String> = state.available_chunks.keys().cloned().collect();
+            state.scan_progress = ScanProgress {
+                chunks_scanned: 0,
+                chunks_total: chunks.len() as u64,
+                corrupt_found: 0,
+                pass_started_at: unix_now(),
+                last_pass_finished_at: state.scan_progress.last_pass_finished_at,
+            };
+            chunks
+        };
+        info!(chunks = chunks.len(), "Starting block scan pass");
+        for chunk_id in chunks {
+            // chunk may have been deleted since the pass started
+            let available = self
+                .state
+                .lock()
+                .await
+                .available_chunks
+                .contains_key(&chunk_id);
+            let result = if available {
+                Some(self.verify_chunk(&chunk_id, bytes_per_sec).await)
+            } else {
+                None
+            };
+            let mut state = self.state.lock().await;
+            state.scan_progress.chunks_scanned += 1;
+            match result {
+                Some(Ok(ScanResult::Verified)) => {
+                    trace!(%chunk_id, "Chunk verified");
+                    state.chunk_verified(&chunk_id, unix_now());
+                }
+                Some(Ok(ScanResult::Corrupt(e))) => {
+                    error!(%chunk_id, "Block scanner found corrupt chunk {e}");
+                    state.scan_progress.corrupt_found += 1;
+                    state.chunk_corrupt(&chunk_id);
+                }
+                Some(Ok(ScanResult::NoChecksums)) => {
+                    trace!(%chunk_id, "Chunk has no checksums, skipping");
+                }
+                Some(Err(e)) => {
+                    error!(%chunk_id, "Error while scanning chunk {e}");
+                    state.disk_error();
+                }
+                None => {}
+            }
+        }
+        let mut state = self.state.lock().await;
+        state.scan_progress.last_pass_finished_at = unix_now();
+        info!(
+            corrupt_found = state.scan_progress.corrupt_found,
+            "Block scan pass finished"
+        );
+    }
+    // reads the chunk block by block sleeping between blocks so scanning never hogs the disk
+    async fn verify_chunk(&self, chunk_id: &str, bytes_per_sec: u64) -> Result<ScanResult> {
+        let Some(checksums) = self.store.get_checksums(chunk_id).await? else {
+            return Ok(ScanResult::NoChecksums);
+        };
+        let reader = self.store.read(chunk_id.to_owned()).await?;
+        let mut reader = VerifyingReader::new(reader, Some(checksums));
+        let mut buf = vec![0u8; CHECKSUM_BLOCK_SIZE];
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => return Ok(ScanResult::Verified),
+                Ok(n) => {
+                    sleep(Duration::from_secs_f64(n as f64 / bytes_per_sec as f64)).await;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    return Ok(ScanResult::Corrupt(e.to_string()));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
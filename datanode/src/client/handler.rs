@@ -4,11 +4,10 @@ use proto::generated::client_datanode::{
     FetchChunkResponse, StoreChunkRequest, StoreChunkResponse,
 };
 use std::sync::Arc;
-use storage::file_storage::FileStorage;
 use storage::storage::Storage;
+use storage::volume_set::VolumeSet;
 use tokio::sync::Mutex;
-use utilities::logger::{error, instrument, trace, tracing};
-use utilities::tcp_pool::TCP_CONNECTION_POOL;
+use utilities::logger::{error, instrument, trace, tracing, warn};
 
 use crate::config::CONFIG;
 use crate::datanode_state::DatanodeState;
@@ -21,19 +20,19 @@ pub struct ClientHandler {
     state: Arc<Mutex<DatanodeState>>,
     peer_service: PeerService,
     namenode_service: NamenodeService,
-    store: FileStorage,
+    store: VolumeSet,
     ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
 }
 impl ClientHandler {
     pub fn new(
         state: Arc<Mutex<DatanodeState>>,
-        store: FileStorage,
+        store: VolumeSet,
         ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
     ) -> Self {
         Self {
             state: state.clone(),
             peer_service: PeerService {},
-            namenode_service: NamenodeService::new(state),
+            namenode_service: NamenodeService::new(state, ticket_decrypter.clone()),
             store,
             ticket_decrypter,
         }
@@ -73,76 +72,41 @@ impl ClientDataNode for ClientHandler {
             ));
         }
 
+        let chunk_id = &store_request.chunk_id;
+        // pipeline of an earlier attempt may lead to a replica which failed
+        self.state.lock().await.clear_pipeline(chunk_id);
+        let mut staged_bytes = self.store.get_staged_size(chunk_id).await.map_err(|e| {
+            tonic::Status::internal(format!("Error while reading staged chunk : {e}"))
+        })?;
+        let mut pipeline = vec![CONFIG.datanode_id.clone()];
         // first we will send the create pipeling request to the next replica
         if store_request.replica_set.len() > 1 {
-            // to send a pipeline request to the peer we need ticket
-            let ticket = self
-                .namenode_service
-                .get_store_chunk_ticket(&store_request.replica_set[1].id, &store_request.chunk_id)
-                .await
-                .map_err(|e| {
-                    tonic::Status::new(
-                        tonic::Code::PermissionDenied,
-                        format!("Error while getting peer ticket : {e}"),
-                    )
-                })?;
-            let client_ticket = self
-                .ticket_decrypter
-                .decrypt_client_ticket(&ticket)
-                .map_err(|e| {
-                    tonic::Status::new(
-                        tonic::Code::PermissionDenied,
-                        format!("Error while getting peer ticket : {e}"),
-                    )
-                })?;
-
             trace!("replica set is >1 so we are creating piplines");
-            let tcp_address = match self
+            match self
                 .peer_service
-                .create_pipeline(
-                    &store_request.chunk_id,
+                .extend_pipeline(
+                    &self.state,
+                    &self.namenode_service,
+                    &self.ticket_decrypter,
+                    chunk_id,
                     &store_request.replica_set[1..],
-                    &client_ticket.encrypted_server_ticket,
                 )
                 .await
             {
-                Ok(addrs) => addrs,
-                Err(e) => {
-                    return Err(tonic::Status::internal(format!(
-                        "error while crating pipeline : {}",
-                        e
-                    )));
+                Ok(response) => {
+                    staged_bytes = std::cmp::min(staged_bytes, response.staged_bytes);
+                    pipeline.extend(response.pipeline);
                 }
-            };
-            trace!(tcp_addrs = %tcp_address,"Got the pipeline address");
-            let tcp_connection = match TCP_CONNECTION_POOL.get_connection(&tcp_address).await {
-                Ok(connection) => connection,
+                // writer sees the missing replicas in the response and recovers without them
                 Err(e) => {
-                    return Err(tonic::Status::internal(format!(
-                        "error while crating pipeline : {}",
-                        e
-                    )));
+                    warn!(error = %e, "Pipeline could not be set up to next replica");
                 }
-            };
-            let mut state = self.state.lock().await;
-            state
-                .chunk_to_pipline
-                .insert(store_request.chunk_id.clone(), tcp_connection);
-            state.chunk_to_next_replica.insert(
-                store_request.chunk_id.clone(),
-                store_request.replica_set[1].addrs.clone(),
-            );
-            state.chunk_to_namenode_store_ticket.insert(
-                store_request.chunk_id.clone(),
-                client_ticket.encrypted_server_ticket,
-            );
-            trace!(
-                "successfully established the tcp connection and stored in chunk to pipeline {:?}",
-                state.chunk_to_pipline
-            );
+            }
         }
         let response = StoreChunkResponse {
             address: CONFIG.external_tcp_addrs.clone(),
+            staged_bytes,
+            pipeline,
         };
         Ok(tonic::Response::new(response))
     }
@@ -169,7 +133,7 @@ impl ClientDataNode for ClientHandler {
         }
 
         let mut state = self.state.lock().await;
-        if let Some(next_replica_node_grpc) = state
+        if let Some(next_replica) = state
             .chunk_to_next_replica
             .remove(&commit_chunk_request.chunk_id)
         {
@@ -181,30 +145,31 @@ impl ClientDataNode for ClientHandler {
             trace!("Have next chunk so sending commit to it again");
             drop(state); // droping state to remove lock
             //send commit message to next_replica_node_grpc
-            let _ = match self
+            // replica which can't be reached is left out, namenode repairs the chunk from us
+            if let Err(e) = self
                 .peer_service
                 .commit_chunk(
                     &commit_chunk_request.chunk_id,
-                    &next_replica_node_grpc,
+                    commit_chunk_request.generation,
+                    &next_replica.addrs,
+                    &next_replica.id,
                     &ticket,
                 )
                 .await
             {
-                Ok(v) => v,
-                Err(e) => {
-                    error!(error = %e,next_addrs=%next_replica_node_grpc,"Error while sending commit messag to next replica");
-                    return Err(tonic::Status::new(
-                        tonic::Code::Aborted,
-                        "Error while commiting message on next replica",
-                    ));
-                }
-            };
+                error!(error = %e,next_addrs=%next_replica.addrs,"Error while sending commit messag to next replica");
+            }
         }
         trace!("after if condition");
         //else {
         //    state.available_chunks
         //}
-        let committed = match self.store.commit(commit_chunk_request.chunk_id).await {
+        let chunk_id = commit_chunk_request.chunk_id;
+        let committed = match self
+            .store
+            .commit(chunk_id.clone(), commit_chunk_request.generation)
+            .await
+        {
             Ok(v) => v,
             Err(e) => {
                 error!(error=%e,"Error while commiting chunk");
@@ -215,6 +180,11 @@ impl ClientDataNode for ClientHandler {
             }
         };
         trace!("commited successfully");
+        if committed {
+            self.namenode_service
+                .chunk_committed(&chunk_id, commit_chunk_request.generation)
+                .await;
+        }
         let commit_chunk_response = CommitChunkResponse { committed };
         Ok(tonic::Response::new(commit_chunk_response))
     }
@@ -244,7 +214,7 @@ impl ClientDataNode for ClientHandler {
         trace!(?state, "Got current state of datanode");
         if !state
             .available_chunks
-            .contains(&fetch_chunk_request.chunk_id)
+            .contains_key(&fetch_chunk_request.chunk_id)
         {
             trace!(available_chunks = ?state.available_chunks,"chunk not available");
             return Err(tonic::Status::new(
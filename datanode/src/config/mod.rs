@@ -4,11 +4,30 @@ use figment::{
 };
 use once_cell::sync::Lazy;
 use serde::Deserialize;
-use storage::file_storage::FileStorageConfig;
+use storage::{file_storage::FileStorageConfig, volume_set::VolumeChoosingPolicy};
 
 fn default_false() -> bool {
     false
 }
+fn default_true() -> bool {
+    true
+}
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeChoosingPolicyKind {
+    #[default]
+    RoundRobin,
+    MostAvailable,
+}
+impl From<VolumeChoosingPolicyKind> for VolumeChoosingPolicy {
+    fn from(value: VolumeChoosingPolicyKind) -> Self {
+        match value {
+            VolumeChoosingPolicyKind::RoundRobin => VolumeChoosingPolicy::RoundRobin,
+            VolumeChoosingPolicyKind::MostAvailable => VolumeChoosingPolicy::MostAvailable,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct StorageConfig {
     // path to the dir where data will be stored
@@ -20,13 +39,64 @@ pub struct StorageConfig {
     #[serde(default)]
     // thi will be used in case we want to create a mount
     pub mount_size_in_mega_byte: u64,
+    // one dir per disk, storage_path is the only volume when empty
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    // picks the volume for a new chunk
+    #[serde(default)]
+    pub volume_choosing_policy: VolumeChoosingPolicyKind,
+}
+impl StorageConfig {
+    pub fn volume_configs(&self) -> Vec<FileStorageConfig> {
+        let roots = if self.volumes.is_empty() {
+            vec![self.storage_path.clone()]
+        } else {
+            self.volumes.clone()
+        };
+        roots
+            .into_iter()
+            .map(|root| FileStorageConfig {
+                root,
+                create_mount: self.create_mount,
+                mount_size_in_mega_byte: self.mount_size_in_mega_byte,
+            })
+            .collect()
+    }
+}
+
+fn default_rack() -> String {
+    "default-rack".to_owned()
 }
-impl Into<FileStorageConfig> for StorageConfig {
-    fn into(self) -> FileStorageConfig {
-        FileStorageConfig {
-            root: self.storage_path,
-            create_mount: self.create_mount,
-            mount_size_in_mega_byte: self.mount_size_in_mega_byte,
+fn default_zone() -> String {
+    "default-zone".to_owned()
+}
+fn default_full_report_interval_secs() -> u64 {
+    600
+}
+fn default_staged_chunk_timeout_secs() -> u64 {
+    60 * 60
+}
+fn default_ticket_clock_skew_secs() -> u64 {
+    30
+}
+fn default_scan_interval_secs() -> u64 {
+    6 * 60 * 60
+}
+fn default_scan_bytes_per_sec() -> u64 {
+    4 * 1024 * 1024
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopologyConfig {
+    #[serde(default = "default_rack")]
+    pub rack: String,
+    #[serde(default = "default_zone")]
+    pub zone: String,
+}
+impl Default for TopologyConfig {
+    fn default() -> Self {
+        Self {
+            rack: default_rack(),
+            zone: default_zone(),
         }
     }
 }
@@ -45,6 +115,36 @@ pub struct Config {
     pub apm_endpoint: String,
     pub namenode_cert: String,
     pub secret_key: String,
+    // tickets are accepted this long before they were minted and after they expired, namenode
+    // clock is not ours
+    #[serde(default = "default_ticket_clock_skew_secs")]
+    pub ticket_clock_skew_secs: u64,
+    // private key of namenode_cert and certificate of the namenode CA, both from /cert/issue.
+    // datanode presents namenode_cert on grpc and the data plane and checks peers against the CA
+    #[serde(default)]
+    pub cert_key: String,
+    #[serde(default)]
+    pub root_cert: String,
+    // plaintext data plane is only meant for benchmarks
+    #[serde(default = "default_true")]
+    pub data_plane_tls: bool,
+    // location of the node, sent to namenode while connecting
+    #[serde(default)]
+    pub topology: TopologyConfig,
+    // changes are reported as they happen, full chunk list is only sent this often
+    #[serde(default = "default_full_report_interval_secs")]
+    pub full_report_interval_secs: u64,
+    // block scanner starts a pass over every chunk this often and reads at most this fast
+    #[serde(default = "default_scan_interval_secs")]
+    pub scan_interval_secs: u64,
+    #[serde(default = "default_scan_bytes_per_sec")]
+    pub scan_bytes_per_sec: u64,
+    // staged chunk not written to for this long is removed along with its pipeline
+    #[serde(default = "default_staged_chunk_timeout_secs")]
+    pub staged_chunk_timeout_secs: u64,
+    // datanode shuts down once more volumes than this have failed
+    #[serde(default)]
+    pub failed_volumes_tolerated: usize,
 }
 
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
@@ -1,26 +1,179 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use proto::generated::{
+    client_namenode::DataNodeMeta,
+    datanode_namenode::{CommandAck, ScanProgress},
+};
+use storage::volume_set::VolumeInfo;
+use utilities::tls::DataStream;
+
+use crate::storage_identity::StorageIdentity;
+
+// disk errors older than this are not reported to namenode
+const DISK_ERROR_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+// chunk changes not yet reported to namenode
+#[derive(Debug, Default)]
+pub struct ChunkReport {
+    // chunk id to generation
+    pub received: HashMap<String, u64>,
+    pub deleted: Vec<String>,
+    pub corrupt: Vec<String>,
+    // chunk id to unix secs it was verified by block scanner
+    pub verified: HashMap<String, u64>,
+}
+impl ChunkReport {
+    pub fn is_empty(&self) -> bool {
+        self.received.is_empty()
+            && self.deleted.is_empty()
+            && self.corrupt.is_empty()
+            && self.verified.is_empty()
+    }
+}
 
-use tokio::net::TcpStream;
 #[derive(Debug)]
 pub struct DatanodeState {
-    pub chunk_to_pipline: HashMap<String, TcpStream>,
+    pub chunk_to_pipline: HashMap<String, DataStream>,
     pub available_storage: usize,
-    pub available_chunks: Vec<String>,
+    pub total_storage: usize,
+    pub volumes: Vec<VolumeInfo>,
+    pub active_reads: usize,
+    pub active_writes: usize,
+    // replicate commands from namenode still running
+    pub pending_replications: usize,
+    disk_errors: VecDeque<Instant>,
+    // chunk id to generation of the replica
+    pub available_chunks: HashMap<String, u64>,
     pub to_be_deleted_chunks: HashSet<String>,
-    pub chunk_to_next_replica: HashMap<String, String>, // this will store the address of next
+    // when pipeline of the chunk was set up, entries of abandoned writes are cleared by age
+    pub pipeline_created_at: HashMap<String, Instant>,
+    pub chunk_report: ChunkReport,
+    // sequence of last chunk report namenode received
+    pub report_sequence: u64,
+    pub full_report_required: bool,
+    pub storage_identity: StorageIdentity,
+    // results of namenode commands, sent with next heartbeat
+    pub command_acks: Vec<CommandAck>,
+    // sent with every heartbeat
+    pub scan_progress: ScanProgress,
+    pub chunk_to_next_replica: HashMap<String, DataNodeMeta>, // this will store the address of next
     pub chunk_to_namenode_store_ticket: HashMap<String, String>, // this will store the ticket that
-                                                        // will be used to talk to peers
-                                                        // in case of store file
+                                                              // will be used to talk to peers
+                                                              // in case of store file
 }
 impl DatanodeState {
-    pub fn new() -> Self {
+    pub fn new(storage_identity: StorageIdentity) -> Self {
         Self {
             chunk_to_pipline: HashMap::default(),
             available_storage: 0,
-            available_chunks: vec![],
+            total_storage: 0,
+            volumes: vec![],
+            active_reads: 0,
+            active_writes: 0,
+            pending_replications: 0,
+            disk_errors: VecDeque::new(),
+            available_chunks: HashMap::default(),
             to_be_deleted_chunks: HashSet::default(),
+            pipeline_created_at: HashMap::default(),
+            chunk_report: ChunkReport::default(),
+            report_sequence: 0,
+            // namenode doesn't know anything about us on startup
+            full_report_required: true,
+            storage_identity,
+            command_acks: vec![],
+            scan_progress: ScanProgress::default(),
             chunk_to_next_replica: HashMap::default(),
             chunk_to_namenode_store_ticket: HashMap::default(),
         }
     }
+    pub fn pipeline_created(
+        &mut self,
+        chunk_id: &str,
+        pipeline: DataStream,
+        next_replica: DataNodeMeta,
+        ticket: String,
+    ) {
+        self.chunk_to_pipline.insert(chunk_id.to_owned(), pipeline);
+        self.chunk_to_next_replica
+            .insert(chunk_id.to_owned(), next_replica);
+        self.chunk_to_namenode_store_ticket
+            .insert(chunk_id.to_owned(), ticket);
+        self.pipeline_created_at
+            .insert(chunk_id.to_owned(), Instant::now());
+    }
+    pub fn clear_pipeline(&mut self, chunk_id: &str) {
+        self.chunk_to_pipline.remove(chunk_id);
+        self.chunk_to_next_replica.remove(chunk_id);
+        self.chunk_to_namenode_store_ticket.remove(chunk_id);
+        self.pipeline_created_at.remove(chunk_id);
+    }
+    // pipelines set up longer than timeout ago
+    pub fn stale_pipelines(&self, timeout: Duration) -> Vec<String> {
+        self.pipeline_created_at
+            .iter()
+            .filter(|(_, created_at)| created_at.elapsed() > timeout)
+            .map(|(chunk_id, _)| chunk_id.clone())
+            .collect()
+    }
+    pub fn transfer_started(&mut self, write: bool) {
+        if write {
+            self.active_writes += 1;
+        } else {
+            self.active_reads += 1;
+        }
+    }
+    pub fn transfer_finished(&mut self, write: bool) {
+        if write {
+            self.active_writes -= 1;
+        } else {
+            self.active_reads -= 1;
+        }
+    }
+    pub fn disk_error(&mut self) {
+        self.disk_errors.push_back(Instant::now());
+    }
+    pub fn recent_disk_errors(&mut self) -> usize {
+        while self
+            .disk_errors
+            .front()
+            .is_some_and(|instant| instant.elapsed() > DISK_ERROR_WINDOW)
+        {
+            self.disk_errors.pop_front();
+        }
+        self.disk_errors.len()
+    }
+    pub fn command_executed(&mut self, command_id: u64, result: Result<(), String>) {
+        self.command_acks.push(CommandAck {
+            id: command_id,
+            success: result.is_ok(),
+            error: result.err().unwrap_or_default(),
+        });
+    }
+    // chunk is already known to namenode so it only has to be served
+    pub fn chunk_available(&mut self, chunk_id: &str, generation: u64) {
+        self.available_chunks
+            .insert(chunk_id.to_owned(), generation);
+    }
+    pub fn chunk_received(&mut self, chunk_id: &str, generation: u64) {
+        self.chunk_available(chunk_id, generation);
+        self.chunk_report
+            .received
+            .insert(chunk_id.to_owned(), generation);
+    }
+    pub fn chunk_deleted(&mut self, chunk_id: &str) {
+        self.available_chunks.remove(chunk_id);
+        self.chunk_report.deleted.push(chunk_id.to_owned());
+    }
+    pub fn chunk_corrupt(&mut self, chunk_id: &str) {
+        self.available_chunks.remove(chunk_id);
+        self.chunk_report.corrupt.push(chunk_id.to_owned());
+    }
+    pub fn chunk_verified(&mut self, chunk_id: &str, verified_at: u64) {
+        self.chunk_report
+            .verified
+            .insert(chunk_id.to_owned(), verified_at);
+    }
 }
@@ -1,14 +1,17 @@
 use std::sync::Arc;
 use tonic::{Status, service::Interceptor};
-use utilities::{logger::info, ticket::ticket_decrypter::TicketDecrypter};
+use utilities::{
+    logger::info,
+    ticket::ticket_validator::{TicketError, TicketValidator},
+};
 
 #[derive(Clone)]
 pub struct TicketIntercepter {
-    ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+    ticket_validator: Arc<TicketValidator>,
 }
 impl TicketIntercepter {
-    pub fn new(ticket_decrypter: Arc<Box<dyn TicketDecrypter>>) -> Self {
-        Self { ticket_decrypter }
+    pub fn new(ticket_validator: Arc<TicketValidator>) -> Self {
+        Self { ticket_validator }
     }
 }
 
@@ -19,14 +22,9 @@ impl Interceptor for TicketIntercepter {
     ) -> Result<tonic::Request<()>, tonic::Status> {
         if let Some(encoded_ticket) = request.metadata().get("ticket") {
             let server_ticket = self
-                .ticket_decrypter
-                .decrypt_server_ticket(encoded_ticket.to_str().unwrap_or(""))
-                .map_err(|e| {
-                    tonic::Status::new(
-                        tonic::Code::PermissionDenied,
-                        format!("Error while decrypting/decoding server ticket {e}"),
-                    )
-                })?;
+                .ticket_validator
+                .validate(encoded_ticket.to_str().unwrap_or(""))
+                .map_err(ticket_error_status)?;
             info!("Got server ticket {:?}", server_ticket);
             request.extensions_mut().insert(server_ticket);
             Ok(request)
@@ -38,3 +36,13 @@ impl Interceptor for TicketIntercepter {
         }
     }
 }
+
+// caller can tell an expired ticket, which a fresh one fixes, from a replayed one
+fn ticket_error_status(e: TicketError) -> Status {
+    let code = match e {
+        TicketError::Invalid(_) => tonic::Code::PermissionDenied,
+        TicketError::Expired => tonic::Code::Unauthenticated,
+        TicketError::Replayed => tonic::Code::AlreadyExists,
+    };
+    Status::new(code, e.to_string())
+}
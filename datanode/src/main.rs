@@ -1,3 +1,4 @@
+mod block_scanner;
 mod client;
 mod config;
 mod datanode_state;
@@ -5,26 +6,36 @@ mod grpc;
 mod namenode;
 mod peer;
 mod state_mantainer;
+mod storage_identity;
 mod tcp;
 
+use block_scanner::BlockScanner;
 use client::handler::ClientHandler;
 use datanode_state::DatanodeState;
+use namenode::command_executor::CommandExecutor;
 use namenode::handler::NamenodeHandler;
-use namenode::service::NamenodeService;
+use namenode::service::{HeartBeatStreamEnd, NamenodeService};
 use proto::generated::client_datanode::client_data_node_server::ClientDataNodeServer;
 use proto::generated::datanode_datanode::peer_server::PeerServer;
 use proto::generated::namenode_datanode::namenode_datanode_server::NamenodeDatanodeServer;
 use state_mantainer::StateMantainer;
 use std::sync::Arc;
 use std::time::Duration;
-use storage::file_storage;
+use storage::volume_set::VolumeSet;
+use storage_identity::StorageIdentity;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 use tonic::transport::Server;
 use utilities::result::Result;
 use utilities::{
-    logger::{error, info, init_logger, trace},
-    ticket::ticket_decrypter::{DefaultTicketDecrypter, TicketDecrypter},
+    grpc_channel_pool::GRPC_CHANNEL_POOL,
+    logger::{error, info, init_logger, warn},
+    tcp_pool::TCP_CONNECTION_POOL,
+    ticket::{
+        ticket_decrypter::{DefaultTicketDecrypter, TicketDecrypter},
+        ticket_validator::TicketValidator,
+    },
+    tls::{self, DataAcceptor},
 };
 
 use crate::config::CONFIG;
@@ -43,19 +54,46 @@ async fn main() -> Result<()> {
         grpc_server_addrs = CONFIG.external_grpc_addrs,
         "Starting the grpc server on address"
     );
-    let state = Arc::new(Mutex::new(DatanodeState::new()));
     info!(storage_path = %CONFIG.storage_config.storage_path, "Creating storage");
-    let store = file_storage::FileStorage::new(CONFIG.storage_config.clone().into()).await;
+    let store = VolumeSet::new(
+        CONFIG.storage_config.volume_configs(),
+        CONFIG.storage_config.volume_choosing_policy.into(),
+    )
+    .await;
+    let storage_identity = match StorageIdentity::load_or_create(&store, &CONFIG.datanode_id).await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            error!(error = %e, "Error while reading storage identity Hence shuting down");
+            return Err(e);
+        }
+    };
+    let state = Arc::new(Mutex::new(DatanodeState::new(storage_identity)));
     let ticket_decrypter: Arc<Box<dyn TicketDecrypter>> =
         Arc::new(Box::new(DefaultTicketDecrypter::new(&CONFIG.secret_key)?));
     let ch = ClientHandler::new(state.clone(), store.clone(), ticket_decrypter.clone());
     let ph =
         peer::handler::PeerHandler::new(state.clone(), store.clone(), ticket_decrypter.clone());
-    let nh = NamenodeHandler::new(store.clone(), ticket_decrypter.clone());
-    let ticket_intercepter = TicketIntercepter::new(ticket_decrypter.clone());
+    let command_executor =
+        CommandExecutor::new(state.clone(), store.clone(), ticket_decrypter.clone());
+    let nh = NamenodeHandler::new(command_executor.clone());
+    // expired tickets and reused single use tickets are refused on grpc and the data plane
+    let ticket_validator = Arc::new(TicketValidator::new(
+        ticket_decrypter.clone(),
+        CONFIG.ticket_clock_skew_secs,
+    ));
+    let ticket_intercepter = TicketIntercepter::new(ticket_validator.clone());
+    // grpc is mutual TLS both ways, every node presents its certificate from the namenode CA
+    GRPC_CHANNEL_POOL
+        .enable_tls(&CONFIG.namenode_cert, &CONFIG.cert_key, &CONFIG.root_cert)
+        .map_err(|e| format!("Error while reading certificates for grpc TLS {e}"))?;
+    let grpc_tls_config =
+        tls::grpc_server_config(&CONFIG.namenode_cert, &CONFIG.cert_key, &CONFIG.root_cert)
+            .map_err(|e| format!("Error while reading certificates for grpc TLS {e}"))?;
     // first we will start grpc server
     info!(grpc_addr = %CONFIG.external_grpc_addrs,"Creating grpc server");
     let grpc_server = Server::builder()
+        .tls_config(grpc_tls_config)?
         .add_service(ClientDataNodeServer::with_interceptor(
             ch,
             ticket_intercepter.clone(),
@@ -68,13 +106,25 @@ async fn main() -> Result<()> {
         .serve(format!("0.0.0.0:{}", CONFIG.internal_grpc_port).parse()?);
     tokio::spawn(grpc_server);
     info!(grpc_addrs = %CONFIG.external_grpc_addrs,"grpc server is now running");
+    // data plane is served and reached over TLS with certificates of the namenode CA
+    let acceptor = if CONFIG.data_plane_tls {
+        TCP_CONNECTION_POOL
+            .enable_tls(&CONFIG.root_cert)
+            .map_err(|e| format!("Error while reading root_cert for data plane TLS {e}"))?;
+        DataAcceptor::tls(&CONFIG.namenode_cert, &CONFIG.cert_key)
+            .map_err(|e| format!("Error while reading cert_key for data plane TLS {e}"))?
+    } else {
+        warn!("Data plane TLS is turned off, chunks are sent in plaintext");
+        DataAcceptor::plaintext()
+    };
     // we will create storage which will be used by the tcp service to serve a file
     info!(tcp_addrs = %CONFIG.external_tcp_addrs,"Starting the tcp server");
     let tcp_handler = tcp::service::TCPService::new(
         format!("0.0.0.0:{}", CONFIG.internal_tcp_port).clone(),
         store.clone(),
         state.clone(),
-        ticket_decrypter,
+        ticket_validator,
+        acceptor,
     )
     .await
     .unwrap();
@@ -90,61 +140,33 @@ async fn main() -> Result<()> {
 
     // starting datanode state mantainer for datanode
     let state_mantainer = StateMantainer::new(store.clone(), state.clone());
-    state_mantainer.start_sync_loop(Duration::from_secs(5));
+    let state_mantainer_handle = state_mantainer.start_sync_loop(Duration::from_secs(5));
+    let block_scanner = BlockScanner::new(store.clone(), state.clone());
+    block_scanner.start_scan_loop(
+        Duration::from_secs(CONFIG.scan_interval_secs),
+        CONFIG.scan_bytes_per_sec,
+    );
 
-    // heartbeat sending logic
-    let namenode_service = NamenodeService::new(state.clone());
-    tokio::spawn(async move {
-        let max_retry_count = 5;
-        let mut retry_count = 0;
+    // heartbeat stream with namenode, opened again after every registration
+    let namenode_service = NamenodeService::new(state.clone(), ticket_decrypter);
+    let heart_beat_handle = tokio::spawn(async move {
         loop {
-            match namenode_service.connect().await {
-                Ok(v) => {
-                    if v {
-                        info!("successfully connected to the namenode");
-                        break;
-                    } else {
-                        if retry_count == max_retry_count {
-                            error!("Namenode refuse to connect after {max_retry_count} hence terminating");
-                            std::process::exit(1);
-                        }
-                        retry_count += 1;
-                        info!("Namenode refused to connect retrying...");
-                    }
-                }
+            namenode_service.register().await;
+            match namenode_service.heart_beat_stream(&command_executor).await {
+                Ok(HeartBeatStreamEnd::Reregister) => {}
+                Ok(HeartBeatStreamEnd::Shutdown) => return,
                 Err(e) => {
-                    error!("{e}");
-                    std::process::exit(1);
+                    error!("Heartbeat stream with namenode broke {e}");
+                    sleep(Duration::from_secs(3)).await;
                 }
             }
-            sleep(Duration::from_secs(retry_count*5)).await;
         }
-        // after every 10 heartbeats we will share state with name node;
-        let mut x: u8 = 0;
-        loop {
-            sleep(Duration::from_secs(3)).await;
-            match namenode_service.send_heart_beat().await {
-                Ok(_) => {
-                    //trace!("sent heartbeat successfully")
-                }
-                Err(e) => {
-                    error!("error while sending heartbeat {e}");
-                }
-            }
-            if x % 10 == 0 {
-                x = 0;
-                match namenode_service.state_sync().await {
-                    Ok(_) => {
-                        trace!("Sent state sync message to namenode");
-                    }
-                    Err(e) => {
-                        error!("Error while sending the state sync method to namenode {e}");
-                    }
-                }
-            }
-            x += 1;
+    });
+    tokio::select! {
+        result = heart_beat_handle => result?,
+        _ = state_mantainer_handle => {
+            error!("Shutting down datanode as too many volumes failed");
         }
-    })
-    .await?;
+    }
     Ok(())
 }
@@ -0,0 +1,209 @@
+use proto::generated::{
+    data_transfer::{TransferMode, TransferRequest},
+    datanode_namenode::{DatanodeCommand, datanode_command::Command},
+};
+use storage::{storage::Storage, volume_set::VolumeSet};
+use utilities::{
+    checksum::{ChecksumReader, VerifyingReader},
+    data_transfer,
+    logger::{Instrument, error, info, instrument, tracing},
+    packet::PacketSender,
+    result::Result,
+    tcp_pool::TCP_CONNECTION_POOL,
+    ticket::ticket_decrypter::TicketDecrypter,
+};
+
+use crate::{datanode_state::DatanodeState, peer::service::PeerService};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Replicates and deletes chunks for namenode, used for commands delivered with heartbeats
+#[derive(Clone)]
+pub struct CommandExecutor {
+    state: Arc<Mutex<DatanodeState>>,
+    store: VolumeSet,
+    peer_service: PeerService,
+    ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+}
+
+impl CommandExecutor {
+    pub fn new(
+        state: Arc<Mutex<DatanodeState>>,
+        store: VolumeSet,
+        ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+    ) -> Self {
+        Self {
+            state,
+            store,
+            peer_service: PeerService::default(),
+            ticket_decrypter,
+        }
+    }
+    // replicate and delete run in background, result is acknowledged with the next heartbeat.
+    // reregister and shutdown are returned back as they are handled by the heartbeat loop
+    pub fn execute(&self, command: DatanodeCommand) -> Option<(u64, Command)> {
+        let command_id = command.id;
+        let command = command.command?;
+        let executor = self.clone();
+        match command {
+            Command::Replicate(replicate) => {
+                tokio::spawn(
+                    async move {
+                        executor.state.lock().await.pending_replications += 1;
+                        let result = executor
+                            .replicate_chunk(
+                                &replicate.chunk_id,
+                                &replicate.target_data_node,
+                                &replicate.target_data_node_id,
+                                &replicate.ticket,
+                            )
+                            .await
+                            .map_err(|e| e.to_string());
+                        if let Err(e) = &result {
+                            error!(chunk_id = %replicate.chunk_id, "Error while replicating chunk {e}");
+                        }
+                        let mut state = executor.state.lock().await;
+                        state.pending_replications -= 1;
+                        state.command_executed(command_id, result);
+                    }
+                    .in_current_span(),
+                );
+                None
+            }
+            Command::Delete(delete) => {
+                tokio::spawn(
+                    async move {
+                        let result = executor
+                            .delete_chunk(&delete.chunk_id)
+                            .await
+                            .map(|_| ())
+                            .map_err(|e| e.to_string());
+                        if let Err(e) = &result {
+                            error!(chunk_id = %delete.chunk_id, "Error while deleting chunk {e}");
+                        }
+                        executor
+                            .state
+                            .lock()
+                            .await
+                            .command_executed(command_id, result);
+                    }
+                    .in_current_span(),
+                );
+                None
+            }
+            command => Some((command_id, command)),
+        }
+    }
+    // returns false if chunk was not present
+    #[instrument(name = "command_delete_chunk", skip(self))]
+    pub async fn delete_chunk(&self, chunk_id: &str) -> Result<bool> {
+        let exists = match self.store.delete(chunk_id.to_owned()).await {
+            Ok(exists) => exists,
+            Err(e) => {
+                self.state.lock().await.disk_error();
+                return Err(e);
+            }
+        };
+        if exists {
+            self.state.lock().await.chunk_deleted(chunk_id);
+        }
+        Ok(exists)
+    }
+    #[instrument(name = "command_replicate_chunk", skip(self, ticket))]
+    pub async fn replicate_chunk(
+        &self,
+        chunk_id: &str,
+        target_data_node: &str,
+        target_data_node_id: &str,
+        ticket: &str,
+    ) -> Result<()> {
+        let client_ticket = self
+            .ticket_decrypter
+            .decrypt_client_ticket(ticket)
+            .map_err(|e| format!("Error while decrypting the ticket {:?}", e))?;
+        // first we will send the grpc call
+        let target_tcp_address = self
+            .peer_service
+            .store_chunk(
+                chunk_id,
+                target_data_node,
+                target_data_node_id,
+                &client_ticket.encrypted_server_ticket,
+            )
+            .await?;
+        self.transfer_chunk_content(
+            target_tcp_address,
+            target_data_node_id,
+            chunk_id.to_owned(),
+            &client_ticket.encrypted_server_ticket,
+        )
+        .await
+        .map_err(|e| format!("Error while transferring the chunk content, {e}"))?;
+        // replica keeps the generation of the source
+        let generation = self
+            .store
+            .get_generation(chunk_id)
+            .await
+            .map_err(|e| format!("Error while reading generation of chunk, {e}"))?;
+        // next we will send commit chunk Request
+        self.peer_service
+            .commit_chunk(
+                chunk_id,
+                generation,
+                target_data_node,
+                target_data_node_id,
+                &client_ticket.encrypted_server_ticket,
+            )
+            .await
+            .map_err(|e| format!("Error while sending commit message , {e}"))?;
+        info!("Chunk replicated");
+        Ok(())
+    }
+    async fn transfer_chunk_content(
+        &self,
+        tcp_address: String,
+        target_data_node_id: &str,
+        chunk_id: String,
+        ticket: &str,
+    ) -> Result<()> {
+        // now we will transfer this chunk to target
+        let chunk_size = self.store.get_chunk_size(&chunk_id).await?;
+        let transfer_request = TransferRequest {
+            mode: TransferMode::Write as i32,
+            chunk_id: chunk_id.clone(),
+            ticket: ticket.to_string(),
+            chunk_size,
+            offset: 0,
+        };
+        // connect to tcp stream
+        let mut tcp_stream = TCP_CONNECTION_POOL
+            .get_connection(&tcp_address, target_data_node_id)
+            .await?;
+        let transfer_response =
+            data_transfer::open_transfer(&mut tcp_stream, &transfer_request).await?;
+        // corrupt replica is never copied, chunks without stored checksums get them computed here
+        let chunk_stream = self.store.read(chunk_id.clone()).await?;
+        let stored_checksums = self.store.get_checksums(&chunk_id).await?;
+        let mut chunk_stream =
+            ChecksumReader::new(VerifyingReader::new(chunk_stream, stored_checksums));
+        let mut packet_sender = PacketSender::new(tcp_stream, 0);
+        if let Err(e) = packet_sender.send(&mut chunk_stream).await {
+            if let Some(e) = e.downcast_ref::<std::io::Error>()
+                && e.kind() == std::io::ErrorKind::InvalidData
+            {
+                self.state.lock().await.chunk_corrupt(&chunk_id);
+            }
+            return Err(e);
+        }
+        let ack = packet_sender
+            .finish(
+                transfer_response.version as u16,
+                chunk_stream.into_checksums(),
+            )
+            .await?;
+        if ack.statuses.first() != Some(&true) {
+            return Err("Target datanode rejected the chunk content".into());
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,3 @@
-pub mod auth_intercepter;
+pub mod command_executor;
 pub mod handler;
 pub mod service;
@@ -1,99 +1,366 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use proto::generated::datanode_namenode::{
-    ConnectionRequest, HeartBeatRequest, StateSyncRequest, StoreChunkTicketRequest,
-    datanode_namenode_client::DatanodeNamenodeClient,
+    ChunkCommittedRequest, ChunkReportRequest, ChunkReportResponse, ConnectionRequest,
+    DatanodeCommand, DatanodeMessage, HeartBeatRequest, NodeKeysRequest, StateSyncRequest,
+    StateSyncResponse, StoreChunkTicketRequest, VolumeReport, datanode_command::Command,
+    datanode_message, datanode_namenode_client::DatanodeNamenodeClient, namenode_message,
+};
+use tokio::{
+    sync::{Mutex, mpsc},
+    time::{interval, sleep},
 };
-use tokio::sync::Mutex;
+use tokio_stream::wrappers::ReceiverStream;
 use utilities::{
     grpc_channel_pool::GRPC_CHANNEL_POOL,
-    logger::{error, instrument, trace, tracing},
+    logger::{error, info, instrument, trace, tracing},
     result::Result,
+    ticket::{ticket_decrypter::TicketDecrypter, types::NodeKey},
+    tls::NAMENODE_SERVER_NAME,
 };
 
 use crate::{
-    config::CONFIG, datanode_state::DatanodeState,
-    namenode::auth_intercepter::NamenodeAuthIntercepter,
+    config::CONFIG, datanode_state::DatanodeState, namenode::command_executor::CommandExecutor,
 };
-use tonic::{service::interceptor::InterceptedService, transport::Channel};
+use tonic::transport::Channel;
+
+// why heartbeat stream ended without an error
+pub enum HeartBeatStreamEnd {
+    Reregister,
+    Shutdown,
+}
 
 pub struct NamenodeService {
     state: Arc<Mutex<DatanodeState>>,
+    ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
 }
 impl NamenodeService {
-    pub fn new(state: Arc<Mutex<DatanodeState>>) -> Self {
-        Self { state }
+    pub fn new(
+        state: Arc<Mutex<DatanodeState>>,
+        ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+    ) -> Self {
+        Self {
+            state,
+            ticket_decrypter,
+        }
     }
-    async fn get_grpc_connection(
-        &self,
-        addrs: &str,
-    ) -> Result<DatanodeNamenodeClient<InterceptedService<Channel, NamenodeAuthIntercepter>>> {
-        let channel = GRPC_CHANNEL_POOL.get_channel(addrs).await.unwrap();
-        Ok(DatanodeNamenodeClient::with_interceptor(
-            channel,
-            NamenodeAuthIntercepter,
-        ))
+    async fn get_grpc_connection(&self, addrs: &str) -> Result<DatanodeNamenodeClient<Channel>> {
+        // namenode knows who we are from our certificate
+        let channel = GRPC_CHANNEL_POOL
+            .get_channel(addrs, NAMENODE_SERVER_NAME)
+            .await
+            .unwrap();
+        Ok(DatanodeNamenodeClient::new(channel))
     }
     #[instrument(name = "service_namenode_connect", skip(self))]
     pub async fn connect(&self) -> Result<bool> {
+        let storage_identity = self.state.lock().await.storage_identity.clone();
         // now we will send connection Request
         let connection_request = ConnectionRequest {
             name: CONFIG.datanode_id.clone(),
             id: CONFIG.datanode_id.clone(),
             addrs: CONFIG.external_grpc_addrs.clone(),
+            rack: CONFIG.topology.rack.clone(),
+            zone: CONFIG.topology.zone.clone(),
+            cluster_id: storage_identity.cluster_id.clone(),
+            storage_id: storage_identity.storage_id.clone(),
+            storage_datanode_id: storage_identity.datanode_id.clone(),
         };
         let mut namenode_client = self.get_grpc_connection(&CONFIG.namenode_addrs).await?;
         match namenode_client
             .connection(tonic::Request::new(connection_request))
             .await
         {
-            Ok(connected) => Ok(connected.into_inner().connected),
+            Ok(response) => {
+                let response = response.into_inner();
+                if !response.connected {
+                    error!(msg = %response.msg, "Namenode refused connection");
+                    return Ok(false);
+                }
+                // first time this storage joins a cluster, from now on it is bound to it
+                if storage_identity.cluster_id.is_empty() {
+                    info!(cluster_id = %response.cluster_id, "Binding storage to cluster");
+                    self.state
+                        .lock()
+                        .await
+                        .storage_identity
+                        .persist(&response.cluster_id)
+                        .await?;
+                }
+                Ok(true)
+            }
             Err(tonic_status) => {
                 error!(error = ?tonic_status,"Error while connecting to namenode");
                 Err(format!("Error while connecting to namenode {tonic_status}").into())
             }
         }
     }
-    #[instrument(name = "service_namenode_send_heart_beat", skip(self))]
-    pub async fn send_heart_beat(&self) -> Result<()> {
-        // now we will send this address to the datanode
+    // keeps trying to connect till namenode accepts us, waiting longer after every failure
+    #[instrument(name = "service_namenode_register", skip(self))]
+    pub async fn register(&self) {
+        let mut backoff = Duration::from_secs(1);
+        loop {
+            match self.connect().await {
+                Ok(true) => {
+                    info!("successfully connected to the namenode");
+                    break;
+                }
+                Ok(false) => {
+                    info!(?backoff, "Namenode refused to connect retrying...");
+                }
+                Err(e) => {
+                    error!(?backoff, "Error while connecting to namenode {e}");
+                }
+            }
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(Duration::from_secs(60));
+        }
+        // namenode doesn't know our chunks yet
+        self.state.lock().await.full_report_required = true;
+    }
+    // runs heartbeat stream till it breaks or namenode asks us to register again or shut down.
+    // heartbeats go every 3 seconds with chunk changes, storage is synced every 10 heartbeats
+    #[instrument(
+        name = "service_namenode_heart_beat_stream",
+        skip(self, command_executor)
+    )]
+    pub async fn heart_beat_stream(
+        &self,
+        command_executor: &CommandExecutor,
+    ) -> Result<HeartBeatStreamEnd> {
+        let (tx, rx) = mpsc::channel(16);
+        let mut namenode_client = self.get_grpc_connection(&CONFIG.namenode_addrs).await?;
+        let mut inbound = namenode_client
+            .heart_beat_stream(tonic::Request::new(ReceiverStream::new(rx)))
+            .await?
+            .into_inner();
+        info!("Heartbeat stream opened");
+        let mut ticker = interval(Duration::from_secs(3));
+        let full_report_interval = Duration::from_secs(CONFIG.full_report_interval_secs);
+        let mut last_full_report = Instant::now();
+        let mut x: u8 = 0;
+        loop {
+            let commands = tokio::select! {
+                _ = ticker.tick() => {
+                    tx.send(self.heart_beat_message().await).await?;
+                    if let Some(chunk_report) = self.chunk_report_message().await {
+                        tx.send(chunk_report).await?;
+                    }
+                    if x.is_multiple_of(10) {
+                        x = 0;
+                        let full_report = self.state.lock().await.full_report_required
+                            || last_full_report.elapsed() > full_report_interval;
+                        if full_report {
+                            last_full_report = Instant::now();
+                        }
+                        tx.send(self.state_sync_message(full_report).await).await?;
+                    }
+                    x += 1;
+                    continue;
+                }
+                message = inbound.message() => {
+                    let message = message?.ok_or("Namenode closed the heartbeat stream")?;
+                    match message.message {
+                        Some(namenode_message::Message::HeartBeat(response)) => {
+                            if !response.connection_alive {
+                                // namenode restarted or dropped us
+                                info!("Namenode doesn't know this datanode, registering again");
+                                return Ok(HeartBeatStreamEnd::Reregister);
+                            }
+                            // namenode rotated our key, tickets use it once we report it
+                            if response.key_id != 0
+                                && response.key_id != self.ticket_decrypter.newest_key_id()
+                                && let Err(e) = self.fetch_keys().await
+                            {
+                                error!(key_id = response.key_id, "Error while fetching node keys {e}");
+                            }
+                            response.commands
+                        }
+                        Some(namenode_message::Message::ChunkReport(response)) => {
+                            self.chunk_report_response(response).await;
+                            vec![]
+                        }
+                        Some(namenode_message::Message::StateSync(response)) => {
+                            self.state_sync_response(response).await
+                        }
+                        Some(namenode_message::Message::Commands(commands)) => commands.commands,
+                        None => vec![],
+                    }
+                }
+            };
+            // replicate and delete run in background, rest ends the stream
+            for (command_id, command) in commands
+                .into_iter()
+                .filter_map(|command| command_executor.execute(command))
+            {
+                match command {
+                    Command::Reregister(_) => {
+                        info!(command_id, "Namenode asked to register again");
+                        self.state.lock().await.command_executed(command_id, Ok(()));
+                        return Ok(HeartBeatStreamEnd::Reregister);
+                    }
+                    Command::Shutdown(shutdown) => {
+                        info!(command_id, reason = %shutdown.reason, "Namenode asked to shut down");
+                        self.state.lock().await.command_executed(command_id, Ok(()));
+                        // best effort so namenode knows command was received
+                        tx.send(self.heart_beat_message().await).await?;
+                        let _ = inbound.message().await;
+                        return Ok(HeartBeatStreamEnd::Shutdown);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    async fn heart_beat_message(&self) -> DatanodeMessage {
+        let mut state = self.state.lock().await;
         let heart_beat_request = HeartBeatRequest {
             datanode_id: CONFIG.datanode_id.clone(),
+            active_transfers: (state.active_reads + state.active_writes) as u64,
+            acks: std::mem::take(&mut state.command_acks),
+            active_reads: state.active_reads as u64,
+            active_writes: state.active_writes as u64,
+            pending_replications: state.pending_replications as u64,
+            volumes: state
+                .volumes
+                .iter()
+                .map(|volume| VolumeReport {
+                    path: volume.root.clone(),
+                    capacity: volume.capacity as u64,
+                    used: volume.capacity.saturating_sub(volume.available) as u64,
+                    available: volume.available as u64,
+                    failed: volume.failed,
+                })
+                .collect(),
+            recent_disk_errors: state.recent_disk_errors() as u64,
+            scan_progress: Some(state.scan_progress),
+            key_id: self.ticket_decrypter.newest_key_id(),
         };
-        let mut namenode_client = self.get_grpc_connection(&CONFIG.namenode_addrs).await?;
-        namenode_client
-            .heart_beat(tonic::Request::new(heart_beat_request))
-            .await?;
-        Ok(())
+        DatanodeMessage {
+            message: Some(datanode_message::Message::HeartBeat(heart_beat_request)),
+        }
     }
-    #[instrument(name = "service_namenode_state_sync", skip(self))]
-    pub async fn state_sync(&self) -> Result<()> {
+    // full report carries every chunk on this node, otherwise only storage is synced
+    async fn state_sync_message(&self, full_report: bool) -> DatanodeMessage {
         let mut state = self.state.lock().await;
         trace!(?state, "sending state sync with");
         let state_sync_request = StateSyncRequest {
             id: CONFIG.datanode_id.clone(),
-            available_chunks: state.available_chunks.clone(),
+            available_chunks: if full_report {
+                state.available_chunks.clone()
+            } else {
+                HashMap::new()
+            },
             availabe_storage: state.available_storage as u64,
+            storage_capacity: state.total_storage as u64,
+            full_report,
+            report_sequence: state.report_sequence,
         };
-        let mut namenode_client = self.get_grpc_connection(&CONFIG.namenode_addrs).await?;
-        let state_sync_response = match namenode_client
-            .state_sync(tonic::Request::new(state_sync_request))
-            .await
-        {
-            Ok(response) => response,
+        // stream is ordered so report reaches namenode unless stream breaks, in which case we
+        // register again and send a new one
+        if full_report {
+            state.full_report_required = false;
+        }
+        DatanodeMessage {
+            message: Some(datanode_message::Message::StateSync(state_sync_request)),
+        }
+    }
+    async fn state_sync_response(&self, response: StateSyncResponse) -> Vec<DatanodeCommand> {
+        let mut state = self.state.lock().await;
+        response.chunks_to_be_deleted.into_iter().for_each(|chunk| {
+            state.to_be_deleted_chunks.insert(chunk);
+        });
+        response.commands
+    }
+    // tells namenode about the new replica right away, if that fails it goes with next chunk report
+    #[instrument(name = "service_namenode_chunk_committed", skip(self))]
+    pub async fn chunk_committed(&self, chunk_id: &str, generation: u64) {
+        let chunk_committed_request = ChunkCommittedRequest {
+            datanode_id: CONFIG.datanode_id.clone(),
+            chunk_id: chunk_id.to_owned(),
+            generation,
+        };
+        let response = match self.get_grpc_connection(&CONFIG.namenode_addrs).await {
+            Ok(mut namenode_client) => namenode_client
+                .chunk_committed(tonic::Request::new(chunk_committed_request))
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let mut state = self.state.lock().await;
+        match response {
+            Ok(response) => {
+                if response.into_inner().to_be_deleted {
+                    state.to_be_deleted_chunks.insert(chunk_id.to_owned());
+                } else {
+                    state.chunk_available(chunk_id, generation);
+                }
+            }
             Err(e) => {
-                error!("Error while sending the state sync message to namenode, {e}");
-                return Err(e.into());
+                error!("Error while notifying namenode about committed chunk, {e}");
+                state.chunk_received(chunk_id, generation);
             }
+        }
+    }
+    // chunk changes since last report, none if nothing changed
+    async fn chunk_report_message(&self) -> Option<DatanodeMessage> {
+        let mut state = self.state.lock().await;
+        if state.chunk_report.is_empty() {
+            return None;
+        }
+        let chunk_report = std::mem::take(&mut state.chunk_report);
+        state.report_sequence += 1;
+        let chunk_report_request = ChunkReportRequest {
+            datanode_id: CONFIG.datanode_id.clone(),
+            sequence: state.report_sequence,
+            received: chunk_report.received,
+            deleted: chunk_report.deleted,
+            corrupt: chunk_report.corrupt,
+            verified: chunk_report.verified,
         };
-
-        state_sync_response
+        Some(DatanodeMessage {
+            message: Some(datanode_message::Message::ChunkReport(chunk_report_request)),
+        })
+    }
+    async fn chunk_report_response(&self, response: ChunkReportResponse) {
+        let mut state = self.state.lock().await;
+        if response.full_report_required {
+            info!(
+                sequence = state.report_sequence,
+                "Namenode requested full chunk report"
+            );
+            state.full_report_required = true;
+        }
+        response.chunks_to_be_deleted.into_iter().for_each(|chunk| {
+            state.to_be_deleted_chunks.insert(chunk);
+        });
+    }
+    // keys to accept tickets with, namenode knows who we are from our certificate
+    #[instrument(name = "service_namenode_fetch_keys", skip(self))]
+    async fn fetch_keys(&self) -> Result<()> {
+        let mut namenode_client = self.get_grpc_connection(&CONFIG.namenode_addrs).await?;
+        let response = namenode_client
+            .node_keys(tonic::Request::new(NodeKeysRequest {}))
+            .await?;
+        let keys: Vec<NodeKey> = response
             .into_inner()
-            .chunks_to_be_deleted
+            .keys
             .into_iter()
-            .for_each(|chunk| {
-                state.to_be_deleted_chunks.insert(chunk);
-            });
+            .map(|k| NodeKey {
+                key_id: k.key_id,
+                key: k.key,
+                expires_at_secs: k.expires_at_secs,
+            })
+            .collect();
+        self.ticket_decrypter.set_keys(&keys)?;
+        info!(
+            key_id = self.ticket_decrypter.newest_key_id(),
+            "Fetched node keys"
+        );
         Ok(())
     }
     #[instrument(name = "service_namenode_send_heart_beat", skip(self))]
@@ -4,11 +4,10 @@ use proto::generated::datanode_datanode::{
     CommitChunkRequest, CommitChunkResponse, CreatePipelineRequest, CreatePipelineResponse,
     StoreChunkRequest, StoreChunkResponse, peer_server::Peer,
 };
-use storage::{file_storage::FileStorage, storage::Storage};
-use tokio::{net::TcpStream, sync::Mutex};
+use storage::{storage::Storage, volume_set::VolumeSet};
+use tokio::sync::Mutex;
 use utilities::{
-    logger::{error, instrument, trace, tracing},
-    result::Result,
+    logger::{error, instrument, trace, tracing, warn},
     ticket::{
         ticket_decrypter::TicketDecrypter,
         types::{Operation, ServerTicket},
@@ -23,32 +22,24 @@ pub struct PeerHandler {
     state: Arc<Mutex<DatanodeState>>,
     peer_service: PeerService,
     namenode_service: NamenodeService,
-    store: FileStorage,
+    store: VolumeSet,
     ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
 }
 
 impl PeerHandler {
     pub fn new(
         state: Arc<Mutex<DatanodeState>>,
-        store: FileStorage,
+        store: VolumeSet,
         ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
     ) -> Self {
         Self {
-            namenode_service: NamenodeService::new(state.clone()),
+            namenode_service: NamenodeService::new(state.clone(), ticket_decrypter.clone()),
             state,
             peer_service: PeerService::new(),
             store,
             ticket_decrypter,
         }
     }
-    async fn get_tcp_connection(&self, addrs: &str) -> Result<TcpStream> {
-        Ok(TcpStream::connect(addrs).await.map_err(|e| {
-            format!(
-                "error while creating the tcp connection addrs : {}, error: {}",
-                addrs, e
-            )
-        })?)
-    }
 }
 
 #[tonic::async_trait]
@@ -75,62 +66,40 @@ impl Peer for PeerHandler {
             ));
         }
 
+        let chunk_id = &create_pipeline_request.chunk_id;
+        // pipeline of an earlier attempt may lead to a replica which failed
+        self.state.lock().await.clear_pipeline(chunk_id);
+        let mut staged_bytes = self.store.get_staged_size(chunk_id).await.map_err(|e| {
+            tonic::Status::internal(format!("Error while reading staged chunk : {e}"))
+        })?;
+        let mut pipeline = vec![CONFIG.datanode_id.clone()];
         // first we will send the create pipeling request to the next replica
         if create_pipeline_request.replica_set.len() > 1 {
             trace!(replica_set = ?create_pipeline_request.replica_set,"Passing create pipeline request to next");
-            let ticket = self
-                .namenode_service
-                .get_store_chunk_ticket(
-                    &create_pipeline_request.replica_set[1].id,
-                    &create_pipeline_request.chunk_id,
-                )
-                .await
-                .unwrap();
-            let client_ticket = self
-                .ticket_decrypter
-                .decrypt_client_ticket(&ticket)
-                .unwrap();
-            let tcp_address = match self
+            match self
                 .peer_service
-                .create_pipeline(
-                    &create_pipeline_request.chunk_id,
+                .extend_pipeline(
+                    &self.state,
+                    &self.namenode_service,
+                    &self.ticket_decrypter,
+                    chunk_id,
                     &create_pipeline_request.replica_set[1..],
-                    &client_ticket.encrypted_server_ticket,
                 )
                 .await
             {
-                Ok(addrs) => addrs,
-                Err(e) => {
-                    return Err(tonic::Status::internal(format!(
-                        "error while crating pipeline : {}",
-                        e
-                    )));
+                Ok(response) => {
+                    staged_bytes = std::cmp::min(staged_bytes, response.staged_bytes);
+                    pipeline.extend(response.pipeline);
                 }
-            };
-            let tcp_connection = match self.get_tcp_connection(&tcp_address).await {
-                Ok(connection) => connection,
                 Err(e) => {
-                    return Err(tonic::Status::internal(format!(
-                        "error while crating pipeline : {}",
-                        e
-                    )));
+                    warn!(error = %e, "Pipeline could not be set up to next replica");
                 }
-            };
-            let mut state = self.state.lock().await;
-            state
-                .chunk_to_pipline
-                .insert(create_pipeline_request.chunk_id.clone(), tcp_connection);
-            state.chunk_to_next_replica.insert(
-                create_pipeline_request.chunk_id.clone(),
-                create_pipeline_request.replica_set[1].addrs.clone(),
-            );
-            state.chunk_to_namenode_store_ticket.insert(
-                create_pipeline_request.chunk_id.clone(),
-                client_ticket.encrypted_server_ticket,
-            );
+            }
         }
         let response = CreatePipelineResponse {
             address: CONFIG.external_tcp_addrs.clone(),
+            staged_bytes,
+            pipeline,
         };
         Ok(tonic::Response::new(response))
     }
@@ -190,7 +159,7 @@ impl Peer for PeerHandler {
         }
 
         let mut state = self.state.lock().await;
-        if let Some(next_replica_node_grpc) = state
+        if let Some(next_replica) = state
             .chunk_to_next_replica
             .remove(&commit_chunk_request.chunk_id)
         {
@@ -201,30 +170,31 @@ impl Peer for PeerHandler {
             trace!("have next replica so transferring commit message");
             drop(state); // droping state to remove lock
             //send commit message to next_replica_node_grpc
-            let _committed = match self
+            // replica which can't be reached is left out, namenode repairs the chunk from us
+            if let Err(e) = self
                 .peer_service
                 .commit_chunk(
                     &commit_chunk_request.chunk_id,
-                    &next_replica_node_grpc,
+                    commit_chunk_request.generation,
+                    &next_replica.addrs,
+                    &next_replica.id,
                     &ticket,
                 )
                 .await
             {
-                Ok(v) => v,
-                Err(e) => {
-                    error!(error = %e,next_addrs=%next_replica_node_grpc,"Error while sending commit messag to next replica");
-                    return Err(tonic::Status::new(
-                        tonic::Code::Aborted,
-                        "Error while commiting message on next replica",
-                    ));
-                }
-            };
+                error!(error = %e,next_addrs=%next_replica.addrs,"Error while sending commit messag to next replica");
+            }
         }
         //else {
         //    state.available_chunks
         //}
         trace!("committed successfully");
-        let committed = match self.store.commit(commit_chunk_request.chunk_id).await {
+        let chunk_id = commit_chunk_request.chunk_id;
+        let committed = match self
+            .store
+            .commit(chunk_id.clone(), commit_chunk_request.generation)
+            .await
+        {
             Ok(v) => v,
             Err(e) => {
                 error!(error=%e,"Error while commiting chunk");
@@ -235,6 +205,11 @@ impl Peer for PeerHandler {
             }
         };
         trace!("commited successfully");
+        if committed {
+            self.namenode_service
+                .chunk_committed(&chunk_id, commit_chunk_request.generation)
+                .await;
+        }
         let commit_chunk_response = CommitChunkResponse { committed };
         Ok(tonic::Response::new(commit_chunk_response))
     }
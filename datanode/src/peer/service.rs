@@ -1,19 +1,25 @@
 use proto::generated::{
     client_namenode::DataNodeMeta,
     datanode_datanode::{
-        CommitChunkRequest, CreatePipelineRequest, StoreChunkRequest, peer_client::PeerClient,
+        CommitChunkRequest, CreatePipelineRequest, CreatePipelineResponse, StoreChunkRequest,
+        peer_client::PeerClient,
     },
 };
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
+use tokio::sync::Mutex;
 use tonic::{metadata::MetadataValue, transport::Channel};
 use utilities::{
     grpc_channel_pool::GRPC_CHANNEL_POOL,
     logger::{instrument, trace, tracing},
     result::Result,
     retry_policy::retry_with_backoff,
+    tcp_pool::TCP_CONNECTION_POOL,
+    ticket::ticket_decrypter::TicketDecrypter,
 };
 
-#[derive(Default)]
+use crate::{datanode_state::DatanodeState, namenode::service::NamenodeService};
+
+#[derive(Clone, Default)]
 pub struct PeerService {}
 
 impl PeerService {
@@ -21,8 +27,12 @@ impl PeerService {
         Self {}
     }
 
-    async fn get_grpc_connection(&self, addrs: &str) -> Result<PeerClient<Channel>> {
-        let channel = GRPC_CHANNEL_POOL.get_channel(addrs).await?;
+    async fn get_grpc_connection(
+        &self,
+        addrs: &str,
+        datanode_id: &str,
+    ) -> Result<PeerClient<Channel>> {
+        let channel = GRPC_CHANNEL_POOL.get_channel(addrs, datanode_id).await?;
         Ok(PeerClient::new(channel))
     }
     #[instrument(name = "service_peer_create_pipeline", skip(self))]
@@ -31,7 +41,7 @@ impl PeerService {
         chunk_id: &str,
         replica_set: &[DataNodeMeta],
         ticket: &str,
-    ) -> Result<String> {
+    ) -> Result<CreatePipelineResponse> {
         trace!("Sending create pipeline request to peers");
         // since there are other replica we will have to send create pipeline message to next
         // replica
@@ -46,7 +56,9 @@ impl PeerService {
                     .metadata_mut()
                     .insert("ticket", MetadataValue::from_str(ticket)?);
                 // send this request to request
-                let mut client = self.get_grpc_connection(&replica_set[0].addrs).await?;
+                let mut client = self
+                    .get_grpc_connection(&replica_set[0].addrs, &replica_set[0].id)
+                    .await?;
                 client
                     .create_pipeline(create_pipeline_request)
                     .await
@@ -61,18 +73,64 @@ impl PeerService {
             3,
         )
         .await?;
-        let create_pipelince_response = response.get_ref();
-        Ok(create_pipelince_response.address.to_owned())
+        Ok(response.into_inner())
+    }
+    // sets up the pipeline from this datanode to the rest of replica set, the response tells how
+    // far it got and how much of the chunk is already staged behind us
+    #[instrument(
+        name = "service_peer_extend_pipeline",
+        skip(self, state, namenode_service, ticket_decrypter, replica_set)
+    )]
+    pub async fn extend_pipeline(
+        &self,
+        state: &Arc<Mutex<DatanodeState>>,
+        namenode_service: &NamenodeService,
+        ticket_decrypter: &Arc<Box<dyn TicketDecrypter>>,
+        chunk_id: &str,
+        replica_set: &[DataNodeMeta],
+    ) -> Result<CreatePipelineResponse> {
+        // to send a pipeline request to the peer we need ticket
+        let ticket = namenode_service
+            .get_store_chunk_ticket(&replica_set[0].id, chunk_id)
+            .await
+            .map_err(|e| format!("Error while getting peer ticket : {e}"))?;
+        let client_ticket = ticket_decrypter
+            .decrypt_client_ticket(&ticket)
+            .map_err(|e| format!("Error while getting peer ticket : {e}"))?;
+        let response = self
+            .create_pipeline(
+                chunk_id,
+                replica_set,
+                &client_ticket.encrypted_server_ticket,
+            )
+            .await?;
+        trace!(tcp_addrs = %response.address,"Got the pipeline address");
+        let tcp_connection = TCP_CONNECTION_POOL
+            .get_connection(&response.address, &replica_set[0].id)
+            .await?;
+        state.lock().await.pipeline_created(
+            chunk_id,
+            tcp_connection,
+            replica_set[0].clone(),
+            client_ticket.encrypted_server_ticket,
+        );
+        Ok(response)
     }
     #[instrument(name = "service_peer_store_chunk", skip(self))]
-    pub async fn store_chunk(&self, chunk_id: &str, addrs: &str, ticket: &str) -> Result<String> {
+    pub async fn store_chunk(
+        &self,
+        chunk_id: &str,
+        addrs: &str,
+        datanode_id: &str,
+        ticket: &str,
+    ) -> Result<String> {
         let response = retry_with_backoff(
             || async {
                 let mut store_chunk_request =tonic::Request::new( StoreChunkRequest {
                     chunk_id: chunk_id.to_owned(),
                 });
                 store_chunk_request.metadata_mut().insert("ticket", MetadataValue::from_str(ticket)?);
-                let mut client = self.get_grpc_connection(addrs).await?;
+                let mut client = self.get_grpc_connection(addrs, datanode_id).await?;
                 client.store_chunk(store_chunk_request).await.map_err(|e| {
                     format!(
                         "Error while sending store chunk request to {addrs}, for chunk {chunk_id}, {e:?}"
@@ -87,17 +145,25 @@ impl PeerService {
         Ok(store_chunk_response.address)
     }
     #[instrument(name = "service_peer_commit_chunk", skip(self))]
-    pub async fn commit_chunk(&self, chunk_id: &str, addrs: &str, ticket: &str) -> Result<bool> {
+    pub async fn commit_chunk(
+        &self,
+        chunk_id: &str,
+        generation: u64,
+        addrs: &str,
+        datanode_id: &str,
+        ticket: &str,
+    ) -> Result<bool> {
         let response = retry_with_backoff(
             || async {
                 let mut commit_chunk_request = tonic::Request::new(CommitChunkRequest {
                     chunk_id: chunk_id.to_owned(),
+                    generation,
                 });
                 commit_chunk_request
                     .metadata_mut()
                     .insert("ticket", MetadataValue::from_str(ticket)?);
 
-                let mut client = self.get_grpc_connection(addrs).await?;
+                let mut client = self.get_grpc_connection(addrs, datanode_id).await?;
                 client
                     .commit_chunk(commit_chunk_request)
                     .await
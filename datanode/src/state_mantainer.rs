@@ -1,33 +1,60 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
 use futures::future::join_all;
-use storage::{file_storage::FileStorage, storage::Storage};
-use tokio::{sync::Mutex, time::interval};
-use utilities::logger::{Level, error, span, trace};
+use storage::{storage::Storage, volume_set::VolumeSet};
+use tokio::{sync::Mutex, task::JoinHandle, time::interval};
+use utilities::logger::{Level, error, info, span, trace};
 
-use crate::datanode_state::DatanodeState;
+use crate::{config::CONFIG, datanode_state::DatanodeState};
 
 pub struct StateMantainer {
-    store: FileStorage,
+    store: VolumeSet,
     state: Arc<Mutex<DatanodeState>>,
 }
 impl StateMantainer {
-    pub fn new(store: FileStorage, state: Arc<Mutex<DatanodeState>>) -> Self {
+    pub fn new(store: VolumeSet, state: Arc<Mutex<DatanodeState>>) -> Self {
         Self { store, state }
     }
-    pub fn start_sync_loop(self, duration: Duration) {
+    // loop only ends when more volumes failed than tolerated, datanode shuts down then
+    pub fn start_sync_loop(self, duration: Duration) -> JoinHandle<()> {
         tokio::spawn(async move {
             let mut ticker = interval(duration);
             loop {
                 ticker.tick().await;
                 let span = span!(Level::INFO, "datanode_state_sync");
                 let _entered = span.enter();
+                let failed_volumes = self.store.check_volumes().await;
+                // chunks of a failed volume are gone, namenode re-replicates them
+                let lost_chunks = self.store.take_lost_chunks();
+                {
+                    let mut state = self.state.lock().await;
+                    state.volumes = self.store.volume_infos();
+                    for chunk in &lost_chunks {
+                        error!(%chunk, "Chunk lost with failed volume");
+                        state.disk_error();
+                        state.chunk_deleted(chunk);
+                    }
+                }
+                if failed_volumes > CONFIG.failed_volumes_tolerated {
+                    error!(
+                        failed_volumes,
+                        tolerated = CONFIG.failed_volumes_tolerated,
+                        "Too many volumes failed"
+                    );
+                    return;
+                }
+                self.remove_abandoned_writes().await;
                 let available_chunks = match self.store.available_chunks().await {
                     Ok(v) => v,
                     Err(e) => {
                         error!(
                             "Skiping datanode state sync : Error while fetching the available chunks list from store {e}."
                         );
+                        self.state.lock().await.disk_error();
                         continue;
                     }
                 };
@@ -40,20 +67,93 @@ impl StateMantainer {
                         continue;
                     }
                 };
+                let total_storage = match self.store.total_storage() {
+                    Ok(v) => v,
+                    Err(e) => {
+                        error!(
+                            "Skipping datanode state sync: Error while fetching the total storage {e}"
+                        );
+                        continue;
+                    }
+                };
                 let mut state = self.state.lock().await;
                 let to_be_deleted = std::mem::take(&mut state.to_be_deleted_chunks);
-                state.available_chunks = available_chunks
-                    .into_iter()
-                    .filter(|chunk| !to_be_deleted.contains(chunk))
-                    .collect();
+                let mut chunk_generations = HashMap::new();
+                for chunk in available_chunks {
+                    if to_be_deleted.contains(&chunk) {
+                        continue;
+                    }
+                    // generation is only read from store the first time we see a chunk
+                    let generation = match state.available_chunks.get(&chunk) {
+                        Some(generation) => *generation,
+                        None => match self.store.get_generation(&chunk).await {
+                            Ok(generation) => generation,
+                            Err(e) => {
+                                error!(%chunk, "Error while reading generation of chunk {e}");
+                                continue;
+                            }
+                        },
+                    };
+                    chunk_generations.insert(chunk, generation);
+                }
+                state.available_chunks = chunk_generations;
                 state.available_storage = available_storage;
+                state.total_storage = total_storage;
                 drop(state);
                 trace!("Deleting chunks {to_be_deleted:?}");
-                let delete_promise = to_be_deleted
-                    .into_iter()
-                    .map(|chunk| self.store.delete(chunk));
-                join_all(delete_promise).await;
+                let store = &self.store;
+                let delete_promise = to_be_deleted.into_iter().map(|chunk| async move {
+                    let deleted = store.delete(chunk.clone()).await;
+                    (chunk, deleted)
+                });
+                let deleted_chunks = join_all(delete_promise).await;
+                let mut state = self.state.lock().await;
+                for (chunk, deleted) in deleted_chunks {
+                    match deleted {
+                        Ok(true) => state.chunk_deleted(&chunk),
+                        Ok(false) => {}
+                        Err(e) => {
+                            error!(%chunk, "Error while deleting chunk {e}");
+                            state.disk_error();
+                        }
+                    }
+                }
+            }
+        })
+    }
+    // staged chunks nobody wrote to or committed for a while are left by writers which died,
+    // pipeline entries of those writes are dropped with them
+    async fn remove_abandoned_writes(&self) {
+        let timeout = Duration::from_secs(CONFIG.staged_chunk_timeout_secs);
+        let staged = match self.store.staged_chunks().await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Error while listing staged chunks {e}");
+                self.state.lock().await.disk_error();
+                return;
+            }
+        };
+        let mut in_progress = HashSet::new();
+        for (chunk_id, age) in staged {
+            if age <= timeout {
+                in_progress.insert(chunk_id);
+                continue;
+            }
+            match self.store.discard_staged(&chunk_id).await {
+                Ok(_) => info!(%chunk_id, ?age, "Removed abandoned staged chunk"),
+                Err(e) => {
+                    error!(%chunk_id, "Error while removing abandoned staged chunk {e}");
+                    self.state.lock().await.disk_error();
+                }
+            }
+        }
+        let mut state = self.state.lock().await;
+        for chunk_id in state.stale_pipelines(timeout) {
+            if in_progress.contains(&chunk_id) {
+                continue;
             }
-        });
+            trace!(%chunk_id, "Clearing pipeline of abandoned write");
+            state.clear_pipeline(&chunk_id);
+        }
     }
 }
@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use storage::volume_set::VolumeSet;
+use tokio::fs;
+use utilities::{
+    logger::{error, info},
+    result::Result,
+};
+
+/// Identity of the datanode storage, kept in VERSION file in root of every volume.
+/// cluster id is empty till the storage is used to join a cluster for the first time
+#[derive(Clone, Debug)]
+pub struct StorageIdentity {
+    pub cluster_id: String,
+    pub datanode_id: String,
+    pub storage_id: String,
+    // VERSION file of every volume in use
+    paths: Vec<PathBuf>,
+}
+
+impl StorageIdentity {
+    // identity comes from the first volume having one, a volume with a different identity was
+    // taken from another datanode or cluster and is refused. new volumes get the identity written
+    pub async fn load_or_create(volumes: &VolumeSet, datanode_id: &str) -> Result<Self> {
+        let mut identity: Option<Self> = None;
+        let mut paths = vec![];
+        for volume_info in volumes.volume_infos() {
+            if volume_info.failed {
+                continue;
+            }
+            let path = Path::new(&volume_info.root).join("VERSION");
+            let Some(found) = Self::read(&path).await? else {
+                paths.push(path);
+                continue;
+            };
+            match &identity {
+                Some(identity) if !identity.same_as(&found) => {
+                    error!(root = %volume_info.root, storage_id = %found.storage_id, "Volume belongs to another storage, refusing it");
+                    volumes.refuse_volume(&volume_info.root, "Volume belongs to another storage");
+                }
+                Some(_) => paths.push(path),
+                None => {
+                    paths.push(path);
+                    identity = Some(found);
+                }
+            }
+        }
+        let mut identity = match identity {
+            Some(identity) => identity,
+            None => {
+                let storage_id = format!("DS-{}", uuid::Uuid::new_v4());
+                info!(%storage_id, "No storage identity found, creating a new one");
+                Self {
+                    cluster_id: String::new(),
+                    datanode_id: datanode_id.to_owned(),
+                    storage_id,
+                    paths: vec![],
+                }
+            }
+        };
+        identity.paths = paths;
+        // volume added after storage was bound gets the identity right away
+        if !identity.cluster_id.is_empty() {
+            identity.write().await?;
+        }
+        Ok(identity)
+    }
+    async fn read(path: &Path) -> Result<Option<Self>> {
+        if !fs::try_exists(path).await? {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).await?;
+        let get = |key: &str| {
+            content
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+                .map(|value| value.trim().to_owned())
+                .ok_or(format!("{key} missing in version file {path:?}"))
+        };
+        Ok(Some(Self {
+            cluster_id: get("cluster_id")?,
+            datanode_id: get("datanode_id")?,
+            storage_id: get("storage_id")?,
+            paths: vec![],
+        }))
+    }
+    fn same_as(&self, other: &Self) -> bool {
+        self.cluster_id == other.cluster_id
+            && self.datanode_id == other.datanode_id
+            && self.storage_id == other.storage_id
+    }
+    async fn write(&self) -> Result<()> {
+        let content = format!(
+            "cluster_id={}\ndatanode_id={}\nstorage_id={}\n",
+            self.cluster_id, self.datanode_id, self.storage_id
+        );
+        for path in &self.paths {
+            fs::write(path, &content).await?;
+        }
+        Ok(())
+    }
+    // called once namenode accepts us, after this storage can only be used in this cluster
+    pub async fn persist(&mut self, cluster_id: &str) -> Result<()> {
+        self.cluster_id = cluster_id.to_owned();
+        self.write().await
+    }
+}
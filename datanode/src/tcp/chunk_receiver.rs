@@ -0,0 +1,216 @@
+use proto::generated::data_transfer::ChunkChecksums;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use storage::{storage::Storage, volume_set::VolumeSet};
+use tokio::{
+    io::{AsyncWriteExt, DuplexStream, ReadHalf, duplex},
+    sync::{Mutex, mpsc, oneshot},
+};
+use utilities::{
+    data_transfer,
+    logger::{Instrument, error, trace, warn},
+    packet::{Ack, PACKET_SIZE, Packet},
+    result::Result,
+    tls::DataStream,
+};
+
+use crate::datanode_state::DatanodeState;
+
+/// Receives the packets of a chunk write, forwards them to the next replica and stores them.
+/// A packet is acked upstream once it is handed to the store and the next replica acked it, ack
+/// of the last packet waits until the whole chunk is stored and verified. A next replica which
+/// goes away is dropped and the write goes on without it
+pub struct ChunkReceiver {
+    chunk_id: String,
+    chunk_size: u64,
+    offset: u64,
+    store: VolumeSet,
+    state: Arc<Mutex<DatanodeState>>,
+}
+
+impl ChunkReceiver {
+    pub fn new(
+        chunk_id: String,
+        chunk_size: u64,
+        offset: u64,
+        store: VolumeSet,
+        state: Arc<Mutex<DatanodeState>>,
+    ) -> Self {
+        Self {
+            chunk_id,
+            chunk_size,
+            offset,
+            store,
+            state,
+        }
+    }
+    // both streams already agreed on the transfer, each with the version it negotiated
+    pub async fn receive(
+        self,
+        upstream: DataStream,
+        version: u16,
+        downstream: Option<(DataStream, u16)>,
+    ) -> Result<()> {
+        let (mut upstream_read, mut upstream_write) = tokio::io::split(upstream);
+        let (downstream_read, mut downstream_write) = match downstream {
+            Some((downstream, version)) => {
+                let (read, write) = tokio::io::split(downstream);
+                (Some(read), Some((write, version)))
+            }
+            None => (None, None),
+        };
+        let (mut store_tx, store_rx) = duplex(PACKET_SIZE);
+        let (trailer_tx, trailer_rx) = oneshot::channel();
+        let store_failed = Arc::new(AtomicBool::new(false));
+        let store_task = {
+            let store = self.store.clone();
+            let state = self.state.clone();
+            let chunk_id = self.chunk_id.clone();
+            let offset = self.offset;
+            let store_failed = store_failed.clone();
+            tokio::spawn(
+                async move {
+                    let result =
+                        Self::store_chunk(store, state, chunk_id, offset, store_rx, trailer_rx)
+                            .await;
+                    if let Err(e) = &result {
+                        error!("Error while storing the chunk {e}");
+                        store_failed.store(true, Ordering::Relaxed);
+                    }
+                    result
+                }
+                .in_current_span(),
+            )
+        };
+        // seqno of every packet handed to the store, responder acks them in order
+        let (received_tx, mut received_rx) = mpsc::unbounded_channel::<(u64, bool)>();
+        let responder = tokio::spawn(
+            async move {
+                let mut downstream_read = downstream_read;
+                let mut store_task = Some(store_task);
+                while let Some((seqno, last)) = received_rx.recv().await {
+                    let stored = if last {
+                        matches!(store_task.take().unwrap().await, Ok(Ok(_)))
+                    } else {
+                        !store_failed.load(Ordering::Relaxed)
+                    };
+                    let mut statuses = vec![stored];
+                    statuses.extend(Self::downstream_statuses(&mut downstream_read, seqno).await);
+                    Ack { seqno, statuses }
+                        .write_to(&mut upstream_write)
+                        .await?;
+                    if last {
+                        break;
+                    }
+                }
+                upstream_write.flush().await?;
+                Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+            }
+            .in_current_span(),
+        );
+        let mut seqno = 0;
+        let mut offset = self.offset;
+        loop {
+            let packet = Packet::read_from(&mut upstream_read).await?;
+            if packet.seqno != seqno || packet.offset != offset {
+                return Err(format!(
+                    "Got packet {} at offset {}, expected packet {seqno} at offset {offset}",
+                    packet.seqno, packet.offset
+                )
+                .into());
+            }
+            // corrupt packet fails this replica, upstream sees it as gone
+            packet.verify()?;
+            offset += packet.data.len() as u64;
+            if offset > self.chunk_size {
+                return Err(
+                    format!("Packet {seqno} goes past chunk size {}", self.chunk_size).into(),
+                );
+            }
+            if let Some((writer, _)) = &mut downstream_write
+                && let Err(e) = packet.write_to(writer).await
+            {
+                warn!("Next replica is gone, continuing without it {e}");
+                downstream_write = None;
+            }
+            // a failed store is reported through the acks
+            if let Err(e) = store_tx.write_all(&packet.data).await {
+                trace!("Store stream closed {e}");
+            }
+            let _ = received_tx.send((packet.seqno, packet.last));
+            seqno += 1;
+            if packet.last {
+                break;
+            }
+        }
+        // writer sends checksums of the chunk right after its last packet
+        let frame = data_transfer::read_frame(&mut upstream_read).await?;
+        if frame.version != version {
+            return Err(format!(
+                "Trailer framed in version {}, transfer agreed on {version}",
+                frame.version
+            )
+            .into());
+        }
+        let trailer: ChunkChecksums = frame.decode()?;
+        if let Some((writer, version)) = &mut downstream_write
+            && let Err(e) = data_transfer::write_frame(writer, *version, &trailer).await
+        {
+            warn!("Next replica is gone, continuing without it {e}");
+        }
+        let _ = trailer_tx.send(trailer);
+        let _ = store_tx.shutdown().await;
+        drop(store_tx);
+        responder.await?
+    }
+    async fn store_chunk(
+        store: VolumeSet,
+        state: Arc<Mutex<DatanodeState>>,
+        chunk_id: String,
+        offset: u64,
+        mut stream: DuplexStream,
+        trailer: oneshot::Receiver<ChunkChecksums>,
+    ) -> Result<u64> {
+        let bytes_written = match store.write(chunk_id.clone(), offset, &mut stream).await {
+            Ok(bytes_written) => bytes_written,
+            Err(e) => {
+                state.lock().await.disk_error();
+                return Err(e);
+            }
+        };
+        trace!("{} bytes written to file", bytes_written);
+        let trailer = trailer.await.map_err(|_| "Checksum trailer not received")?;
+        store
+            .verify_checksums(&chunk_id, &trailer.checksums)
+            .await?;
+        Ok(bytes_written)
+    }
+    // statuses of the rest of the pipeline for the packet, none once next replica is gone
+    async fn downstream_statuses(
+        downstream_read: &mut Option<ReadHalf<DataStream>>,
+        seqno: u64,
+    ) -> Vec<bool> {
+        let Some(reader) = downstream_read else {
+            return vec![];
+        };
+        match Ack::read_from(reader).await {
+            Ok(ack) if ack.seqno == seqno => ack.statuses,
+            Ok(ack) => {
+                error!(
+                    expected = seqno,
+                    got = ack.seqno,
+                    "Next replica acked out of order, dropping it"
+                );
+                *downstream_read = None;
+                vec![]
+            }
+            Err(e) => {
+                warn!("Next replica stopped acking, dropping it {e}");
+                *downstream_read = None;
+                vec![]
+            }
+        }
+    }
+}
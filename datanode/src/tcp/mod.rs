@@ -1,2 +1,2 @@
+pub mod chunk_receiver;
 pub mod service;
-pub mod stream_tee;
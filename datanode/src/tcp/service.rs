@@ -1,39 +1,48 @@
+use proto::generated::data_transfer::{TransferMode, TransferRequest, TransferStatus};
 use std::sync::Arc;
-use storage::{file_storage::FileStorage, storage::Storage};
+use storage::{storage::Storage, volume_set::VolumeSet};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, copy},
+    io::{AsyncWriteExt, copy},
     net::{TcpListener, TcpStream},
     sync::Mutex,
 };
 use utilities::{
-    data_packet::DataPacket,
+    checksum::VerifyingReader,
+    data_transfer,
     logger::{Instrument, Level, Span, error, span, trace},
     result::Result,
-    ticket::{ticket_decrypter::TicketDecrypter, types::Operation},
+    ticket::{
+        ticket_validator::{TicketError, TicketValidator},
+        types::Operation,
+    },
+    tls::{DataAcceptor, DataStream},
 };
 
-use crate::{config::CONFIG, datanode_state::DatanodeState, tcp::stream_tee};
+use crate::{config::CONFIG, datanode_state::DatanodeState, tcp::chunk_receiver::ChunkReceiver};
 
 pub struct TCPService {
     listener: TcpListener,
-    store: FileStorage,
+    store: VolumeSet,
     state: Arc<Mutex<DatanodeState>>,
-    ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+    ticket_validator: Arc<TicketValidator>,
+    acceptor: DataAcceptor,
 }
 
 impl TCPService {
     pub async fn new(
         address: String,
-        store: FileStorage,
+        store: VolumeSet,
         state: Arc<Mutex<DatanodeState>>,
-        ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+        ticket_validator: Arc<TicketValidator>,
+        acceptor: DataAcceptor,
     ) -> Result<Self> {
         let listener = TcpListener::bind(address).await?;
         Ok(TCPService {
             listener,
             store,
             state,
-            ticket_decrypter,
+            ticket_validator,
+            acceptor,
         })
     }
     pub async fn start_and_accept(&self) -> Result<()> {
@@ -41,12 +50,19 @@ impl TCPService {
             let (tcp_stream, _) = self.listener.accept().await?;
             let store = self.store.clone();
             let state = self.state.clone();
-            let ticket_decrypter = self.ticket_decrypter.clone();
+            let ticket_validator = self.ticket_validator.clone();
+            let acceptor = self.acceptor.clone();
             let span = Span::current();
             tokio::spawn(
                 async move {
-                    if let Err(e) =
-                        Self::handle_connection(tcp_stream, store, state, ticket_decrypter).await
+                    if let Err(e) = Self::handle_connection(
+                        tcp_stream,
+                        acceptor,
+                        store,
+                        state,
+                        ticket_validator,
+                    )
+                    .await
                     {
                         error!("error while handling the tcp connection {e}");
                     }
@@ -56,19 +72,54 @@ impl TCPService {
         }
     }
     async fn handle_connection(
-        mut tcp_stream: TcpStream,
-        store: FileStorage,
+        tcp_stream: TcpStream,
+        acceptor: DataAcceptor,
+        store: VolumeSet,
         state: Arc<Mutex<DatanodeState>>,
-        ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+        ticket_validator: Arc<TicketValidator>,
     ) -> Result<()> {
-        // first we will fetch the chunk_id
-        let headers = DataPacket::decode(&mut tcp_stream).await?;
-        let ticket = ticket_decrypter.decrypt_server_ticket(headers.get("ticket")?)?;
-        let chunk_id = headers.get("chunk_id")?.to_string();
+        let mut tcp_stream = acceptor.accept(tcp_stream).await?;
+        // peers speaking a version we don't support are told so and dropped
+        let (version, request) = data_transfer::accept_transfer(&mut tcp_stream).await?;
+        // reported to namenode in heartbeat so it can spread reads and writes
+        let write = request.mode() == TransferMode::Write;
+        state.lock().await.transfer_started(write);
+        let result = Self::handle_transfer(
+            tcp_stream,
+            version,
+            request,
+            store,
+            state.clone(),
+            ticket_validator,
+        )
+        .await;
+        state.lock().await.transfer_finished(write);
+        result
+    }
+    async fn handle_transfer(
+        mut tcp_stream: DataStream,
+        version: u16,
+        request: TransferRequest,
+        store: VolumeSet,
+        state: Arc<Mutex<DatanodeState>>,
+        ticket_validator: Arc<TicketValidator>,
+    ) -> Result<()> {
+        let ticket = match ticket_validator.validate(&request.ticket) {
+            Ok(ticket) => ticket,
+            Err(e) => {
+                let status = match e {
+                    TicketError::Invalid(_) => TransferStatus::Rejected,
+                    TicketError::Expired => TransferStatus::TicketExpired,
+                    TicketError::Replayed => TransferStatus::TicketReplayed,
+                };
+                Self::reject(&mut tcp_stream, version, status, &e.to_string()).await;
+                return Err(e.into());
+            }
+        };
+        let chunk_id = request.chunk_id.clone();
         trace!(%chunk_id,"Got TCP stream {:?}",ticket);
-        // now we will fetch the mode (client wants to read or write)
-        let mode = headers.get("mode")?;
-        if mode == "Write" {
+        let mode = request.mode();
+        if mode == TransferMode::Write {
             let is_valid_ticket = match ticket.operation {
                 Operation::StoreChunk {
                     chunk_id: ticket_chunk_id,
@@ -81,104 +132,66 @@ impl TCPService {
             };
 
             if !is_valid_ticket {
+                Self::reject(
+                    &mut tcp_stream,
+                    version,
+                    TransferStatus::Rejected,
+                    "Ticket provided is not valid",
+                )
+                .await;
                 return Err("Ticket provided is not valid".into());
             }
             let span = span!(Level::INFO,"service_tcp_write_chunk",%chunk_id);
             let _gaurd = span.enter();
             trace!(%chunk_id,"Mode set to write");
-            let chunk_size: u64 = headers.get("chunk_size")?.parse()?;
-            trace!(%chunk_size,"Bytes to be written from the chunk");
-            let (read_stream, mut write_stream) = tcp_stream.into_split();
-            let mut limited_read_stream = read_stream.take(chunk_size);
-            // read a file from the
+            let chunk_size = request.chunk_size;
+            // writer resumes from what the pipeline has staged after a failure
+            let offset = request.offset;
+            if offset > chunk_size {
+                let error = format!("Offset {offset} is past chunk size {chunk_size}");
+                Self::reject(&mut tcp_stream, version, TransferStatus::Rejected, &error).await;
+                return Err(error.into());
+            }
+            trace!(%chunk_size, %offset, "Bytes to be written from the chunk");
             // after reading the chunk_id and mode  we will post that details to the pipeline
             let pipeline_options = {
                 let mut state_lock = state.lock().await;
                 state_lock.chunk_to_pipline.remove(&chunk_id)
             };
-            if let Some(mut pipeline) = pipeline_options {
-                // if there is pipeline there will be definately a ticket
-                let ticket = {
-                    let state_lock = state.lock().await;
-                    state_lock
-                        .chunk_to_namenode_store_ticket
-                        .get(&chunk_id)
-                        .unwrap()
-                        .to_string()
-                };
-                // create tee only if you need one otherwise 2nd stream will not be consumed and
-                // program will be in lockin
-                let (mut stream1, mut stream2) =
-                    stream_tee::tee_tcp_stream(limited_read_stream, write_stream);
-                let mut pipeline_headers = DataPacket::new();
-                pipeline_headers.insert("chunk_id".to_string(), chunk_id.clone());
-                pipeline_headers.insert("mode".to_string(), "Write".to_string());
-                pipeline_headers.insert("ticket".to_string(), ticket);
-                pipeline_headers.insert("chunk_size".to_string(), chunk_size.to_string());
-                tokio::io::copy(&mut pipeline_headers.encode(), &mut pipeline).await?;
-
-                // faced issue when not running below task parrally because if we do one by one
-                // after first finish tx1 and tx2 both will be dropped which will hang state when
-                // fetching data from other stream. :)
-                tokio::spawn(
-                    async move {
-                        let bytes_received = match copy(&mut stream1, &mut pipeline).await {
-                            Ok(_) => match DataPacket::decode(&mut pipeline).await {
-                                Ok(v) => v
-                                    .get("bytes_received")
-                                    .unwrap_or("0")
-                                    .parse()
-                                    .unwrap_or(0_u64),
-                                Err(_e) => 0,
-                            },
-                            Err(e) => {
-                                error!("Error while sending data to pipeline {e}");
-                                0
-                            }
-                        };
-                        stream1.write_u64(bytes_received).await;
-                    }
-                    .in_current_span(),
-                );
-                tokio::spawn(
-                    async move {
-                        match store.write(chunk_id.clone(), &mut stream2).await {
-                            Ok(bytes_written) => {
-                                let _ = stream2.write_u64(bytes_written).await;
-                            }
-                            Err(e) => {
-                                error!("Error while writing data to store {e}");
-                                let _ = stream2.write_u64(0).await;
-                            }
+            let downstream = match pipeline_options {
+                Some(mut pipeline) => {
+                    // if there is pipeline there will be definately a ticket
+                    let ticket = {
+                        let state_lock = state.lock().await;
+                        state_lock
+                            .chunk_to_namenode_store_ticket
+                            .get(&chunk_id)
+                            .unwrap()
+                            .to_string()
+                    };
+                    let pipeline_request = TransferRequest {
+                        mode: TransferMode::Write as i32,
+                        chunk_id: chunk_id.clone(),
+                        ticket,
+                        chunk_size,
+                        offset,
+                    };
+                    match data_transfer::open_transfer(&mut pipeline, &pipeline_request).await {
+                        Ok(response) => Some((pipeline, response.version as u16)),
+                        Err(e) => {
+                            error!("Next replica is gone, writing without it {e}");
+                            None
                         }
                     }
-                    .in_current_span(),
-                );
-            } else {
-                let written_bytes = match store.write(chunk_id, &mut limited_read_stream).await {
-                    Ok(bytes_written_to_file) => {
-                        trace!("{} bytes written to file", bytes_written_to_file);
-                        bytes_written_to_file
-                    }
-                    Err(e) => {
-                        error!("Error while storing the chunk");
-                        error!("{}", e);
-                        0
-                    }
-                };
-                let mut reply_packet = DataPacket::new();
-                reply_packet.insert("bytes_received".to_string(), written_bytes.to_string());
-                let mut reply_packet_stream = reply_packet.encode();
-                let _ = tokio::io::copy(&mut reply_packet_stream, &mut write_stream).await;
-                match write_stream.flush().await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        error!("Error while flushing the written bytes to client");
-                        error!("{}", e);
-                    }
                 }
-            }
-        } else if mode == "Read" {
+                None => None,
+            };
+            data_transfer::accept(&mut tcp_stream, version, None).await?;
+            // staged part of a failed write is kept, writer resumes from it
+            ChunkReceiver::new(chunk_id, chunk_size, offset, store, state)
+                .receive(tcp_stream, version, downstream)
+                .await?;
+        } else if mode == TransferMode::Read {
             let is_valid_ticket = match ticket.operation {
                 Operation::FetchChunk {
                     chunk_id: ticket_chunk_id,
@@ -188,6 +201,13 @@ impl TCPService {
 
             if !is_valid_ticket {
                 trace!("Ticket provided is not valid");
+                Self::reject(
+                    &mut tcp_stream,
+                    version,
+                    TransferStatus::Rejected,
+                    "Ticket provided is not valid",
+                )
+                .await;
                 return Err("Ticket provided is not valid".into());
             }
 
@@ -196,14 +216,68 @@ impl TCPService {
             trace!(%chunk_id,"Mode set to read");
 
             //if file is already present just delete it (for future)
-            let reader = store.read(chunk_id).await?;
-            copy(&mut reader.take(u64::MAX), &mut tcp_stream).await?;
+            let reader = match store.read(chunk_id.clone()).await {
+                Ok(reader) => reader,
+                Err(e) => {
+                    // we think we have the chunk but can't read it so namenode should repair it
+                    let mut state = state.lock().await;
+                    state.disk_error();
+                    if state.available_chunks.contains_key(&chunk_id) {
+                        error!(%chunk_id, "Reporting unreadable chunk as corrupt {e}");
+                        state.chunk_corrupt(&chunk_id);
+                    }
+                    drop(state);
+                    Self::reject(
+                        &mut tcp_stream,
+                        version,
+                        TransferStatus::Rejected,
+                        "Chunk can't be read",
+                    )
+                    .await;
+                    return Err(e);
+                }
+            };
+            let checksums = match store.get_checksums(&chunk_id).await {
+                Ok(checksums) => checksums,
+                Err(e) => {
+                    state.lock().await.disk_error();
+                    Self::reject(
+                        &mut tcp_stream,
+                        version,
+                        TransferStatus::Rejected,
+                        "Chunk can't be read",
+                    )
+                    .await;
+                    return Err(e);
+                }
+            };
+            // checksums go first so client can verify the content as well
+            data_transfer::accept(&mut tcp_stream, version, checksums.clone()).await?;
+            let mut verifying_reader = VerifyingReader::new(reader, checksums);
+            if let Err(e) = copy(&mut verifying_reader, &mut tcp_stream).await {
+                if e.kind() == std::io::ErrorKind::InvalidData {
+                    error!(%chunk_id, "Reporting chunk as corrupt {e}");
+                    state.lock().await.chunk_corrupt(&chunk_id);
+                }
+                return Err(e.into());
+            }
             tcp_stream.flush().await?;
         } else {
-            return Err(
-                format!("accepted request for chunk id {chunk_id} for unknown mode",).into(),
-            );
+            let error = format!("accepted request for chunk id {chunk_id} for unknown mode");
+            Self::reject(&mut tcp_stream, version, TransferStatus::Rejected, &error).await;
+            return Err(error.into());
         }
         Ok(())
     }
+    // peer is told why before the connection goes away, it may already be gone
+    async fn reject(
+        tcp_stream: &mut DataStream,
+        version: u16,
+        status: TransferStatus,
+        error: &str,
+    ) {
+        if let Err(e) = data_transfer::reject(tcp_stream, version, status, error).await {
+            trace!("Could not send rejection {e}");
+        }
+    }
 }
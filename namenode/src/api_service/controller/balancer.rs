@@ -0,0 +1,47 @@
+use crate::{
+    api_service::middleware::auth::NodeMetadataWrapper,
+    balancer::{Balancer, BalancerStatus},
+};
+use rocket::{State, get, post, serde::json::Json};
+use serde::Serialize;
+use std::collections::HashMap;
+use utilities::logger::info;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct StartBalancerResponse {
+    started: bool,
+    message: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BalancerStatusResponse {
+    status: BalancerStatus,
+    // utilization of every datanode in percent
+    utilization: HashMap<String, f64>,
+}
+
+#[post("/start")]
+pub async fn start_balancer(
+    node_meta: NodeMetadataWrapper,
+    balancer: &State<Balancer>,
+) -> Json<StartBalancerResponse> {
+    let started = balancer.trigger().await;
+    info!(requested_by = %node_meta.0.id, %started, "Balancer run requested");
+    let message = if started {
+        "Balancer started".to_string()
+    } else {
+        "Balancer is already running".to_string()
+    };
+    Json(StartBalancerResponse { started, message })
+}
+
+#[get("/status")]
+pub async fn get_balancer_status(
+    _node_meta: NodeMetadataWrapper,
+    balancer: &State<Balancer>,
+) -> Json<BalancerStatusResponse> {
+    Json(BalancerStatusResponse {
+        status: balancer.status().await,
+        utilization: balancer.utilization().await,
+    })
+}
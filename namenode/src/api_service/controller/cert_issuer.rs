@@ -7,12 +7,19 @@ use rocket::{State, get, post, response::status, serde::json::Json};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use utilities::{auth::types::NodeType, logger::error, ticket::ticket_mint::TicketMint};
+use utilities::{
+    auth::types::NodeType,
+    logger::{error, info},
+    ticket::ticket_mint::TicketMint,
+};
 
 #[derive(Clone, Debug, Serialize)]
 pub struct IssueCertifcateResponse {
     cert: String,
     key: String,
+    // private key of cert and certificate of the CA, used for TLS on the data plane
+    cert_key: String,
+    root_cert: String,
 }
 #[derive(Clone, Debug, Serialize)]
 pub struct IssueCertifcateErrorResponse {
@@ -33,7 +40,7 @@ pub async fn issue_cert(
     tm: &State<Arc<Mutex<TicketMint>>>,
     ledger: &State<Box<dyn Ledger + Send + Sync>>,
 ) -> Result<Json<IssueCertifcateResponse>, status::Custom<Json<IssueCertifcateErrorResponse>>> {
-    let (cert, _key_pair) = match ca.issue_certificate(req.node_id.clone(), req.node_type.clone()) {
+    let (cert, key_pair) = match ca.issue_certificate(req.node_id.clone(), req.node_type.clone()) {
         Ok(v) => v,
         Err(e) => {
             error!("Erorr while generating the certifcate: {}", e);
@@ -58,10 +65,14 @@ pub async fn issue_cert(
             ));
         }
     };
-    ledger.generate_key(&node_meta.0.id, &key).await;
+    // key belongs to the node the certificate is issued for, not to the caller
+    ledger.generate_key(&req.node_id, &key).await;
+    info!(node_id = %req.node_id, requested_by = %node_meta.0.id, "Issued node certificate");
     Ok(Json(IssueCertifcateResponse {
         cert: BASE64_STANDARD.encode(cert.der()),
         key,
+        cert_key: BASE64_STANDARD.encode(key_pair.serialize_der()),
+        root_cert: BASE64_STANDARD.encode(ca.get_root_cert().der()),
     }))
 }
 
@@ -0,0 +1,259 @@
+use crate::{
+    api_service::middleware::auth::NodeMetadataWrapper,
+    config::CONFIG,
+    ledger::default_ledger::Ledger,
+    namenode_state::{NamenodeState, datanode_details::ScanDetail},
+};
+use proto::generated::datanode_namenode::{
+    ReregisterCommand, ShutdownCommand, datanode_command::Command,
+};
+use rocket::{State, delete, get, post, response::status, serde::json::Json};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use utilities::{
+    logger::{error, info},
+    ticket::ticket_mint::TicketMint,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    datanode_id: String,
+    duration_secs: Option<u64>,
+}
+#[derive(Clone, Debug, Serialize)]
+pub struct MaintenanceResponse {
+    datanode_id: String,
+    in_maintenance: bool,
+    remaining_secs: u64,
+}
+#[derive(Clone, Debug, Serialize)]
+pub struct DecommissionResponse {
+    datanode_id: String,
+    state: String,
+}
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandResponse {
+    datanode_id: String,
+    // none when same command is already waiting for the datanode
+    command_id: Option<u64>,
+}
+#[derive(Clone, Debug, Serialize)]
+pub struct ScanResponse {
+    datanode_id: String,
+    progress: ScanDetail,
+    // chunk id to unix secs the replica was last verified by block scanner
+    last_verified: HashMap<String, u64>,
+}
+#[derive(Clone, Debug, Serialize)]
+pub struct RotateKeyResponse {
+    datanode_id: String,
+    // tickets are encrypted with it once datanode reports it has fetched it
+    key_id: u32,
+}
+#[derive(Clone, Debug, Serialize)]
+pub struct DatanodeErrorResponse {
+    message: String,
+}
+
+fn datanode_not_found(datanode_id: &str) -> status::Custom<Json<DatanodeErrorResponse>> {
+    status::Custom(
+        rocket::http::Status::NotFound,
+        Json(DatanodeErrorResponse {
+            message: format!("Datanode {datanode_id} is not registered with namenode"),
+        }),
+    )
+}
+
+#[post("/maintenance", data = "<req>")]
+pub async fn start_maintenance(
+    req: Json<MaintenanceRequest>,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<MaintenanceResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    let duration = Duration::from_secs(
+        req.duration_secs
+            .unwrap_or(CONFIG.maintenance_duration_secs),
+    );
+    let mut state = state.lock().await;
+    let datanode_details = match state.datanode_to_detail_map.get_mut(&req.datanode_id) {
+        Some(v) => v,
+        None => return Err(datanode_not_found(&req.datanode_id)),
+    };
+    if let Err(message) = datanode_details.start_maintenance(duration) {
+        error!(datanode_id = %req.datanode_id, "Refusing maintenance, {message}");
+        return Err(status::Custom(
+            rocket::http::Status::Conflict,
+            Json(DatanodeErrorResponse { message }),
+        ));
+    }
+    info!(datanode_id = %req.datanode_id, requested_by = %node_meta.0.id, ?duration, "Datanode entered maintenance");
+    Ok(Json(MaintenanceResponse {
+        datanode_id: req.datanode_id.clone(),
+        in_maintenance: true,
+        remaining_secs: duration.as_secs(),
+    }))
+}
+
+#[delete("/maintenance/<datanode_id>")]
+pub async fn end_maintenance(
+    datanode_id: &str,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<MaintenanceResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    let mut state = state.lock().await;
+    let datanode_details = match state.datanode_to_detail_map.get_mut(datanode_id) {
+        Some(v) => v,
+        None => return Err(datanode_not_found(datanode_id)),
+    };
+    datanode_details.end_maintenance();
+    info!(%datanode_id, requested_by = %node_meta.0.id, "Datanode maintenance ended");
+    Ok(Json(MaintenanceResponse {
+        datanode_id: datanode_id.to_string(),
+        in_maintenance: false,
+        remaining_secs: 0,
+    }))
+}
+
+#[post("/decommission/<datanode_id>")]
+pub async fn decommission(
+    datanode_id: &str,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<DecommissionResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    let mut state = state.lock().await;
+    let datanode_details = match state.datanode_to_detail_map.get_mut(datanode_id) {
+        Some(v) => v,
+        None => return Err(datanode_not_found(datanode_id)),
+    };
+    info!(%datanode_id, requested_by = %node_meta.0.id, from = datanode_details.state.name(), "Datanode decommissioning");
+    datanode_details.decommission();
+    Ok(Json(DecommissionResponse {
+        datanode_id: datanode_id.to_string(),
+        state: datanode_details.state.name().to_owned(),
+    }))
+}
+
+#[delete("/decommission/<datanode_id>")]
+pub async fn recommission(
+    datanode_id: &str,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<DecommissionResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    let mut state = state.lock().await;
+    let datanode_details = match state.datanode_to_detail_map.get_mut(datanode_id) {
+        Some(v) => v,
+        None => return Err(datanode_not_found(datanode_id)),
+    };
+    // node has to heartbeat again before it is used
+    datanode_details.register();
+    info!(%datanode_id, requested_by = %node_meta.0.id, "Datanode recommissioned");
+    Ok(Json(DecommissionResponse {
+        datanode_id: datanode_id.to_string(),
+        state: datanode_details.state.name().to_owned(),
+    }))
+}
+
+// command is delivered with the next heartbeat response of the datanode
+async fn queue_command(
+    datanode_id: &str,
+    command: Command,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<CommandResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    let mut state = state.lock().await;
+    let datanode_details = match state.datanode_to_detail_map.get_mut(datanode_id) {
+        Some(v) => v,
+        None => return Err(datanode_not_found(datanode_id)),
+    };
+    Ok(Json(CommandResponse {
+        datanode_id: datanode_id.to_string(),
+        command_id: datanode_details.commands.push(command),
+    }))
+}
+
+#[post("/reregister/<datanode_id>")]
+pub async fn reregister(
+    datanode_id: &str,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<CommandResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    info!(%datanode_id, requested_by = %node_meta.0.id, "Asking datanode to register again");
+    queue_command(
+        datanode_id,
+        Command::Reregister(ReregisterCommand {}),
+        state,
+    )
+    .await
+}
+
+#[post("/shutdown/<datanode_id>")]
+pub async fn shutdown(
+    datanode_id: &str,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<CommandResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    info!(%datanode_id, requested_by = %node_meta.0.id, "Asking datanode to shut down");
+    let command = Command::Shutdown(ShutdownCommand {
+        reason: format!("Requested by {}", node_meta.0.id),
+    });
+    queue_command(datanode_id, command, state).await
+}
+
+#[get("/scan/<datanode_id>")]
+pub async fn scan_progress(
+    datanode_id: &str,
+    _node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+) -> Result<Json<ScanResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    let state = state.lock().await;
+    let datanode_details = match state.datanode_to_detail_map.get(datanode_id) {
+        Some(v) => v,
+        None => return Err(datanode_not_found(datanode_id)),
+    };
+    Ok(Json(ScanResponse {
+        datanode_id: datanode_id.to_string(),
+        progress: datanode_details.scan_progress.clone(),
+        last_verified: datanode_details.chunk_last_verified.clone(),
+    }))
+}
+
+// issues a new ticket key, datanode fetches it after its next heartbeat and the old key is
+// accepted for key_grace_secs after that
+#[post("/rotate-key/<datanode_id>")]
+pub async fn rotate_key(
+    datanode_id: &str,
+    node_meta: NodeMetadataWrapper,
+    state: &State<Arc<Mutex<NamenodeState>>>,
+    tm: &State<Arc<Mutex<TicketMint>>>,
+    ledger: &State<Box<dyn Ledger + Send + Sync>>,
+) -> Result<Json<RotateKeyResponse>, status::Custom<Json<DatanodeErrorResponse>>> {
+    if !state
+        .lock()
+        .await
+        .datanode_to_detail_map
+        .contains_key(datanode_id)
+    {
+        return Err(datanode_not_found(datanode_id));
+    }
+    let mut tm = tm.lock().await;
+    let (key_id, key) = match tm.rotate_node_key(datanode_id) {
+        Ok(v) => v,
+        Err(e) => {
+            error!(%datanode_id, "Error while rotating datanode key {e}");
+            return Err(status::Custom(
+                rocket::http::Status::Conflict,
+                Json(DatanodeErrorResponse {
+                    message: e.to_string(),
+                }),
+            ));
+        }
+    };
+    // recorded before the mint is released so rotations reach the ledger in order
+    ledger.rotate_key(datanode_id, key_id, &key).await;
+    drop(tm);
+    info!(%datanode_id, key_id, requested_by = %node_meta.0.id, "Datanode key rotated");
+    Ok(Json(RotateKeyResponse {
+        datanode_id: datanode_id.to_string(),
+        key_id,
+    }))
+}
@@ -1,3 +1,5 @@
 pub mod auth;
+pub mod balancer;
 pub mod cert_issuer;
+pub mod datanode;
 pub mod monitoring;
@@ -5,11 +5,12 @@ pub mod middleware;
 pub mod routes;
 
 use crate::{
-    api_service::routes::{auth, cert_isssuer, monitoring},
+    api_service::routes::{auth, balancer, cert_isssuer, datanode, monitoring},
+    balancer::Balancer,
     certificates::certificate_generator::CertificateAuthority,
     config::CONFIG,
     ledger::default_ledger::Ledger,
-    namenode_state::state_snapshot::SnapshotStore,
+    namenode_state::{NamenodeState, state_snapshot::SnapshotStore},
 };
 use rocket_cors::CorsOptions;
 use std::sync::Arc;
@@ -21,6 +22,8 @@ pub fn rocket(
     ca: Arc<CertificateAuthority>,
     ticket_mint: Arc<Mutex<TicketMint>>,
     ledger: Box<dyn Ledger + Send + Sync>,
+    namenode_state: Arc<Mutex<NamenodeState>>,
+    balancer: Balancer,
 ) -> Rocket<Build> {
     let cors = CorsOptions::default()
         .to_cors()
@@ -42,9 +45,13 @@ pub fn rocket(
         .manage(ca)
         .manage(ticket_mint)
         .manage(ledger)
+        .manage(namenode_state)
+        .manage(balancer)
         .mount("/monitoring", monitoring::routes())
         .mount("/auth", auth::routes())
         .mount("/cert", cert_isssuer::routes())
+        .mount("/datanode", datanode::routes())
+        .mount("/balancer", balancer::routes())
         .attach(cors)
         .attach(AdHoc::on_ignite("Monitoring Controller", |rocket| async {
             rocket
@@ -0,0 +1,6 @@
+use crate::api_service::controller::balancer::{get_balancer_status, start_balancer};
+use rocket::{Route, routes};
+
+pub fn routes() -> Vec<Route> {
+    routes![start_balancer, get_balancer_status]
+}
@@ -0,0 +1,18 @@
+use crate::api_service::controller::datanode::{
+    decommission, end_maintenance, recommission, reregister, rotate_key, scan_progress, shutdown,
+    start_maintenance,
+};
+use rocket::{Route, routes};
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        start_maintenance,
+        end_maintenance,
+        decommission,
+        recommission,
+        reregister,
+        shutdown,
+        scan_progress,
+        rotate_key
+    ]
+}
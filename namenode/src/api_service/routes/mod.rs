@@ -1,3 +1,5 @@
 pub mod auth;
+pub mod balancer;
 pub mod cert_isssuer;
+pub mod datanode;
 pub mod monitoring;
@@ -0,0 +1,412 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use proto::generated::client_namenode::DataNodeMeta;
+use serde::Serialize;
+use tokio::{
+    sync::Mutex,
+    time::{interval, sleep},
+};
+use utilities::{
+    logger::{Instrument, Level, error, info, instrument, span, trace, tracing},
+    result::Result,
+    ticket::ticket_mint::TicketMint,
+};
+
+use crate::{
+    config::CONFIG,
+    datanode::service::DatanodeService,
+    namenode_state::{
+        NamenodeState,
+        chunk_details::{ChunkReplicationStatus, ChunkState},
+    },
+};
+
+#[derive(Clone, Debug)]
+struct ChunkMove {
+    chunk_id: String,
+    size: u64,
+    source: DataNodeMeta,
+    target: DataNodeMeta,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BalancerReport {
+    pub started_at: Option<SystemTime>,
+    pub finished_at: Option<SystemTime>,
+    pub chunks_moved: u64,
+    pub bytes_moved: u64,
+    pub failed_moves: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BalancerStatus {
+    pub running: bool,
+    pub last_run: Option<BalancerReport>,
+}
+
+/// Moves chunks from over utilized datanodes to under utilized ones
+#[derive(Clone)]
+pub struct Balancer {
+    namenode_state: Arc<Mutex<NamenodeState>>,
+    datanode_service: DatanodeService,
+    status: Arc<Mutex<BalancerStatus>>,
+    // in percentage points of utilization
+    threshold: f64,
+    bandwidth_bytes_per_sec: u64,
+}
+
+impl Balancer {
+    pub fn new(
+        namenode_state: Arc<Mutex<NamenodeState>>,
+        ticket_mint: Arc<Mutex<TicketMint>>,
+    ) -> Self {
+        Self {
+            datanode_service: DatanodeService::new(namenode_state.clone(), ticket_mint),
+            namenode_state,
+            status: Arc::default(),
+            threshold: CONFIG.balancer_threshold,
+            bandwidth_bytes_per_sec: CONFIG.balancer_bandwidth_bytes_per_sec.max(1),
+        }
+    }
+    // runs balancer periodically, used when balancer is configured to run continuously
+    pub fn start(&self, duration: Duration) {
+        let balancer = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(duration);
+            loop {
+                ticker.tick().await;
+                balancer.run().await;
+            }
+        });
+    }
+    // fire and forget, returns false if balancer is already running
+    pub async fn trigger(&self) -> bool {
+        if self.status.lock().await.running {
+            return false;
+        }
+        let balancer = self.clone();
+        tokio::spawn(async move { balancer.run().await }.in_current_span());
+        true
+    }
+    pub async fn status(&self) -> BalancerStatus {
+        self.status.lock().await.clone()
+    }
+    pub async fn utilization(&self) -> HashMap<String, f64> {
+        let state = self.namenode_state.lock().await;
+        state
+            .datanode_to_detail_map
+            .iter()
+            .filter_map(|(datanode_id, datanode_details)| {
+                datanode_details
+                    .utilization()
+                    .map(|utilization| (datanode_id.to_owned(), utilization * 100.0))
+            })
+            .collect()
+    }
+    async fn run(&self) {
+        {
+            let mut status = self.status.lock().await;
+            if status.running {
+                return;
+            }
+            status.running = true;
+        }
+        let span = span!(Level::INFO, "namenode_balancer_run");
+        let report = self.balance().instrument(span).await;
+        let mut status = self.status.lock().await;
+        status.running = false;
+        status.last_run = Some(report);
+    }
+    async fn balance(&self) -> BalancerReport {
+        let mut report = BalancerReport {
+            started_at: Some(SystemTime::now()),
+            ..BalancerReport::default()
+        };
+        let moves = {
+            let state = self.namenode_state.lock().await;
+            self.plan_moves(&state)
+        };
+        info!(moves = moves.len(), "Planned chunk moves for balancing");
+        for chunk_move in moves {
+            let started = Instant::now();
+            match self.move_chunk(&chunk_move).await {
+                Ok(()) => {
+                    report.chunks_moved += 1;
+                    report.bytes_moved += chunk_move.size;
+                }
+                Err(e) => {
+                    error!(chunk_id = %chunk_move.chunk_id, "Error while moving chunk {e}");
+                    report.failed_moves += 1;
+                }
+            }
+            // throttling so that balancing traffic stays under configured bandwidth
+            let allowed = Duration::from_secs_f64(
+                chunk_move.size as f64 / self.bandwidth_bytes_per_sec as f64,
+            );
+            if let Some(remaining) = allowed.checked_sub(started.elapsed()) {
+                sleep(remaining).await;
+            }
+        }
+        report.finished_at = Some(SystemTime::now());
+        info!(?report, "Balancer run finished");
+        report
+    }
+    fn plan_moves(&self, state: &NamenodeState) -> Vec<ChunkMove> {
+        // (used, capacity) of every node which can take part in balancing
+        let mut usage: HashMap<&str, (u64, u64)> = state
+            .datanode_to_detail_map
+            .iter()
+            .filter(|(_, datanode_details)| {
+                datanode_details.is_active() && datanode_details.storage_capacity > 0
+            })
+            .map(|(datanode_id, datanode_details)| {
+                let used = datanode_details
+                    .storage_capacity
+                    .saturating_sub(datanode_details.storage_remaining);
+                (
+                    datanode_id.as_str(),
+                    (used, datanode_details.storage_capacity),
+                )
+            })
+            .collect();
+        if usage.len() < 2 {
+            return vec![];
+        }
+        let total_used: u64 = usage.values().map(|(used, _)| used).sum();
+        let total_capacity: u64 = usage.values().map(|(_, capacity)| capacity).sum();
+        let average = total_used as f64 / total_capacity as f64;
+        let threshold = self.threshold / 100.0;
+        let utilization = |(used, capacity): (u64, u64)| used as f64 / capacity as f64;
+
+        let mut sources: Vec<&str> = usage
+            .iter()
+            .filter(|(_, value)| utilization(**value) > average)
+            .map(|(datanode_id, _)| *datanode_id)
+            .collect();
+        sources.sort_by(|a, b| utilization(usage[b]).total_cmp(&utilization(usage[a])));
+        let targets: Vec<&str> = usage
+            .iter()
+            .filter(|(_, value)| utilization(**value) < average)
+            .map(|(datanode_id, _)| *datanode_id)
+            .collect();
+        trace!(average, ?sources, ?targets, "Balancer candidates");
+
+        let mut moves = vec![];
+        let mut planned_chunks = HashSet::new();
+        for source in sources {
+            for (chunk_id, chunk_details) in &state.chunk_id_to_detail_map {
+                let source_utilization = utilization(usage[source]);
+                if source_utilization <= average {
+                    break;
+                }
+                if chunk_details.state != ChunkState::Commited
+                    || !chunk_details.locations.contains(source)
+                    || planned_chunks.contains(chunk_id)
+                    || !matches!(
+                        chunk_details.get_replication_status(),
+                        ChunkReplicationStatus::Balanced
+                    )
+                {
+                    continue;
+                }
+                let size = chunk_details.end_offset - chunk_details.start_offset;
+                let racks_before = Self::racks(state, chunk_details.locations.iter());
+                // least utilized target which doesn't hold this chunk already
+                let target = targets
+                    .iter()
+                    .filter(|target| {
+                        let target_utilization = utilization(usage[**target]);
+                        !chunk_details.locations.contains(**target)
+                            && state.datanode_to_detail_map[**target].can_store(size)
+                            && target_utilization < average
+                            && (source_utilization > average + threshold
+                                || target_utilization < average - threshold)
+                            // moving shouldn't reduce the number of racks holding the chunk
+                            && Self::racks(
+                                state,
+                                chunk_details
+                                    .locations
+                                    .iter()
+                                    .filter(|location| *location != source)
+                                    .chain(std::iter::once(&target.to_string())),
+                            ) >= racks_before
+                    })
+                    .min_by(|a, b| utilization(usage[**a]).total_cmp(&utilization(usage[**b])));
+                let Some(target) = target else {
+                    continue;
+                };
+                if let Some((used, _)) = usage.get_mut(source) {
+                    *used = used.saturating_sub(size);
+                }
+                if let Some((used, _)) = usage.get_mut(*target) {
+                    *used += size;
+                }
+                planned_chunks.insert(chunk_id.to_owned());
+                moves.push(ChunkMove {
+                    chunk_id: chunk_id.to_owned(),
+                    size,
+                    source: (&state.datanode_to_detail_map[source]).into(),
+                    target: (&state.datanode_to_detail_map[*target]).into(),
+                });
+            }
+        }
+        moves
+    }
+    // number of distinct racks among the given datanodes
+    fn racks<'a>(state: &NamenodeState, datanode_ids: impl Iterator<Item = &'a String>) -> usize {
+        datanode_ids
+            .filter_map(|datanode_id| state.datanode_to_detail_map.get(datanode_id))
+            .map(|datanode_details| datanode_details.network_location())
+            .collect::<HashSet<_>>()
+            .len()
+    }
+    #[instrument(name = "namenode_balancer_move_chunk", skip(self, chunk_move), fields(chunk_id = %chunk_move.chunk_id, source = %chunk_move.source.id, target = %chunk_move.target.id))]
+    async fn move_chunk(&self, chunk_move: &ChunkMove) -> Result<()> {
+        self.datanode_service
+            .replicate_chunk(
+                chunk_move.source.clone(),
+                chunk_move.target.clone(),
+                &chunk_move.chunk_id,
+            )
+            .await?
+            .ok_or("Chunk is already being replicated by source")?;
+        // replica shows up in chunk locations as soon as target commits it
+        let started_at = Instant::now();
+        loop {
+            sleep(Duration::from_secs(1)).await;
+            let state = self.namenode_state.lock().await;
+            let replicated = state
+                .chunk_id_to_detail_map
+                .get(&chunk_move.chunk_id)
+                .ok_or("Chunk deleted while it was being moved")?
+                .locations
+                .contains(&chunk_move.target.id);
+            if replicated {
+                break;
+            }
+            if started_at.elapsed() > Duration::from_secs(CONFIG.command_timeout_secs) {
+                return Err("Target didn't receive the replica in time".into());
+            }
+        }
+        self.datanode_service
+            .delete_chunk(&chunk_move.source, &chunk_move.chunk_id)
+            .await?;
+        // source replica stops counting right away so state mantainer doesn't offload another one,
+        // if delete fails it is picked up again with the next full report of source
+        let mut state = self.namenode_state.lock().await;
+        if let Some(chunk_details) = state.chunk_id_to_detail_map.get_mut(&chunk_move.chunk_id) {
+            chunk_details.remove_location(&chunk_move.source.id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::namenode_state::tests::{chunk, datanode, state};
+    use utilities::ticket::ticket_generator::DefaultTicketGenerator;
+
+    fn balancer(threshold: f64) -> Balancer {
+        let ticket_mint = Arc::new(Mutex::new(TicketMint::new(Box::new(
+            DefaultTicketGenerator::new(),
+        ))));
+        let mut balancer = Balancer::new(Arc::new(Mutex::new(NamenodeState::new())), ticket_mint);
+        balancer.threshold = threshold;
+        balancer
+    }
+
+    // chunks of a source are picked in no particular order so only nodes are compared
+    fn planned_moves(balancer: &Balancer, state: &NamenodeState) -> Vec<(String, String)> {
+        let mut moves: Vec<(String, String)> = balancer
+            .plan_moves(state)
+            .into_iter()
+            .map(|chunk_move| (chunk_move.source.id, chunk_move.target.id))
+            .collect();
+        moves.sort();
+        moves
+    }
+
+    #[tokio::test]
+    async fn over_utilized_node_moves_chunk_to_node_without_it() {
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 1000, 100),
+                datanode("n2", "r2", 1000, 700),
+                datanode("n3", "r3", 1000, 700),
+                datanode("n4", "r4", 1000, 700),
+            ],
+            vec![chunk("c1", 100, &["n1", "n2", "n3"])],
+        );
+        assert_eq!(
+            planned_moves(&balancer(10.0), &cluster),
+            vec![("n1".to_owned(), "n4".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn moves_follow_threshold() {
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 1000, 440),
+                datanode("n2", "r2", 1000, 450),
+                datanode("n3", "r3", 1000, 550),
+                datanode("n4", "r4", 1000, 550),
+            ],
+            vec![chunk("c1", 50, &["n1", "n2", "n3"])],
+        );
+        assert!(planned_moves(&balancer(10.0), &cluster).is_empty());
+        assert_eq!(
+            planned_moves(&balancer(2.0), &cluster),
+            vec![("n1".to_owned(), "n4".to_owned())]
+        );
+        // source drops under average after first move
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 1000, 200),
+                datanode("n2", "r2", 1000, 500),
+                datanode("n3", "r3", 1000, 500),
+                datanode("n4", "r4", 1000, 800),
+            ],
+            vec![
+                chunk("c1", 200, &["n1", "n2", "n3"]),
+                chunk("c2", 200, &["n1", "n2", "n3"]),
+            ],
+        );
+        assert_eq!(
+            planned_moves(&balancer(10.0), &cluster),
+            vec![("n1".to_owned(), "n4".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn move_keeps_racks_holding_the_chunk() {
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 1000, 100),
+                datanode("n2", "r2", 1000, 700),
+                datanode("n3", "r3", 1000, 700),
+                datanode("n4", "r2", 1000, 700),
+            ],
+            vec![chunk("c1", 100, &["n1", "n2", "n3"])],
+        );
+        assert!(planned_moves(&balancer(10.0), &cluster).is_empty());
+    }
+
+    #[tokio::test]
+    async fn undereplicated_chunk_is_left_to_repair() {
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 1000, 100),
+                datanode("n2", "r2", 1000, 700),
+                datanode("n3", "r3", 1000, 700),
+            ],
+            vec![chunk("c1", 100, &["n1", "n2"])],
+        );
+        assert!(planned_moves(&balancer(10.0), &cluster).is_empty());
+    }
+}
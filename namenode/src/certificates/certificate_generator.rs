@@ -1,12 +1,15 @@
 use crate::config::CONFIG;
+use base64::{Engine, prelude::BASE64_STANDARD};
 use rcgen::{
     BasicConstraints, Certificate, CertificateParams, IsCa, Issuer, KeyPair, PKCS_ECDSA_P256_SHA256,
 };
 use std::{fs, path::PathBuf};
+use tonic::transport::ServerTlsConfig;
 use utilities::{
     auth::types::NodeType,
     logger::{info, instrument, tracing},
     result::Result,
+    tls::{self, NAMENODE_SERVER_NAME},
 };
 
 #[derive(Debug)]
@@ -54,7 +57,8 @@ impl CertificateAuthority {
         let leaf_key = KeyPair::generate_for(&PKCS_ECDSA_P256_SHA256)
             .expect("Error while generating the key pair");
 
-        let mut params = CertificateParams::new(vec!["Datanode".to_string()]).unwrap();
+        // node id is the name datanodes are reached by on the data plane
+        let mut params = CertificateParams::new(vec![node_id.clone()])?;
 
         params.is_ca = IsCa::NoCa;
 
@@ -67,6 +71,16 @@ impl CertificateAuthority {
         let cert = params.signed_by(&leaf_key, &self.issuer)?;
         Ok((cert, leaf_key))
     }
+    // namenode serves grpc with a certificate of its own, issued again on every start
+    pub fn server_tls_config(&self) -> Result<ServerTlsConfig> {
+        let (cert, key_pair) =
+            self.issue_certificate(NAMENODE_SERVER_NAME.to_owned(), NodeType::Namenode)?;
+        tls::grpc_server_config(
+            &BASE64_STANDARD.encode(cert.der()),
+            &BASE64_STANDARD.encode(key_pair.serialize_der()),
+            &BASE64_STANDARD.encode(self.cert.der()),
+        )
+    }
     // pub fn revoke_certifcate() {}
     pub fn get_root_cert(&self) -> Certificate {
         self.cert.clone()
@@ -1,25 +1,24 @@
 use std::{sync::Arc, vec};
 
 use proto::generated::client_namenode::{
-    ChunkMeta, DeleteFileRequest, DeleteFileResponse, FetchFileRequest, FetchFileResponse,
-    StoreFileRequest, StoreFileResponse, client_name_node_server::ClientNameNode,
+    ChunkMeta, DataNodeMeta, DeleteFileRequest, DeleteFileResponse, FetchFileRequest,
+    FetchFileResponse, RecoverPipelineRequest, RecoverPipelineResponse, ReportBadChunkRequest,
+    ReportBadChunkResponse, StoreFileRequest, StoreFileResponse,
+    client_name_node_server::ClientNameNode,
 };
 use tokio::sync::Mutex;
 use tonic::Code;
 use utilities::{
     auth::types::NodeMetadata,
-    logger::{instrument, trace, tracing},
+    logger::{instrument, trace, tracing, warn},
     ticket::ticket_mint::TicketMint,
 };
 
 use crate::{
     chunk_generator::{ChunkGenerator, DefaultChunkGenerator},
-    datanode::selection_policy::{
-        default_selection_policy::DefaultDatanodeSelectionPolicy,
-        selection_policy::DatanodeSelectionPolicy,
-    },
+    datanode::selection_policy::{get_selection_policy, policy::DatanodeSelectionPolicy},
     ledger::default_ledger::Ledger,
-    namenode_state::NamenodeState,
+    namenode_state::{NamenodeState, chunk_details::ChunkState},
 };
 
 pub struct ClientHandler {
@@ -35,8 +34,7 @@ impl ClientHandler {
         ledger: Box<dyn Ledger + Send + Sync>,
         ticket_mint: Arc<Mutex<TicketMint>>,
     ) -> Self {
-        let datanode_selection_policy =
-            Box::new(DefaultDatanodeSelectionPolicy::new(state.clone()));
+        let datanode_selection_policy = get_selection_policy(state.clone());
         let chunk_generator = Box::new(DefaultChunkGenerator::new((64 * 1024 * 1024) as u64));
         Self {
             state,
@@ -78,7 +76,7 @@ impl ClientNameNode for ClientHandler {
                 .await;
             let location = self
                 .datanode_selector
-                .get_datanodes_to_store(chunk.end_offset - chunk.start_offset)
+                .get_datanodes_to_store(chunk.end_offset - chunk.start_offset, &node_meta.id)
                 .await
                 .map_err(|e| tonic::Status::internal(format!("{e}")))?;
             let ticket = tm
@@ -97,6 +95,8 @@ impl ClientNameNode for ClientHandler {
                 end_offset: chunk.end_offset,
                 ticket,
                 location,
+                location_tickets: vec![],
+                generation: chunk.generation,
             });
         }
         // add this detail to namenode meta
@@ -106,7 +106,8 @@ impl ClientNameNode for ClientHandler {
             chunk_details.iter().map(|chunk| chunk.id.clone()).collect(),
         );
         // inserting the chunk boundary detail in state
-        chunk_details.into_iter().for_each(|chunk| {
+        chunk_details.into_iter().for_each(|mut chunk| {
+            chunk.writer_id = node_meta.id.clone();
             state.chunk_id_to_detail_map.insert(chunk.id.clone(), chunk);
         });
         trace!(chunk_meta = ?chunk_meta,"Handled request");
@@ -122,6 +123,7 @@ impl ClientNameNode for ClientHandler {
         request: tonic::Request<FetchFileRequest>,
     ) -> Result<tonic::Response<FetchFileResponse>, tonic::Status> {
         let node_meta = request.extensions().get::<NodeMetadata>().unwrap(); // node meta will be always
+        let reader_host = request.remote_addr().map(|addrs| addrs.ip().to_string());
         let fetch_file_request = request.get_ref();
         //TODO: find something better than cloning state
         //if we don't clone here this becomes deadlock when we can function get_datanodes_to_serve
@@ -131,7 +133,11 @@ impl ClientNameNode for ClientHandler {
         if let Some(chunks) = state.file_to_chunk_map.get(&fetch_file_request.file_name) {
             let mut chunk_list: Vec<ChunkMeta> = vec![];
             for chunk in chunks {
-                let location = match self.datanode_selector.get_datanodes_to_serve(chunk).await {
+                let location = match self
+                    .datanode_selector
+                    .get_datanodes_to_serve(chunk, &node_meta.id, reader_host.as_deref())
+                    .await
+                {
                     Ok(location) => location,
                     Err(e) => {
                         return Err(tonic::Status::not_found(format!("{e}")));
@@ -143,22 +149,29 @@ impl ClientNameNode for ClientHandler {
                         return Err(tonic::Status::not_found("Error while geting chunk meta"));
                     }
                 };
-                let ticket = tm
-                    .mint_ticket(
-                        &node_meta.id,
-                        &location.id,
-                        utilities::ticket::types::Operation::FetchChunk {
-                            chunk_id: chunk.to_string(),
-                        },
-                    )
-                    .map_err(|e| tonic::Status::internal(format!("{e}")))?;
+                // ticket is bound to target node so every replica needs its own
+                let mut location_tickets = vec![];
+                for datanode_meta in &location {
+                    let ticket = tm
+                        .mint_ticket(
+                            &node_meta.id,
+                            &datanode_meta.id,
+                            utilities::ticket::types::Operation::FetchChunk {
+                                chunk_id: chunk.to_string(),
+                            },
+                        )
+                        .map_err(|e| tonic::Status::internal(format!("{e}")))?;
+                    location_tickets.push(ticket);
+                }
 
                 chunk_list.push(ChunkMeta {
                     id: chunk.to_string(),
-                    location: vec![location],
+                    location,
                     start_offset: chunk_details.start_offset,
                     end_offset: chunk_details.end_offset,
-                    ticket,
+                    ticket: location_tickets[0].clone(),
+                    location_tickets,
+                    generation: chunk_details.generation,
                 });
             }
             trace!(chunk_list = ?chunk_list,"fetch file request Handled");
@@ -211,4 +224,95 @@ impl ClientNameNode for ClientHandler {
         let delete_file_response = DeleteFileResponse { file_present: true };
         Ok(tonic::Response::new(delete_file_response))
     }
+    #[instrument(name="grpc_client_report_bad_chunk",skip(self,request),fields(chunk_id= %request.get_ref().chunk_id,datanode_id= %request.get_ref().datanode_id))]
+    async fn report_bad_chunk(
+        &self,
+        request: tonic::Request<ReportBadChunkRequest>,
+    ) -> Result<tonic::Response<ReportBadChunkResponse>, tonic::Status> {
+        let node_meta = request.extensions().get::<NodeMetadata>().unwrap(); // node meta will be always
+        let report = request.get_ref();
+        warn!(client_id = %node_meta.id, reason = %report.reason, "Client reported bad replica");
+        let mut state = self.state.lock().await;
+        let chunk_details = state
+            .chunk_id_to_detail_map
+            .get_mut(&report.chunk_id)
+            .ok_or_else(|| {
+                tonic::Status::not_found(format!("Chunk {} not found", report.chunk_id))
+            })?;
+        // replica is only suspect, state mantainer re-replicates the chunk from a good one and
+        // deletes this one after that
+        let marked_corrupt = chunk_details.mark_corrupt(&report.datanode_id);
+        Ok(tonic::Response::new(ReportBadChunkResponse {
+            marked_corrupt,
+        }))
+    }
+    #[instrument(name="grpc_client_recover_pipeline",skip(self,request),fields(chunk_id= %request.get_ref().chunk_id))]
+    async fn recover_pipeline(
+        &self,
+        request: tonic::Request<RecoverPipelineRequest>,
+    ) -> Result<tonic::Response<RecoverPipelineResponse>, tonic::Status> {
+        let node_meta = request.extensions().get::<NodeMetadata>().unwrap(); // node meta will be always
+        let recover_request = request.get_ref();
+        warn!(client_id = %node_meta.id, failed = ?recover_request.failed, "Client lost datanodes of write pipeline");
+        if recover_request.surviving.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "No datanode left in the pipeline",
+            ));
+        }
+        let mut state = self.state.lock().await;
+        let mut location = vec![];
+        for datanode_id in &recover_request.surviving {
+            let Some(datanode_details) = state.datanode_to_detail_map.get(datanode_id) else {
+                return Err(tonic::Status::not_found(format!(
+                    "Datanode {datanode_id} not found"
+                )));
+            };
+            location.push(DataNodeMeta::from(datanode_details));
+        }
+        let chunk_details = state
+            .chunk_id_to_detail_map
+            .get_mut(&recover_request.chunk_id)
+            .ok_or_else(|| {
+                tonic::Status::not_found(format!("Chunk {} not found", recover_request.chunk_id))
+            })?;
+        // anyone else could bump the generation and take over the write
+        if chunk_details.writer_id != node_meta.id {
+            return Err(tonic::Status::permission_denied(format!(
+                "Chunk {} is written by another client",
+                recover_request.chunk_id
+            )));
+        }
+        if chunk_details.state != ChunkState::Initialized {
+            return Err(tonic::Status::failed_precondition(format!(
+                "Chunk {} is not being written",
+                recover_request.chunk_id
+            )));
+        }
+        // whatever the failed datanodes hold turns stale, state mantainer restores the replica
+        // count once survivors commit
+        chunk_details.generation += 1;
+        let generation = chunk_details.generation;
+        drop(state);
+        // datanodes keep the generation across restarts, so has to namenode
+        self.ledger
+            .chunk_generation(&recover_request.chunk_id, generation)
+            .await;
+        let ticket = self
+            .ticket_mint
+            .lock()
+            .await
+            .mint_ticket(
+                &node_meta.id,
+                &location[0].id,
+                utilities::ticket::types::Operation::StoreChunk {
+                    chunk_id: recover_request.chunk_id.clone(),
+                },
+            )
+            .map_err(|e| tonic::Status::internal(format!("{e}")))?;
+        Ok(tonic::Response::new(RecoverPipelineResponse {
+            generation,
+            location,
+            ticket,
+        }))
+    }
 }
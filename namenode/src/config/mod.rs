@@ -14,6 +14,44 @@ fn default_api_password() -> String {
 fn default_certificate_dir() -> String {
     "./certificate".to_string()
 }
+fn default_maintenance_duration_secs() -> u64 {
+    15 * 60
+}
+fn default_stale_interval_secs() -> u64 {
+    10
+}
+fn default_dead_interval_secs() -> u64 {
+    30
+}
+fn default_command_timeout_secs() -> u64 {
+    60
+}
+fn default_max_datanode_load() -> u64 {
+    32
+}
+fn default_max_recent_disk_errors() -> u64 {
+    5
+}
+fn default_balancer_threshold() -> f64 {
+    10.0
+}
+fn default_balancer_bandwidth_bytes_per_sec() -> u64 {
+    10 * 1024 * 1024
+}
+fn default_balancer_interval_secs() -> u64 {
+    60
+}
+fn default_key_grace_secs() -> u64 {
+    10 * 60
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionPolicyKind {
+    #[default]
+    Default,
+    RackAware,
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
@@ -34,6 +72,40 @@ pub struct Config {
     pub jwt_sign_key: String,
     #[serde(default = "default_certificate_dir")]
     pub certificate_dir: String,
+    // datanode is stale when no heartbeat was received for these many seconds
+    #[serde(default = "default_stale_interval_secs")]
+    pub stale_interval_secs: u64,
+    // and dead after these many, its replicas are re-replicated
+    #[serde(default = "default_dead_interval_secs")]
+    pub dead_interval_secs: u64,
+    // command delivered to a datanode is dropped when not acknowledged in these many seconds
+    #[serde(default = "default_command_timeout_secs")]
+    pub command_timeout_secs: u64,
+    // no new chunks are placed on a datanode with these many transfers and replications running
+    #[serde(default = "default_max_datanode_load")]
+    pub max_datanode_load: u64,
+    // or with these many disk errors reported in its last heartbeat
+    #[serde(default = "default_max_recent_disk_errors")]
+    pub max_recent_disk_errors: u64,
+    // used when maintenance is requested without an explicit duration
+    #[serde(default = "default_maintenance_duration_secs")]
+    pub maintenance_duration_secs: u64,
+    // a node is over/under utilized when it is this many percentage points away from average
+    #[serde(default = "default_balancer_threshold")]
+    pub balancer_threshold: f64,
+    #[serde(default = "default_balancer_bandwidth_bytes_per_sec")]
+    pub balancer_bandwidth_bytes_per_sec: u64,
+    // when false balancer only runs when requested through api
+    #[serde(default)]
+    pub balancer_continuous: bool,
+    #[serde(default = "default_balancer_interval_secs")]
+    pub balancer_interval_secs: u64,
+    // policy used to place, serve and repair chunks
+    #[serde(default)]
+    pub selection_policy: SelectionPolicyKind,
+    // node keys replaced by a rotation are accepted this long, has to be longer than ticket ttl
+    #[serde(default = "default_key_grace_secs")]
+    pub key_grace_secs: u64,
 }
 impl Default for Config {
     fn default() -> Self {
@@ -51,6 +123,18 @@ impl Default for Config {
             api_password: "password".to_string(),
             jwt_sign_key: "key".to_string(),
             certificate_dir: "./certificate".to_string(),
+            stale_interval_secs: 10,
+            dead_interval_secs: 30,
+            command_timeout_secs: 60,
+            max_datanode_load: 32,
+            max_recent_disk_errors: 5,
+            maintenance_duration_secs: 15 * 60,
+            balancer_threshold: 10.0,
+            balancer_bandwidth_bytes_per_sec: 10 * 1024 * 1024,
+            balancer_continuous: false,
+            balancer_interval_secs: 60,
+            selection_policy: SelectionPolicyKind::Default,
+            key_grace_secs: 10 * 60,
         }
     }
 }
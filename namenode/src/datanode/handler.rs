@@ -1,86 +1,482 @@
-use std::sync::Arc;
+use std::{pin::Pin, sync::Arc};
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify, mpsc};
+use tokio_stream::{Stream, wrappers::ReceiverStream};
 use utilities::{
-    logger::{instrument, tracing},
+    auth::types::{NodeMetadata, NodeType},
+    logger::{error, info, instrument, trace, tracing, warn},
     ticket::ticket_mint::TicketMint,
 };
 
+use crate::config::CONFIG;
 use crate::namenode_state::NamenodeState;
-use crate::namenode_state::datanode_details::DatanodeDetail;
+use crate::namenode_state::datanode_details::{DatanodeDetail, DatanodeState, VolumeDetail};
 
 use proto::generated::datanode_namenode::{
-    ConnectionRequest, ConnectionResponse, HeartBeatRequest, HeartBeatResponse, StateSyncRequest,
-    StateSyncResponse, StoreChunkTicketRequest, StoreChunkTicketResponse,
-    datanode_namenode_server::DatanodeNamenode,
+    ChunkCommittedRequest, ChunkCommittedResponse, ChunkReportRequest, ChunkReportResponse,
+    ConnectionRequest, ConnectionResponse, DatanodeCommands, DatanodeMessage, HeartBeatRequest,
+    HeartBeatResponse, NamenodeMessage, NodeKey, NodeKeysRequest, NodeKeysResponse,
+    ReregisterCommand, StateSyncRequest, StateSyncResponse, StoreChunkTicketRequest,
+    StoreChunkTicketResponse, datanode_command::Command, datanode_message,
+    datanode_namenode_server::DatanodeNamenode, namenode_message,
 };
 
+#[derive(Clone)]
 pub struct DatanodeHandler {
     state: Arc<Mutex<NamenodeState>>,
     ticket_mint: Arc<Mutex<TicketMint>>,
+    cluster_id: String,
 }
 impl DatanodeHandler {
     pub fn new(
         namenode_state: Arc<Mutex<NamenodeState>>,
         ticket_mint: Arc<Mutex<TicketMint>>,
+        cluster_id: String,
     ) -> Self {
         Self {
             state: namenode_state,
             ticket_mint,
+            cluster_id,
         }
     }
-}
-
-#[tonic::async_trait]
-impl DatanodeNamenode for DatanodeHandler {
-    #[instrument(name="grpc_datanode_heart_beat",skip(self,request),fields(datanode_id= %request.get_ref().datanode_id))]
-    async fn heart_beat(
+    // checks storage of connecting datanode belongs to this cluster and to this node
+    fn validate_identity(
         &self,
-        request: tonic::Request<HeartBeatRequest>,
-    ) -> Result<tonic::Response<HeartBeatResponse>, tonic::Status> {
-        let heart_beat_request = request.into_inner();
-        //trace!("got heartbeat request {:?}", heart_beat_request);
+        state: &NamenodeState,
+        connection_request: &ConnectionRequest,
+    ) -> Result<(), String> {
+        if !connection_request.cluster_id.is_empty()
+            && connection_request.cluster_id != self.cluster_id
+        {
+            return Err(format!(
+                "Storage belongs to cluster {} but namenode cluster is {}",
+                connection_request.cluster_id, self.cluster_id
+            ));
+        }
+        if !connection_request.storage_datanode_id.is_empty()
+            && connection_request.storage_datanode_id != connection_request.id
+        {
+            return Err(format!(
+                "Storage belongs to datanode {} and can't be used by {}",
+                connection_request.storage_datanode_id, connection_request.id
+            ));
+        }
+        if connection_request.storage_id.is_empty() {
+            return Ok(());
+        }
+        for (datanode_id, datanode_details) in &state.datanode_to_detail_map {
+            if datanode_details.storage_id.is_empty() {
+                continue;
+            }
+            if *datanode_id == connection_request.id
+                && datanode_details.storage_id != connection_request.storage_id
+            {
+                return Err(format!(
+                    "Datanode {} is registered with storage {} but connected with {}",
+                    datanode_id, datanode_details.storage_id, connection_request.storage_id
+                ));
+            }
+            if *datanode_id != connection_request.id
+                && datanode_details.storage_id == connection_request.storage_id
+            {
+                return Err(format!(
+                    "Storage {} is already in use by datanode {}",
+                    connection_request.storage_id, datanode_id
+                ));
+            }
+        }
+        Ok(())
+    }
+    // reply for a message received on heartbeat stream of the datanode named in the certificate
+    async fn process_datanode_message(
+        &self,
+        caller_id: &str,
+        message: DatanodeMessage,
+    ) -> Option<NamenodeMessage> {
+        let message = match message.message? {
+            datanode_message::Message::HeartBeat(heart_beat_request) => {
+                namenode_message::Message::HeartBeat(
+                    self.process_heart_beat(caller_id, heart_beat_request).await,
+                )
+            }
+            datanode_message::Message::ChunkReport(chunk_report_request) => {
+                namenode_message::Message::ChunkReport(
+                    self.process_chunk_report(chunk_report_request).await,
+                )
+            }
+            datanode_message::Message::StateSync(state_sync_request) => {
+                namenode_message::Message::StateSync(
+                    self.process_state_sync(state_sync_request).await,
+                )
+            }
+        };
+        Some(NamenodeMessage {
+            message: Some(message),
+        })
+    }
+    // commands queued after the last heartbeat, pushed without waiting for the next one
+    async fn queued_commands(&self, datanode_id: &str) -> Option<NamenodeMessage> {
         let mut state = self.state.lock().await;
-        let response = if let Some(datanode_details) = state
+        let commands = state
             .datanode_to_detail_map
-            .get_mut(&heart_beat_request.datanode_id)
-        {
+            .get_mut(datanode_id)?
+            .commands
+            .deliver();
+        if commands.is_empty() {
+            return None;
+        }
+        Some(NamenodeMessage {
+            message: Some(namenode_message::Message::Commands(DatanodeCommands {
+                commands,
+            })),
+        })
+    }
+    // datanode is not used for writes from now, it is dead if it doesn't come back in time
+    async fn stream_closed(&self, datanode_id: &str) {
+        let mut state = self.state.lock().await;
+        let Some(datanode_details) = state.datanode_to_detail_map.get_mut(datanode_id) else {
+            return;
+        };
+        datanode_details.disconnected = true;
+        if datanode_details.is_active() {
+            info!(%datanode_id, from = datanode_details.state.name(), to = DatanodeState::Stale.name(), "Datanode state changed");
+            datanode_details.state = DatanodeState::Stale;
+        }
+    }
+    // caller id comes from the certificate, heartbeat of an unknown datanode changes nothing
+    async fn process_heart_beat(
+        &self,
+        datanode_id: &str,
+        heart_beat_request: HeartBeatRequest,
+    ) -> HeartBeatResponse {
+        //trace!("got heartbeat request {:?}", heart_beat_request);
+        let mut response = self
+            .record_heart_beat(datanode_id, &heart_beat_request)
+            .await;
+        if response.connection_alive {
+            // tickets switch to a rotated key only once datanode reports it has it
+            let mut tm = self.ticket_mint.lock().await;
+            tm.activate_node_key(datanode_id, heart_beat_request.key_id);
+            response.key_id = tm.newest_node_key_id(datanode_id).unwrap_or_default();
+        }
+        response
+    }
+    async fn record_heart_beat(
+        &self,
+        datanode_id: &str,
+        heart_beat_request: &HeartBeatRequest,
+    ) -> HeartBeatResponse {
+        let mut state = self.state.lock().await;
+        if let Some(datanode_details) = state.datanode_to_detail_map.get_mut(datanode_id) {
             datanode_details.mark_heartbeat();
+            datanode_details.active_transfers = heart_beat_request.active_transfers;
+            datanode_details.active_reads = heart_beat_request.active_reads;
+            datanode_details.active_writes = heart_beat_request.active_writes;
+            datanode_details.pending_replications = heart_beat_request.pending_replications;
+            datanode_details.recent_disk_errors = heart_beat_request.recent_disk_errors;
+            datanode_details.volumes = heart_beat_request
+                .volumes
+                .iter()
+                .cloned()
+                .map(VolumeDetail::from)
+                .collect();
+            if let Some(scan_progress) = heart_beat_request.scan_progress {
+                datanode_details.scan_progress = scan_progress.into();
+            }
+            for ack in &heart_beat_request.acks {
+                let Some(command) = datanode_details.commands.ack(ack) else {
+                    continue;
+                };
+                if ack.success {
+                    trace!(%datanode_id, command_id = ack.id, "Datanode executed command");
+                } else {
+                    warn!(%datanode_id, command_id = ack.id, error = %ack.error, ?command, "Datanode failed to execute command");
+                }
+            }
+            // its replicas were dropped while it was dead, registering again gets us a full report
+            if datanode_details.state == DatanodeState::Dead {
+                datanode_details
+                    .commands
+                    .push(Command::Reregister(ReregisterCommand {}));
+            }
             HeartBeatResponse {
                 connection_alive: true,
+                commands: datanode_details.commands.deliver(),
+                key_id: 0,
             }
         } else {
             HeartBeatResponse {
                 connection_alive: false,
+                commands: vec![],
+                key_id: 0,
+            }
+        }
+    }
+    async fn process_state_sync(&self, state_sync_request: StateSyncRequest) -> StateSyncResponse {
+        let mut state = self.state.lock().await;
+        let commands = match state.datanode_to_detail_map.get_mut(&state_sync_request.id) {
+            Some(datanode_details) => {
+                datanode_details.sync_state(
+                    state_sync_request.availabe_storage,
+                    state_sync_request.storage_capacity,
+                );
+                datanode_details.commands.deliver()
+            }
+            None => vec![],
+        };
+        let mut chunks_to_be_deleted = vec![];
+        // storage only sync, chunks are reported incrementally
+        if !state_sync_request.full_report {
+            let response = StateSyncResponse {
+                chunks_to_be_deleted,
+                commands,
+            };
+            return response;
+        }
+        if let Some(datanode_details) = state.datanode_to_detail_map.get_mut(&state_sync_request.id)
+        {
+            datanode_details.full_report_received(state_sync_request.report_sequence);
+        }
+        for (chunk_id, generation) in &state_sync_request.available_chunks {
+            chunk_received(
+                &mut state,
+                &state_sync_request.id,
+                chunk_id,
+                *generation,
+                &mut chunks_to_be_deleted,
+            );
+        }
+        state
+            .chunk_id_to_detail_map
+            .iter_mut()
+            .filter(|(_, chunk_meta)| chunk_meta.locations.contains(&state_sync_request.id))
+            .for_each(|(chunk_id, chunk_meta)| {
+                // corrupt replica is left out of the report but stays on disk till it is deleted
+                if !state_sync_request.available_chunks.contains_key(chunk_id)
+                    && !chunk_meta.is_corrupt(&state_sync_request.id)
+                {
+                    chunk_meta.remove_location(&state_sync_request.id);
+                }
+            });
+        StateSyncResponse {
+            chunks_to_be_deleted,
+            commands,
+        }
+    }
+    async fn process_chunk_report(
+        &self,
+        chunk_report_request: ChunkReportRequest,
+    ) -> ChunkReportResponse {
+        let datanode_id = &chunk_report_request.datanode_id;
+        let mut state = self.state.lock().await;
+        let full_report_required = match state.datanode_to_detail_map.get_mut(datanode_id) {
+            Some(datanode_details) => {
+                !datanode_details.accept_report_sequence(chunk_report_request.sequence)
             }
+            None => true,
         };
+        // deltas can't be trusted after a gap, full report replaces them
+        if full_report_required {
+            info!(%datanode_id, "Chunk report out of sequence, requesting full report");
+            return ChunkReportResponse {
+                full_report_required,
+                chunks_to_be_deleted: vec![],
+            };
+        }
+        let mut chunks_to_be_deleted = vec![];
+        for (chunk_id, generation) in &chunk_report_request.received {
+            chunk_received(
+                &mut state,
+                datanode_id,
+                chunk_id,
+                *generation,
+                &mut chunks_to_be_deleted,
+            );
+        }
+        for chunk_id in &chunk_report_request.deleted {
+            if let Some(chunk_details) = state.chunk_id_to_detail_map.get_mut(chunk_id) {
+                chunk_details.remove_location(datanode_id);
+            }
+        }
+        // corrupt replica is kept till state mantainer repairs the chunk from a good one, it may
+        // be the last copy we have
+        for chunk_id in &chunk_report_request.corrupt {
+            warn!(%datanode_id, %chunk_id, "Datanode reported corrupt replica");
+            if let Some(chunk_details) = state.chunk_id_to_detail_map.get_mut(chunk_id) {
+                chunk_details.mark_corrupt(datanode_id);
+            }
+        }
+        // last verified times are only kept for replicas datanode still holds
+        if let Some(datanode_details) = state.datanode_to_detail_map.get_mut(datanode_id) {
+            datanode_details
+                .chunk_last_verified
+                .extend(chunk_report_request.verified.clone());
+            for chunk_id in chunk_report_request
+                .deleted
+                .iter()
+                .chain(&chunk_report_request.corrupt)
+            {
+                datanode_details.chunk_last_verified.remove(chunk_id);
+            }
+        }
+        ChunkReportResponse {
+            full_report_required,
+            chunks_to_be_deleted,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl DatanodeNamenode for DatanodeHandler {
+    #[instrument(name="grpc_datanode_heart_beat",skip(self,request),fields(datanode_id= %request.get_ref().datanode_id))]
+    async fn heart_beat(
+        &self,
+        request: tonic::Request<HeartBeatRequest>,
+    ) -> Result<tonic::Response<HeartBeatResponse>, tonic::Status> {
+        let caller_id = caller_datanode_id(&request)?;
+        check_datanode_id(&caller_id, &request.get_ref().datanode_id)?;
+        let response = self
+            .process_heart_beat(&caller_id, request.into_inner())
+            .await;
         Ok(tonic::Response::new(response))
     }
+    type HeartBeatStreamStream =
+        Pin<Box<dyn Stream<Item = Result<NamenodeMessage, tonic::Status>> + Send>>;
+    async fn heart_beat_stream(
+        &self,
+        request: tonic::Request<tonic::Streaming<DatanodeMessage>>,
+    ) -> Result<tonic::Response<Self::HeartBeatStreamStream>, tonic::Status> {
+        let caller_id = caller_datanode_id(&request)?;
+        let mut inbound = request.into_inner();
+        let (tx, rx) = mpsc::channel(16);
+        let handler = self.clone();
+        tokio::spawn(async move {
+            // datanode is known once its first heartbeat arrives
+            let mut datanode_id: Option<String> = None;
+            let mut notify: Option<Arc<Notify>> = None;
+            loop {
+                let commands_queued = {
+                    let notify = notify.clone();
+                    async move {
+                        match notify {
+                            Some(notify) => notify.notified().await,
+                            None => std::future::pending().await,
+                        }
+                    }
+                };
+                let reply = tokio::select! {
+                    message = inbound.message() => match message {
+                        Ok(Some(message)) => {
+                            if let Some(id) = message_datanode_id(&message)
+                                && let Err(status) = check_datanode_id(&caller_id, id)
+                            {
+                                warn!(%caller_id, "Refusing heartbeat stream message {status}");
+                                let _ = tx.send(Err(status)).await;
+                                break;
+                            }
+                            if let Some(datanode_message::Message::HeartBeat(heart_beat)) =
+                                &message.message
+                            {
+                                if datanode_id.is_none() {
+                                    info!(datanode_id = %heart_beat.datanode_id, "Heartbeat stream opened");
+                                }
+                                datanode_id = Some(heart_beat.datanode_id.clone());
+                                notify = handler
+                                    .state
+                                    .lock()
+                                    .await
+                                    .datanode_to_detail_map
+                                    .get(&heart_beat.datanode_id)
+                                    .map(|datanode_details| datanode_details.commands.notifier());
+                            }
+                            handler.process_datanode_message(&caller_id, message).await
+                        }
+                        Ok(None) => break,
+                        Err(e) => {
+                            warn!(?datanode_id, "Error on heartbeat stream {e}");
+                            break;
+                        }
+                    },
+                    _ = commands_queued => match &datanode_id {
+                        Some(datanode_id) => handler.queued_commands(datanode_id).await,
+                        None => None,
+                    },
+                };
+                if let Some(reply) = reply
+                    && tx.send(Ok(reply)).await.is_err()
+                {
+                    break;
+                }
+            }
+            if let Some(datanode_id) = datanode_id {
+                warn!(%datanode_id, "Heartbeat stream closed");
+                handler.stream_closed(&datanode_id).await;
+            }
+        });
+        Ok(tonic::Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
     #[instrument(name="grpc_datanode_connection",skip(self,request),fields(datanode_id= %request.get_ref().id))]
     async fn connection(
         &self,
         request: tonic::Request<ConnectionRequest>,
     ) -> Result<tonic::Response<ConnectionResponse>, tonic::Status> {
+        let caller_id = caller_datanode_id(&request)?;
+        check_datanode_id(&caller_id, &request.get_ref().id)?;
         let connection_request = request.into_inner();
         /*trace!(
             "got connection request from data node {}",
             connection_request.id
         );*/
         let mut state = self.state.lock().await;
+        if let Err(msg) = self.validate_identity(&state, &connection_request) {
+            error!(datanode_id = %connection_request.id, %msg, "Refusing datanode connection");
+            let response = ConnectionResponse {
+                connected: false,
+                msg,
+                cluster_id: self.cluster_id.clone(),
+            };
+            return Ok(tonic::Response::new(response));
+        }
         // if the connection already exist we will accept the connection and mark node as active
         let response = if let Some(datanode_details) =
             state.datanode_to_detail_map.get_mut(&connection_request.id)
         {
-            if datanode_details.is_active() {
+            if datanode_details.state == DatanodeState::Decommissioned {
+                ConnectionResponse {
+                    connected: false,
+                    msg: "Datanode is decommissioned".to_owned(),
+                    cluster_id: self.cluster_id.clone(),
+                }
+            } else if datanode_details.can_serve() && !datanode_details.disconnected {
+                // node is still heartbeating, a node whose stream broke can come back right away
                 ConnectionResponse {
                     connected: false,
                     msg: "Connection already exist for the specified id".to_owned(),
+                    cluster_id: self.cluster_id.clone(),
                 }
             } else {
-                datanode_details.mark_heartbeat();
+                // node is back from maintenance so we can serve from it again
+                if datanode_details.in_maintenance() {
+                    info!(datanode_id = %connection_request.id, "Datanode returned from maintenance");
+                }
+                info!(datanode_id = %connection_request.id, from = datanode_details.state.name(), "Datanode registered again");
+                let decommissioning = datanode_details.is_decommissioning();
+                datanode_details.register();
+                // draining goes on after the node comes back
+                if decommissioning {
+                    datanode_details.state = DatanodeState::Decommissioning;
+                }
+                // node could have been moved while it was down
+                datanode_details.rack = connection_request.rack;
+                datanode_details.zone = connection_request.zone;
+                // node registered before it had a storage identity
+                if datanode_details.storage_id.is_empty() {
+                    datanode_details.storage_id = connection_request.storage_id;
+                }
                 ConnectionResponse {
                     connected: true,
                     msg: "Connection restablished".to_owned(),
+                    cluster_id: self.cluster_id.clone(),
                 }
             }
         } else {
@@ -90,11 +486,15 @@ impl DatanodeNamenode for DatanodeHandler {
                     connection_request.id,
                     connection_request.name,
                     connection_request.addrs,
+                    connection_request.rack,
+                    connection_request.zone,
+                    connection_request.storage_id,
                 ),
             );
             ConnectionResponse {
                 connected: true,
                 msg: "Connected successfully".to_owned(),
+                cluster_id: self.cluster_id.clone(),
             }
         };
         drop(state);
@@ -105,35 +505,40 @@ impl DatanodeNamenode for DatanodeHandler {
         &self,
         request: tonic::Request<StateSyncRequest>,
     ) -> Result<tonic::Response<StateSyncResponse>, tonic::Status> {
-        let state_sync_request = request.into_inner();
+        let caller_id = caller_datanode_id(&request)?;
+        check_datanode_id(&caller_id, &request.get_ref().id)?;
+        let response = self.process_state_sync(request.into_inner()).await;
+        Ok(tonic::Response::new(response))
+    }
+    #[instrument(name="grpc_datanode_chunk_report",skip(self,request),fields(datanode_id= %request.get_ref().datanode_id, sequence = %request.get_ref().sequence))]
+    async fn chunk_report(
+        &self,
+        request: tonic::Request<ChunkReportRequest>,
+    ) -> Result<tonic::Response<ChunkReportResponse>, tonic::Status> {
+        let caller_id = caller_datanode_id(&request)?;
+        check_datanode_id(&caller_id, &request.get_ref().datanode_id)?;
+        let response = self.process_chunk_report(request.into_inner()).await;
+        Ok(tonic::Response::new(response))
+    }
+    #[instrument(name="grpc_datanode_chunk_committed",skip(self,request),fields(datanode_id= %request.get_ref().datanode_id, chunk_id = %request.get_ref().chunk_id))]
+    async fn chunk_committed(
+        &self,
+        request: tonic::Request<ChunkCommittedRequest>,
+    ) -> Result<tonic::Response<ChunkCommittedResponse>, tonic::Status> {
+        let caller_id = caller_datanode_id(&request)?;
+        check_datanode_id(&caller_id, &request.get_ref().datanode_id)?;
+        let chunk_committed_request = request.into_inner();
         let mut state = self.state.lock().await;
-        if let Some(datanode_details) = state.datanode_to_detail_map.get_mut(&state_sync_request.id)
-        {
-            datanode_details.sync_state(state_sync_request.availabe_storage);
-        }
         let mut chunks_to_be_deleted = vec![];
-        for chunk_id in &state_sync_request.available_chunks {
-            if let Some(chunk_details) = state.chunk_id_to_detail_map.get_mut(chunk_id) {
-                if chunk_details.is_deleted() {
-                    chunks_to_be_deleted.push(chunk_id.to_owned());
-                    continue;
-                }
-                chunk_details.add_location(&state_sync_request.id);
-            } else {
-                chunks_to_be_deleted.push(chunk_id.to_owned());
-            }
-        }
-        state
-            .chunk_id_to_detail_map
-            .iter_mut()
-            .filter(|(_, chunk_meta)| chunk_meta.locations.contains(&state_sync_request.id))
-            .for_each(|(chunk_id, chunk_meta)| {
-                if !state_sync_request.available_chunks.contains(chunk_id) {
-                    chunk_meta.remove_location(&state_sync_request.id);
-                }
-            });
-        let response = StateSyncResponse {
-            chunks_to_be_deleted,
+        chunk_received(
+            &mut state,
+            &chunk_committed_request.datanode_id,
+            &chunk_committed_request.chunk_id,
+            chunk_committed_request.generation,
+            &mut chunks_to_be_deleted,
+        );
+        let response = ChunkCommittedResponse {
+            to_be_deleted: !chunks_to_be_deleted.is_empty(),
         };
         Ok(tonic::Response::new(response))
     }
@@ -142,6 +547,8 @@ impl DatanodeNamenode for DatanodeHandler {
         &self,
         request: tonic::Request<StoreChunkTicketRequest>,
     ) -> Result<tonic::Response<StoreChunkTicketResponse>, tonic::Status> {
+        let caller_id = caller_datanode_id(&request)?;
+        check_datanode_id(&caller_id, &request.get_ref().source_id)?;
         let mut tm = self.ticket_mint.lock().await;
         let store_chunk_request = request.get_ref();
         let ticket = tm
@@ -161,4 +568,91 @@ impl DatanodeNamenode for DatanodeHandler {
         let pipeline_response = StoreChunkTicketResponse { ticket };
         Ok(tonic::Response::new(pipeline_response))
     }
+    #[instrument(name = "grpc_datanode_node_keys", skip(self, request))]
+    async fn node_keys(
+        &self,
+        request: tonic::Request<NodeKeysRequest>,
+    ) -> Result<tonic::Response<NodeKeysResponse>, tonic::Status> {
+        // keys are only handed to the datanode they belong to
+        let node_id = caller_datanode_id(&request)?;
+        let keys = self
+            .ticket_mint
+            .lock()
+            .await
+            .node_keys(&node_id, CONFIG.key_grace_secs)
+            .map_err(|e| tonic::Status::new(tonic::Code::NotFound, e.to_string()))?;
+        trace!(%node_id, key_ids = ?keys.iter().map(|k| k.key_id).collect::<Vec<_>>(), "Handing out node keys");
+        let response = NodeKeysResponse {
+            keys: keys
+                .into_iter()
+                .map(|k| NodeKey {
+                    key_id: k.key_id,
+                    key: k.key,
+                    expires_at_secs: k.expires_at_secs,
+                })
+                .collect(),
+        };
+        Ok(tonic::Response::new(response))
+    }
+}
+
+// datanode calling us as named in the certificate it presented, other nodes have no business here
+#[allow(clippy::result_large_err)]
+fn caller_datanode_id<T>(request: &tonic::Request<T>) -> Result<String, tonic::Status> {
+    match request.extensions().get::<NodeMetadata>() {
+        Some(node_metadata) if matches!(node_metadata.node_type, NodeType::Datanode) => {
+            Ok(node_metadata.id.clone())
+        }
+        Some(node_metadata) => Err(tonic::Status::new(
+            tonic::Code::PermissionDenied,
+            format!("Node {} is not a datanode", node_metadata.id),
+        )),
+        None => Err(tonic::Status::new(
+            tonic::Code::Unauthenticated,
+            "Caller is not known",
+        )),
+    }
+}
+
+// a datanode only speaks for itself
+#[allow(clippy::result_large_err)]
+fn check_datanode_id(caller_id: &str, datanode_id: &str) -> Result<(), tonic::Status> {
+    if caller_id != datanode_id {
+        return Err(tonic::Status::new(
+            tonic::Code::PermissionDenied,
+            format!("Datanode {caller_id} can't act as {datanode_id}"),
+        ));
+    }
+    Ok(())
+}
+
+fn message_datanode_id(message: &DatanodeMessage) -> Option<&str> {
+    match message.message.as_ref()? {
+        datanode_message::Message::HeartBeat(heart_beat) => Some(&heart_beat.datanode_id),
+        datanode_message::Message::ChunkReport(chunk_report) => Some(&chunk_report.datanode_id),
+        datanode_message::Message::StateSync(state_sync) => Some(&state_sync.id),
+    }
+}
+
+// records replica on datanode, replicas of unknown, deleted or stale chunks are sent back for deletion
+fn chunk_received(
+    state: &mut NamenodeState,
+    datanode_id: &str,
+    chunk_id: &str,
+    generation: u64,
+    chunks_to_be_deleted: &mut Vec<String>,
+) {
+    if let Some(chunk_details) = state.chunk_id_to_detail_map.get_mut(chunk_id) {
+        if chunk_details.is_deleted() {
+            chunks_to_be_deleted.push(chunk_id.to_owned());
+            return;
+        }
+        let current_generation = chunk_details.generation;
+        if !chunk_details.add_replica(datanode_id, generation) {
+            warn!(%datanode_id, %chunk_id, generation, current_generation, "Replica with invalid generation scheduled for deletion");
+            chunks_to_be_deleted.push(chunk_id.to_owned());
+        }
+    } else {
+        chunks_to_be_deleted.push(chunk_id.to_owned());
+    }
 }
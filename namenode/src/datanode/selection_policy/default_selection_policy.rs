@@ -1,5 +1,5 @@
-use super::selection_policy::DatanodeSelectionPolicy;
-use crate::namenode_state::NamenodeState;
+use super::policy::DatanodeSelectionPolicy;
+use crate::namenode_state::{NamenodeState, datanode_details::DatanodeDetail};
 use proto::generated::client_namenode::DataNodeMeta;
 use std::{error::Error, sync::Arc};
 use tokio::sync::Mutex;
@@ -20,6 +20,7 @@ impl DatanodeSelectionPolicy for DefaultDatanodeSelectionPolicy {
     async fn get_datanodes_to_store(
         &self,
         chunk_size: u64,
+        _writer_id: &str,
     ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>> {
         let state = self.namenode_state.lock().await;
         let candidates: Vec<DataNodeMeta> = state
@@ -35,35 +36,52 @@ impl DatanodeSelectionPolicy for DefaultDatanodeSelectionPolicy {
         return Ok(candidates);
     }
     #[instrument(name = "policy_datanode_selection_to_serve", skip(self))]
-    async fn get_datanodes_to_serve(&self, chunk_id: &str) -> Result<DataNodeMeta, Box<dyn Error>> {
+    async fn get_datanodes_to_serve(
+        &self,
+        chunk_id: &str,
+        reader_id: &str,
+        reader_host: Option<&str>,
+    ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>> {
         let namenode_state = self.namenode_state.lock().await;
-        let candidate = namenode_state
-            .chunk_id_to_detail_map
-            .get(chunk_id)
-            .expect(&format!(
-                "Error while fetching chunk detals for chunk {chunk_id}"
-            ))
+        let chunk_details = match namenode_state.chunk_id_to_detail_map.get(chunk_id) {
+            Some(chunk_details) => chunk_details,
+            None => return Err(format!("Chunk details not available for {chunk_id}").into()),
+        };
+        let reader = namenode_state.locate_node(reader_id, reader_host);
+        let mut candidates: Vec<&DatanodeDetail> = chunk_details
             .get_locations()
             .iter()
-            .map(|location| namenode_state.datanode_to_detail_map.get(location))
-            .find_map(|datanode_details| {
-                if datanode_details.is_some() && datanode_details.unwrap().is_active() {
-                    return datanode_details;
-                }
-                None
-            })
-            .expect(&format!(
-                "No chunk details available to provide chunk {chunk_id}"
-            ));
-        return Ok(candidate.into());
+            .filter_map(|location| namenode_state.datanode_to_detail_map.get(location))
+            .filter(|datanode_details| datanode_details.can_serve())
+            .collect();
+        if candidates.is_empty() {
+            return Err(format!("No active datanode available to provide chunk {chunk_id}").into());
+        }
+        // when we don't know where reader is every replica is equally far, overloaded nodes and
+        // corrupt replicas are only used when no other replica is left
+        candidates.sort_by_key(|datanode_details| {
+            (
+                chunk_details.is_corrupt(&datanode_details.id),
+                datanode_details.is_stale(),
+                datanode_details.is_overloaded(),
+                reader.map_or(0, |reader| reader.distance(datanode_details)),
+                datanode_details.load(),
+            )
+        });
+        Ok(candidates
+            .into_iter()
+            .map(|datanode_details| datanode_details.into())
+            .collect())
     }
     async fn get_datanodes_to_repair(
         &self,
         chunk_id: &str,
     ) -> Result<(DataNodeMeta, DataNodeMeta), Box<dyn Error>> {
         let state = self.namenode_state.lock().await;
-        // fair assumption that chunk meta is present
-        let chunk_details = state.chunk_id_to_detail_map.get(chunk_id).unwrap();
+        let chunk_details = match state.chunk_id_to_detail_map.get(chunk_id) {
+            Some(chunk_details) => chunk_details,
+            None => return Err(format!("Chunk details not available for {chunk_id}").into()),
+        };
         let locations = chunk_details.get_locations();
         let chunk_size = chunk_details.end_offset - chunk_details.start_offset;
         let target_datanode =
@@ -82,14 +100,17 @@ impl DatanodeSelectionPolicy for DefaultDatanodeSelectionPolicy {
                 Some(target) => target,
                 None => return Err("Datanode with sufficent storage is not available".into()),
             };
-        Ok((
-            state
-                .datanode_to_detail_map
-                .get(&locations[0])
-                .unwrap()
-                .into(),
-            target_datanode.into(),
-        ))
+        // replica on a node in maintenance or a corrupt one can't be used as a source
+        let source_datanode = match locations
+            .iter()
+            .filter(|location| !chunk_details.is_corrupt(location))
+            .filter_map(|location| state.datanode_to_detail_map.get(location))
+            .find(|datanode_details| datanode_details.can_serve())
+        {
+            Some(source) => source,
+            None => return Err("No active datanode available to replicate chunk from".into()),
+        };
+        Ok((source_datanode.into(), target_datanode.into()))
     }
     // choose node with lowest available storage among candidates
     async fn get_datanode_to_offload(
@@ -98,12 +119,22 @@ impl DatanodeSelectionPolicy for DefaultDatanodeSelectionPolicy {
         count: usize,
     ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>> {
         let state = self.namenode_state.lock().await;
+        let chunk_details = match state.chunk_id_to_detail_map.get(chunk_id) {
+            Some(chunk_details) => chunk_details,
+            None => return Err(format!("Chunk details not available for {chunk_id}").into()),
+        };
         // it is a fair assumption that there are more than 3 locations for overreplicated chunk
-        let mut locations = state
-            .chunk_id_to_detail_map
-            .get(chunk_id)
-            .unwrap()
-            .get_locations();
+        let mut locations: Vec<String> = chunk_details
+            .get_locations()
+            .into_iter()
+            // node in maintenance is not reachable so we keep its replica
+            .filter(|location| {
+                state
+                    .datanode_to_detail_map
+                    .get(location)
+                    .is_some_and(|datanode_details| !datanode_details.in_maintenance())
+            })
+            .collect();
         locations.sort_by(|a, b| {
             let available_storage_a = state
                 .datanode_to_detail_map
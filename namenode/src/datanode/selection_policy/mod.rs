@@ -1,2 +1,29 @@
 pub mod default_selection_policy;
-pub mod selection_policy;
+pub mod policy;
+pub mod rack_aware_selection_policy;
+
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    config::{CONFIG, SelectionPolicyKind},
+    namenode_state::NamenodeState,
+};
+use default_selection_policy::DefaultDatanodeSelectionPolicy;
+use policy::DatanodeSelectionPolicy;
+use rack_aware_selection_policy::RackAwareDatanodeSelectionPolicy;
+
+// returns the policy configured for this namenode
+pub fn get_selection_policy(
+    namenode_state: Arc<Mutex<NamenodeState>>,
+) -> Box<dyn DatanodeSelectionPolicy + Send + Sync> {
+    match CONFIG.selection_policy {
+        SelectionPolicyKind::Default => {
+            Box::new(DefaultDatanodeSelectionPolicy::new(namenode_state))
+        }
+        SelectionPolicyKind::RackAware => {
+            Box::new(RackAwareDatanodeSelectionPolicy::new(namenode_state))
+        }
+    }
+}
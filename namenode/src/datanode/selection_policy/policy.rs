@@ -7,8 +7,15 @@ pub trait DatanodeSelectionPolicy {
     async fn get_datanodes_to_store(
         &self,
         chunk_size: u64,
+        writer_id: &str,
+    ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>>;
+    // all live replicas, closest and least loaded first
+    async fn get_datanodes_to_serve(
+        &self,
+        chunk_id: &str,
+        reader_id: &str,
+        reader_host: Option<&str>,
     ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>>;
-    async fn get_datanodes_to_serve(&self, chunk_id: &str) -> Result<DataNodeMeta, Box<dyn Error>>;
     async fn get_datanodes_to_repair(
         &self,
         chunk_id: &str,
@@ -0,0 +1,321 @@
+use super::{
+    default_selection_policy::DefaultDatanodeSelectionPolicy, policy::DatanodeSelectionPolicy,
+};
+use crate::namenode_state::{NamenodeState, datanode_details::DatanodeDetail};
+use proto::generated::client_namenode::DataNodeMeta;
+use std::{cmp::Reverse, collections::HashMap, error::Error, sync::Arc};
+use tokio::sync::Mutex;
+use tonic::async_trait;
+use utilities::logger::{instrument, tracing, warn};
+
+// a rack should not hold more than these many replicas of a chunk
+const MAX_REPLICAS_PER_RACK: usize = 2;
+
+/// Places replicas so that a chunk survives loss of a whole rack.
+/// first replica goes on the writer if it is a datanode, second on a different rack and third
+/// on the same rack as second
+pub struct RackAwareDatanodeSelectionPolicy {
+    namenode_state: Arc<Mutex<NamenodeState>>,
+    // reads are already ordered by distance in default policy so we reuse it
+    default_policy: DefaultDatanodeSelectionPolicy,
+}
+impl RackAwareDatanodeSelectionPolicy {
+    pub fn new(namenode_state: Arc<Mutex<NamenodeState>>) -> Self {
+        Self {
+            default_policy: DefaultDatanodeSelectionPolicy::new(namenode_state.clone()),
+            namenode_state,
+        }
+    }
+}
+
+#[async_trait]
+impl DatanodeSelectionPolicy for RackAwareDatanodeSelectionPolicy {
+    #[instrument(name = "policy_rack_aware_datanode_selection_to_store", skip(self))]
+    async fn get_datanodes_to_store(
+        &self,
+        chunk_size: u64,
+        writer_id: &str,
+    ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>> {
+        let state = self.namenode_state.lock().await;
+        let mut candidates: Vec<&DatanodeDetail> = state
+            .datanode_to_detail_map
+            .values()
+            .filter(|datanode_detail| datanode_detail.can_store(chunk_size))
+            .collect();
+        // emptier nodes first so writes spread out
+        candidates.sort_by_key(|datanode_detail| Reverse(datanode_detail.storage_remaining));
+        let first = match candidates
+            .iter()
+            .find(|datanode_detail| datanode_detail.id == writer_id)
+            .or(candidates.first())
+        {
+            Some(first) => *first,
+            None => return Err("No datanode available to store chunk".into()),
+        };
+        let mut selected = vec![first];
+        let first_location = first.network_location();
+        let second = candidates
+            .iter()
+            .find(|datanode_detail| datanode_detail.network_location() != first_location)
+            .or(candidates
+                .iter()
+                .find(|datanode_detail| datanode_detail.id != first.id));
+        if let Some(second) = second {
+            selected.push(*second);
+            let second_location = second.network_location();
+            let not_selected = |datanode_detail: &&&DatanodeDetail| {
+                datanode_detail.id != first.id && datanode_detail.id != second.id
+            };
+            let third = candidates
+                .iter()
+                .filter(not_selected)
+                .find(|datanode_detail| {
+                    second_location != first_location
+                        && datanode_detail.network_location() == second_location
+                })
+                .or(candidates
+                    .iter()
+                    .filter(not_selected)
+                    .find(|datanode_detail| datanode_detail.network_location() != first_location))
+                .or(candidates.iter().find(not_selected));
+            if let Some(third) = third {
+                selected.push(*third);
+            }
+        }
+        Ok(selected
+            .into_iter()
+            .map(|datanode_detail| datanode_detail.into())
+            .collect())
+    }
+    async fn get_datanodes_to_serve(
+        &self,
+        chunk_id: &str,
+        reader_id: &str,
+        reader_host: Option<&str>,
+    ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>> {
+        self.default_policy
+            .get_datanodes_to_serve(chunk_id, reader_id, reader_host)
+            .await
+    }
+    #[instrument(name = "policy_rack_aware_datanode_selection_to_repair", skip(self))]
+    async fn get_datanodes_to_repair(
+        &self,
+        chunk_id: &str,
+    ) -> Result<(DataNodeMeta, DataNodeMeta), Box<dyn Error>> {
+        let state = self.namenode_state.lock().await;
+        let chunk_details = match state.chunk_id_to_detail_map.get(chunk_id) {
+            Some(chunk_details) => chunk_details,
+            None => return Err(format!("Chunk details not available for {chunk_id}").into()),
+        };
+        let locations = chunk_details.get_locations();
+        let chunk_size = chunk_details.end_offset - chunk_details.start_offset;
+        let replica_holders: Vec<&DatanodeDetail> = locations
+            .iter()
+            .filter_map(|location| state.datanode_to_detail_map.get(location))
+            .collect();
+        let mut replicas_per_rack: HashMap<String, usize> = HashMap::new();
+        for datanode_detail in &replica_holders {
+            *replicas_per_rack
+                .entry(datanode_detail.network_location())
+                .or_default() += 1;
+        }
+        let mut candidates: Vec<&DatanodeDetail> = state
+            .datanode_to_detail_map
+            .values()
+            .filter(|datanode_detail| {
+                datanode_detail.can_store(chunk_size) && !locations.contains(&datanode_detail.id)
+            })
+            .collect();
+        candidates.sort_by_key(|datanode_detail| Reverse(datanode_detail.storage_remaining));
+        // if every replica sits on one rack new one has to go somewhere else
+        let single_rack = replicas_per_rack.len() == 1;
+        let target_datanode = match candidates
+            .iter()
+            .find(|datanode_detail| {
+                let replicas = replicas_per_rack
+                    .get(&datanode_detail.network_location())
+                    .copied()
+                    .unwrap_or_default();
+                if single_rack {
+                    replicas == 0
+                } else {
+                    replicas < MAX_REPLICAS_PER_RACK
+                }
+            })
+            .or_else(|| {
+                // better to have the replica on a busy rack than not to have it at all
+                let fallback = candidates.first();
+                if fallback.is_some() {
+                    warn!(
+                        chunk_id,
+                        "No datanode satisfies rack constraints for repair"
+                    );
+                }
+                fallback
+            }) {
+            Some(target) => *target,
+            None => return Err("Datanode with sufficent storage is not available".into()),
+        };
+        // replica on the target's rack is cheapest to copy from, corrupt ones are never copied
+        let target_location = target_datanode.network_location();
+        let can_be_source = |datanode_details: &&&DatanodeDetail| {
+            datanode_details.can_serve() && !chunk_details.is_corrupt(&datanode_details.id)
+        };
+        let source_datanode = match replica_holders
+            .iter()
+            .filter(can_be_source)
+            .find(|datanode_details| datanode_details.network_location() == target_location)
+            .or(replica_holders.iter().find(can_be_source))
+        {
+            Some(source) => *source,
+            None => return Err("No active datanode available to replicate chunk from".into()),
+        };
+        Ok((source_datanode.into(), target_datanode.into()))
+    }
+    // removes replicas from the most crowded rack first so rack spread is kept
+    async fn get_datanode_to_offload(
+        &self,
+        chunk_id: &str,
+        count: usize,
+    ) -> Result<Vec<DataNodeMeta>, Box<dyn Error>> {
+        let state = self.namenode_state.lock().await;
+        let chunk_details = match state.chunk_id_to_detail_map.get(chunk_id) {
+            Some(chunk_details) => chunk_details,
+            None => return Err(format!("Chunk details not available for {chunk_id}").into()),
+        };
+        let mut replicas_per_rack: HashMap<String, usize> = HashMap::new();
+        // node in maintenance is not reachable so we keep its replica
+        let mut candidates: Vec<&DatanodeDetail> = chunk_details
+            .get_locations()
+            .iter()
+            .filter_map(|location| state.datanode_to_detail_map.get(location))
+            .inspect(|datanode_details| {
+                *replicas_per_rack
+                    .entry(datanode_details.network_location())
+                    .or_default() += 1;
+            })
+            .filter(|datanode_details| !datanode_details.in_maintenance())
+            .collect();
+        let mut offload = vec![];
+        while offload.len() < count && !candidates.is_empty() {
+            let (index, _) = candidates
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| {
+                    replicas_per_rack[&a.network_location()]
+                        .cmp(&replicas_per_rack[&b.network_location()])
+                        .then(b.storage_remaining.cmp(&a.storage_remaining))
+                })
+                .unwrap();
+            let datanode_details = candidates.swap_remove(index);
+            if let Some(replicas) = replicas_per_rack.get_mut(&datanode_details.network_location())
+            {
+                *replicas -= 1;
+            }
+            offload.push(datanode_details.into());
+        }
+        Ok(offload)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::namenode_state::tests::{chunk, datanode, state};
+
+    fn policy(state: NamenodeState) -> RackAwareDatanodeSelectionPolicy {
+        RackAwareDatanodeSelectionPolicy::new(Arc::new(Mutex::new(state)))
+    }
+
+    fn ids(datanodes: Vec<DataNodeMeta>) -> Vec<String> {
+        datanodes.into_iter().map(|datanode| datanode.id).collect()
+    }
+
+    #[tokio::test]
+    async fn store_spreads_replicas_over_racks() {
+        let cluster = || {
+            state(
+                vec![
+                    datanode("n1", "r1", 10_000, 5000),
+                    datanode("n2", "r1", 10_000, 9000),
+                    datanode("n3", "r2", 10_000, 8000),
+                    datanode("n4", "r2", 10_000, 7000),
+                    datanode("n5", "r3", 10_000, 6000),
+                ],
+                vec![],
+            )
+        };
+        // writer gets the first replica, other two share another rack
+        let selected = policy(cluster())
+            .get_datanodes_to_store(100, "n1")
+            .await
+            .unwrap();
+        assert_eq!(ids(selected), vec!["n1", "n3", "n4"]);
+        let selected = policy(cluster())
+            .get_datanodes_to_store(100, "client")
+            .await
+            .unwrap();
+        assert_eq!(ids(selected), vec!["n2", "n3", "n4"]);
+        // single rack still gets all replicas
+        let single_rack = state(
+            vec![
+                datanode("n1", "r1", 10_000, 9000),
+                datanode("n2", "r1", 10_000, 8000),
+                datanode("n3", "r1", 10_000, 7000),
+            ],
+            vec![],
+        );
+        let selected = policy(single_rack)
+            .get_datanodes_to_store(100, "client")
+            .await
+            .unwrap();
+        assert_eq!(ids(selected), vec!["n1", "n2", "n3"]);
+    }
+
+    #[tokio::test]
+    async fn repair_moves_replica_off_full_rack() {
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 10_000, 1000),
+                datanode("n2", "r1", 10_000, 2000),
+                datanode("n3", "r1", 10_000, 9000),
+                datanode("n4", "r2", 10_000, 5000),
+            ],
+            vec![chunk("c1", 100, &["n1", "n2"])],
+        );
+        let (source, target) = policy(cluster).get_datanodes_to_repair("c1").await.unwrap();
+        assert_eq!(target.id, "n4");
+        assert!(["n1", "n2"].contains(&source.id.as_str()));
+        let no_target = state(
+            vec![
+                datanode("n1", "r1", 10_000, 1000),
+                datanode("n2", "r2", 10_000, 2000),
+            ],
+            vec![chunk("c1", 100, &["n1", "n2"])],
+        );
+        assert!(
+            policy(no_target)
+                .get_datanodes_to_repair("c1")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn offload_starts_with_crowded_rack() {
+        let cluster = state(
+            vec![
+                datanode("n1", "r1", 10_000, 9000),
+                datanode("n2", "r1", 10_000, 5000),
+                datanode("n3", "r2", 10_000, 1000),
+                datanode("n4", "r3", 10_000, 1000),
+            ],
+            vec![chunk("c1", 100, &["n1", "n2", "n3", "n4"])],
+        );
+        let offload = policy(cluster)
+            .get_datanode_to_offload("c1", 1)
+            .await
+            .unwrap();
+        assert_eq!(ids(offload), vec!["n2"]);
+    }
+}
@@ -1,52 +1,59 @@
 use proto::generated::{
     client_namenode::DataNodeMeta,
-    namenode_datanode::{
-        DeleteChunkRequest, ReplicateChunkRequest, namenode_datanode_client::NamenodeDatanodeClient,
-    },
+    datanode_namenode::{DeleteCommand, ReplicateCommand, datanode_command::Command},
 };
-use std::{str::FromStr, sync::Arc};
+use std::sync::Arc;
 use tokio::sync::Mutex;
-use tonic::{metadata::MetadataValue, transport::Channel};
 use utilities::{
-    grpc_channel_pool::GRPC_CHANNEL_POOL,
-    logger::{instrument, tracing},
+    logger::{instrument, trace, tracing},
     result::Result,
     ticket::ticket_mint::TicketMint,
 };
 
+use crate::namenode_state::NamenodeState;
+
+/// Sends work to datanodes, commands are queued and delivered with the next heartbeat response
+/// so namenode never has to dial a datanode
 #[derive(Clone)]
 pub struct DatanodeService {
+    namenode_state: Arc<Mutex<NamenodeState>>,
     ticket_mint: Arc<Mutex<TicketMint>>,
 }
 
 impl DatanodeService {
-    pub fn new(ticket_mint: Arc<Mutex<TicketMint>>) -> Self {
-        Self { ticket_mint }
+    pub fn new(
+        namenode_state: Arc<Mutex<NamenodeState>>,
+        ticket_mint: Arc<Mutex<TicketMint>>,
+    ) -> Self {
+        Self {
+            namenode_state,
+            ticket_mint,
+        }
     }
-    async fn get_connection(addrs: &str) -> Result<NamenodeDatanodeClient<Channel>> {
-        let channel = GRPC_CHANNEL_POOL.get_channel(addrs).await.unwrap();
-        Ok(NamenodeDatanodeClient::new(channel))
+    // returns id of the queued command, none when same command is already queued for datanode
+    async fn queue_command(&self, datanode_id: &str, command: Command) -> Result<Option<u64>> {
+        let mut state = self.namenode_state.lock().await;
+        let datanode_details = state
+            .datanode_to_detail_map
+            .get_mut(datanode_id)
+            .ok_or_else(|| format!("Datanode {datanode_id} is not registered"))?;
+        let command_id = datanode_details.commands.push(command);
+        trace!(%datanode_id, ?command_id, "Queued command for datanode");
+        Ok(command_id)
     }
     #[instrument(name = "service_datanode_delete_chunk", skip(self))]
-    pub async fn delete_chunk(&self, datanode_addrs: &str, chunk_id: &str) -> Result<bool> {
-        let mut request = tonic::Request::new(DeleteChunkRequest {
-            id: chunk_id.to_owned(),
-        });
-        let ticket = self.ticket_mint.lock().await.mint_ticket(
-            "namenode",
-            datanode_addrs,
-            utilities::ticket::types::Operation::DeleteChunk {
-                chunk_id: chunk_id.to_string(),
-            },
-        )?;
-        request
-            .metadata_mut()
-            .insert("ticket", MetadataValue::from_str(&ticket)?);
-        let response = Self::get_connection(datanode_addrs).await?
-            .delete_chunk(request).await
-            .map_err(|e|
-         format!("Error while sending the delete message to datanode : {datanode_addrs},  for chunk : {chunk_id}, error : {e}"))?;
-        Ok(response.get_ref().available)
+    pub async fn delete_chunk(
+        &self,
+        datanode_meta: &DataNodeMeta,
+        chunk_id: &str,
+    ) -> Result<Option<u64>> {
+        self.queue_command(
+            &datanode_meta.id,
+            Command::Delete(DeleteCommand {
+                chunk_id: chunk_id.to_owned(),
+            }),
+        )
+        .await
     }
     #[instrument(name = "service_datanode_replicate_chunk", skip(self))]
     pub async fn replicate_chunk(
@@ -54,7 +61,7 @@ impl DatanodeService {
         source_datanode_meta: DataNodeMeta,
         target_datanode_meta: DataNodeMeta,
         chunk_id: &str,
-    ) -> Result<()> {
+    ) -> Result<Option<u64>> {
         let ticket = {
             self.ticket_mint.lock().await.mint_ticket(
                 &source_datanode_meta.id,
@@ -64,32 +71,15 @@ impl DatanodeService {
                 },
             )?
         };
-        let mut request = tonic::Request::new(ReplicateChunkRequest {
-            target_data_node: target_datanode_meta.addrs,
-            chunk_id: chunk_id.to_owned(),
-            ticket,
-        });
-        let namenode_ticket = {
-            self.ticket_mint.lock().await.get_server_ticket(
-                &source_datanode_meta.id,
-                utilities::ticket::types::Operation::ReplicateChunk {
-                    chunk_id: chunk_id.to_string(),
-                },
-            )?
-        };
-        request
-            .metadata_mut()
-            .insert("ticket", MetadataValue::from_str(&namenode_ticket)?);
-        let source_address = source_datanode_meta.addrs;
-        let _ = Self::get_connection(&source_address)
-            .await?
-            .replicate_chunk(request)
-            .await
-            .map_err(|e| {
-                format!(
-                    "Error while sending the replicate chunk message to datanode : {source_address}, {e}"
-                )
-            })?;
-        Ok(())
+        self.queue_command(
+            &source_datanode_meta.id,
+            Command::Replicate(ReplicateCommand {
+                chunk_id: chunk_id.to_owned(),
+                target_data_node: target_datanode_meta.addrs,
+                ticket,
+                target_data_node_id: target_datanode_meta.id,
+            }),
+        )
+        .await
     }
 }
@@ -91,10 +91,18 @@ impl Recorder for DefaultLedger {
         let log = format!("delete_chunk {file_name},{chunk_id}");
         self.insert_log(log).await
     }
+    async fn chunk_generation(&self, chunk_id: &str, generation: u64) {
+        let log = format!("chunk_generation {chunk_id},{generation}");
+        self.insert_log(log).await;
+    }
     async fn generate_key(&self, node_id: &str, key: &str) {
         let log = format!("generate_key {node_id},{key}");
         self.insert_log(log).await;
     }
+    async fn rotate_key(&self, node_id: &str, key_id: u32, key: &str) {
+        let log = format!("rotate_key {node_id},{key_id},{key}");
+        self.insert_log(log).await;
+    }
 }
 
 impl Replayer for DefaultLedger {
@@ -160,10 +168,36 @@ impl Replayer for DefaultLedger {
                             .expect("Delet record found for non existent chunk");
                         chunk_details.mark_deleted();
                     }
+                    "chunk_generation" => {
+                        // it will be of structure chunk_id,generation
+                        let tokens: Vec<&str> = item.split(',').collect();
+                        if tokens.len() < 2 {
+                            error!(%log,"Invalid chunk_generation log format");
+                            return Err("Invalid chunk_generation log".into());
+                        }
+                        let generation: u64 = tokens[1].parse()?;
+                        match state.chunk_id_to_detail_map.get_mut(tokens[0]) {
+                            // bumps of one chunk can reach the ledger out of order
+                            Some(chunk_details) => {
+                                chunk_details.generation = chunk_details.generation.max(generation)
+                            }
+                            None => error!(%log,"Generation found for non existent chunk"),
+                        }
+                    }
                     "generate_key" => {
                         let tokens: Vec<&str> = item.split(',').collect();
                         ticket_mint.add_node_key_with_key(tokens[0], tokens[1])?;
                     }
+                    "rotate_key" => {
+                        // it will be of structure node_id,key_id,key
+                        let tokens: Vec<&str> = item.split(',').collect();
+                        if tokens.len() < 3 {
+                            error!(%log,"Invalid rotate_key log format");
+                            return Err("Invalid rotate_key log".into());
+                        }
+                        let key_id: u32 = tokens[1].parse()?;
+                        ticket_mint.rotate_node_key_with_key(tokens[0], key_id, tokens[2])?;
+                    }
                     _ => {
                         error!(%log,"Log with invalid operation found");
                         return Err("Invalid operation".into());
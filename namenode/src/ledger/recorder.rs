@@ -13,5 +13,7 @@ pub trait Recorder {
     );
     async fn delete_file(&self, file_name: &str);
     async fn delete_chunk(&self, file_name: &str, chunk_id: &str);
+    async fn chunk_generation(&self, chunk_id: &str, generation: u64);
     async fn generate_key(&self, node_id: &str, ticket: &str);
+    async fn rotate_key(&self, node_id: &str, key_id: u32, key: &str);
 }
@@ -1,4 +1,5 @@
 mod api_service;
+mod balancer;
 mod certificates;
 mod chunk_generator;
 mod client_handler;
@@ -7,6 +8,7 @@ mod datanode;
 mod grpc;
 mod ledger;
 mod namenode_state;
+mod version;
 use client_handler::ClientHandler;
 use config::CONFIG;
 use datanode::handler::DatanodeHandler;
@@ -15,7 +17,7 @@ use proto::generated::{
     client_namenode::client_name_node_server::ClientNameNodeServer,
     datanode_namenode::datanode_namenode_server::DatanodeNamenodeServer,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 use tonic::transport::Server;
 use utilities::{
@@ -25,9 +27,11 @@ use utilities::{
 
 use crate::{
     api_service::rocket,
+    balancer::Balancer,
     certificates::certificate_generator::CertificateAuthority,
     grpc::auth::get_auth_intercepter_layer,
     namenode_state::{state_mantainer::StateMantainer, state_snapshot::SnapshotStore},
+    version::NamenodeVersion,
 };
 
 #[tokio::main]
@@ -57,6 +61,16 @@ async fn main() -> Result<()> {
             return Err(e);
         }
     };
+    // version file lives next to ledger as both describe the same namespace
+    let version_file = std::path::Path::new(&CONFIG.ledger_file).with_file_name("VERSION");
+    let namenode_version = match NamenodeVersion::load_or_format(&version_file).await {
+        Ok(v) => v,
+        Err(e) => {
+            error!(error=%e,"Error while reading namenode version Hence shuting down");
+            return Err(e);
+        }
+    };
+    info!(cluster_id = %namenode_version.cluster_id, "Loaded namenode version");
     let ca = match CertificateAuthority::new() {
         Ok(c) => Arc::new(c),
         Err(e) => {
@@ -78,10 +92,16 @@ async fn main() -> Result<()> {
     )
     .await;
     state_mantainer.start();
+    let balancer = Balancer::new(state.clone(), ticket_mint_thrd_safe.clone());
+    if CONFIG.balancer_continuous {
+        info!("Starting balancer in continuous mode");
+        balancer.start(Duration::from_secs(CONFIG.balancer_interval_secs));
+    }
     let rocket_ca = ca.clone();
     // starting API service
     let rocket_ledger = ledger.clone();
     let rocket_ticket_mint = ticket_mint_thrd_safe.clone();
+    let rocket_state = state.clone();
     tokio::spawn(async move {
         info!("Starting : rocket server");
         let result = rocket(
@@ -89,15 +109,25 @@ async fn main() -> Result<()> {
             rocket_ca,
             rocket_ticket_mint,
             Box::new(rocket_ledger),
+            rocket_state,
+            balancer,
         )
         .launch()
         .await;
         error!("Rocket service has returned result : {:?}", result);
     });
     info!("grpc server starting");
-    let root_cert = ca.get_root_cert().pem();
+    // callers are known by the certificate they present, see AuthIntercepter
+    let tls_config = match ca.server_tls_config() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error while creating grpc TLS config {:}", e);
+            return Err(e);
+        }
+    };
     Server::builder()
-        .layer(get_auth_intercepter_layer(&root_cert))
+        .tls_config(tls_config)?
+        .layer(get_auth_intercepter_layer())
         .add_service(ClientNameNodeServer::new(ClientHandler::new(
             state.clone(),
             Box::new(ledger),
@@ -106,6 +136,7 @@ async fn main() -> Result<()> {
         .add_service(DatanodeNamenodeServer::new(DatanodeHandler::new(
             state.clone(),
             ticket_mint_thrd_safe.clone(),
+            namenode_version.cluster_id,
         )))
         .serve(format!("0.0.0.0:{}", CONFIG.internal_grpc_port).parse()?)
         .await?;
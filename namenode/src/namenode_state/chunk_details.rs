@@ -15,6 +15,13 @@ pub struct ChunkDetails {
     pub start_offset: u64,
     pub end_offset: u64,
     pub state: ChunkState,
+    // replicas with an older generation are stale
+    pub generation: u64,
+    // replicas reported corrupt, they stay till good replicas replace them so the chunk never
+    // loses its last copy
+    pub corrupt_locations: HashSet<String>,
+    // client writing the chunk, only it can recover the write pipeline
+    pub writer_id: String,
 }
 
 #[derive(Default, Debug, Clone)]
@@ -34,6 +41,9 @@ impl ChunkDetails {
             start_offset,
             end_offset,
             state: ChunkState::Initialized,
+            generation: 1,
+            corrupt_locations: HashSet::default(),
+            writer_id: String::new(),
         }
     }
     pub fn get_locations(&self) -> Vec<String> {
@@ -42,25 +52,70 @@ impl ChunkDetails {
     pub fn remove_invalid_locations(&mut self, invalid_locations: &HashSet<String>) {
         self.locations
             .retain(|datanode_id| !invalid_locations.contains(datanode_id));
+        self.corrupt_locations
+            .retain(|datanode_id| !invalid_locations.contains(datanode_id));
     }
     pub fn remove_location(&mut self, location: &str) {
         self.locations.remove(location);
+        self.corrupt_locations.remove(location);
+    }
+    // returns false when datanode doesn't hold the chunk
+    pub fn mark_corrupt(&mut self, datanode_id: &str) -> bool {
+        if !self.locations.contains(datanode_id) {
+            return false;
+        }
+        self.corrupt_locations.insert(datanode_id.to_owned());
+        true
+    }
+    pub fn is_corrupt(&self, datanode_id: &str) -> bool {
+        self.corrupt_locations.contains(datanode_id)
+    }
+    // corrupt replicas which can be deleted, none till enough good replicas are there
+    pub fn replaceable_corrupt_locations(&self, draining: &HashSet<String>) -> Vec<String> {
+        if self.good_replicas(draining) < 3 {
+            return vec![];
+        }
+        self.corrupt_locations.iter().cloned().collect()
+    }
+    // returns false for a replica not matching our generation, it is not counted and has to be
+    // deleted. namenode hands out every generation so a newer one is as invalid as a stale one
+    pub fn add_replica(&mut self, datanode_id: &str, generation: u64) -> bool {
+        if generation != self.generation {
+            self.remove_location(datanode_id);
+            return false;
+        }
+        self.add_location(datanode_id);
+        true
     }
     pub fn add_location(&mut self, datanode_id: &str) {
         self.state = ChunkState::Commited;
         self.locations.insert(datanode_id.to_owned());
     }
     pub fn get_replication_status(&self) -> ChunkReplicationStatus {
+        self.replication_status(&HashSet::new())
+    }
+    // replicas on draining nodes can still be read but are not counted towards replication,
+    // neither are corrupt ones
+    pub fn replication_status(&self, draining: &HashSet<String>) -> ChunkReplicationStatus {
         if self.locations.is_empty() {
             return ChunkReplicationStatus::Lost;
         }
-        if self.locations.len() > 3 {
-            return ChunkReplicationStatus::Overreplicated((self.locations.len() - 3) as u8);
-        } else if self.locations.len() < 3 {
-            return ChunkReplicationStatus::Undereplicated((3 - self.locations.len()) as u8);
+        let count = self.good_replicas(draining);
+        if count > 3 {
+            return ChunkReplicationStatus::Overreplicated((count - 3) as u8);
+        } else if count < 3 {
+            return ChunkReplicationStatus::Undereplicated((3 - count) as u8);
         }
         ChunkReplicationStatus::Balanced
     }
+    fn good_replicas(&self, draining: &HashSet<String>) -> usize {
+        self.locations
+            .iter()
+            .filter(|datanode_id| {
+                !draining.contains(*datanode_id) && !self.corrupt_locations.contains(*datanode_id)
+            })
+            .count()
+    }
     pub fn mark_deleted(&mut self) {
         self.state = ChunkState::Deleted(Instant::now())
     }
@@ -74,3 +129,40 @@ impl ChunkDetails {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::namenode_state::tests::chunk;
+
+    #[test]
+    fn corrupt_replica_is_kept_till_replaced() {
+        let no_draining = HashSet::new();
+        let mut chunk_details = chunk("c1", 100, &["n1"]);
+        assert!(chunk_details.mark_corrupt("n1"));
+        assert!(!chunk_details.mark_corrupt("n2"));
+        // last copy stays even though it is not counted
+        assert!(matches!(
+            chunk_details.get_replication_status(),
+            ChunkReplicationStatus::Undereplicated(3)
+        ));
+        assert!(
+            chunk_details
+                .replaceable_corrupt_locations(&no_draining)
+                .is_empty()
+        );
+        for datanode_id in ["n2", "n3", "n4"] {
+            chunk_details.add_location(datanode_id);
+        }
+        assert!(matches!(
+            chunk_details.get_replication_status(),
+            ChunkReplicationStatus::Balanced
+        ));
+        assert_eq!(
+            chunk_details.replaceable_corrupt_locations(&no_draining),
+            vec!["n1".to_owned()]
+        );
+        chunk_details.remove_location("n1");
+        assert!(!chunk_details.is_corrupt("n1"));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+use proto::generated::datanode_namenode::{CommandAck, DatanodeCommand, datanode_command::Command};
+
+/// Commands waiting for a datanode, handed out with its next heartbeat or state sync response
+#[derive(Debug, Clone, Default)]
+pub struct CommandQueue {
+    next_command_id: u64,
+    pending: VecDeque<DatanodeCommand>,
+    // delivered but not acknowledged yet, with the instant it was delivered
+    delivered: HashMap<u64, (DatanodeCommand, Instant)>,
+    // wakes up heartbeat stream of the datanode so commands are pushed right away
+    notify: Arc<Notify>,
+}
+
+impl CommandQueue {
+    // returns none when same command is already waiting or running on the datanode
+    pub fn push(&mut self, command: Command) -> Option<u64> {
+        let queued = self
+            .pending
+            .iter()
+            .chain(self.delivered.values().map(|(queued, _)| queued))
+            .any(|queued| {
+                queued
+                    .command
+                    .as_ref()
+                    .is_some_and(|c| same_work(c, &command))
+            });
+        if queued {
+            return None;
+        }
+        self.next_command_id += 1;
+        self.pending.push_back(DatanodeCommand {
+            id: self.next_command_id,
+            command: Some(command),
+        });
+        self.notify.notify_one();
+        Some(self.next_command_id)
+    }
+    pub fn notifier(&self) -> Arc<Notify> {
+        self.notify.clone()
+    }
+    // moves every pending command to delivered
+    pub fn deliver(&mut self) -> Vec<DatanodeCommand> {
+        let now = Instant::now();
+        self.pending
+            .drain(..)
+            .inspect(|command| {
+                self.delivered.insert(command.id, (command.clone(), now));
+            })
+            .collect()
+    }
+    pub fn ack(&mut self, ack: &CommandAck) -> Option<DatanodeCommand> {
+        self.delivered.remove(&ack.id).map(|(command, _)| command)
+    }
+    // drops delivered commands datanode never acknowledged, work they were doing will be
+    // scheduled again if it is still needed
+    pub fn expire(&mut self, timeout: Duration) -> Vec<DatanodeCommand> {
+        let expired: Vec<u64> = self
+            .delivered
+            .iter()
+            .filter(|(_, (_, delivered_at))| delivered_at.elapsed() > timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.delivered.remove(&id).map(|(command, _)| command))
+            .collect()
+    }
+    // datanode lost whatever it was running when it registers again
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.delivered.clear();
+    }
+    // true when a queued or running command works on the chunk
+    pub fn touches_chunk(&self, chunk_id: &str) -> bool {
+        self.pending
+            .iter()
+            .chain(self.delivered.values().map(|(queued, _)| queued))
+            .any(|queued| match &queued.command {
+                Some(Command::Replicate(replicate)) => replicate.chunk_id == chunk_id,
+                Some(Command::Delete(delete)) => delete.chunk_id == chunk_id,
+                _ => false,
+            })
+    }
+    pub fn count(&self) -> usize {
+        self.pending.len() + self.delivered.len()
+    }
+}
+
+// tickets differ every time so replicate and delete are matched on the chunk only
+fn same_work(a: &Command, b: &Command) -> bool {
+    match (a, b) {
+        (Command::Replicate(a), Command::Replicate(b)) => a.chunk_id == b.chunk_id,
+        (Command::Delete(a), Command::Delete(b)) => a.chunk_id == b.chunk_id,
+        (Command::Reregister(_), Command::Reregister(_)) => true,
+        (Command::Shutdown(_), Command::Shutdown(_)) => true,
+        _ => false,
+    }
+}
@@ -1,11 +1,86 @@
-use std::time::{Duration, Instant};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
-use proto::generated::client_namenode::DataNodeMeta;
+use proto::generated::{
+    client_namenode::DataNodeMeta,
+    datanode_namenode::{ScanProgress, VolumeReport},
+};
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+use super::command_queue::CommandQueue;
+use crate::config::CONFIG;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DatanodeState {
+    // connected but no heartbeat received yet
+    Registered,
     Active,
-    Inactive(Instant),
+    // missed a few heartbeats, reads are deprioritized but replicas are not repaired yet
+    Stale,
+    // replicas on this node are re-replicated somewhere else
+    Dead,
+    // taken out by admin, still serves its replicas till they are re-created on other nodes
+    Decommissioning,
+    // taken out of the cluster by admin, stays out till it is recommissioned
+    Decommissioned,
+    // node is expected to go down for a short while, holds the instant maintenance will expire
+    Maintenance(Instant),
+}
+impl DatanodeState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DatanodeState::Registered => "Registered",
+            DatanodeState::Active => "Active",
+            DatanodeState::Stale => "Stale",
+            DatanodeState::Dead => "Dead",
+            DatanodeState::Decommissioning => "Decommissioning",
+            DatanodeState::Decommissioned => "Decommissioned",
+            DatanodeState::Maintenance(_) => "Maintenance",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Serialize)]
+pub struct VolumeDetail {
+    pub path: String,
+    pub capacity: u64,
+    pub used: u64,
+    pub available: u64,
+    pub failed: bool,
+}
+impl From<VolumeReport> for VolumeDetail {
+    fn from(value: VolumeReport) -> Self {
+        Self {
+            path: value.path,
+            capacity: value.capacity,
+            used: value.used,
+            available: value.available,
+            failed: value.failed,
+        }
+    }
+}
+
+// progress of the block scanner on a datanode, times are unix secs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanDetail {
+    pub chunks_scanned: u64,
+    pub chunks_total: u64,
+    pub corrupt_found: u64,
+    pub pass_started_at: u64,
+    pub last_pass_finished_at: u64,
+}
+impl From<ScanProgress> for ScanDetail {
+    fn from(value: ScanProgress) -> Self {
+        Self {
+            chunks_scanned: value.chunks_scanned,
+            chunks_total: value.chunks_total,
+            corrupt_found: value.corrupt_found,
+            pass_started_at: value.pass_started_at,
+            last_pass_finished_at: value.last_pass_finished_at,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -13,37 +88,226 @@ pub struct DatanodeDetail {
     pub id: String,
     pub name: String,
     pub addrs: String,
+    pub rack: String,
+    pub zone: String,
+    // identity of the storage directory node registered with
+    pub storage_id: String,
     pub storage_remaining: u64,
+    pub storage_capacity: u64,
+    // tcp transfers in progress as reported in last heartbeat
+    pub active_transfers: u64,
+    pub active_reads: u64,
+    pub active_writes: u64,
+    pub pending_replications: u64,
+    pub volumes: Vec<VolumeDetail>,
+    pub recent_disk_errors: u64,
+    pub scan_progress: ScanDetail,
+    // chunk id to unix secs block scanner last verified the replica
+    pub chunk_last_verified: HashMap<String, u64>,
     pub hearbeat_instant: Instant,
+    pub registered_at: Instant,
     pub state: DatanodeState,
+    // sequence of last chunk report applied, none till we get a full report
+    pub last_report_sequence: Option<u64>,
+    // work for the datanode, delivered with heartbeat responses
+    pub commands: CommandQueue,
+    // heartbeat stream broke, node is stale till it heartbeats again
+    pub disconnected: bool,
 }
 
 impl DatanodeDetail {
-    pub fn new(id: String, name: String, addrs: String) -> Self {
+    pub fn new(
+        id: String,
+        name: String,
+        addrs: String,
+        rack: String,
+        zone: String,
+        storage_id: String,
+    ) -> Self {
         Self {
             id,
             name,
             addrs,
+            rack,
+            zone,
+            storage_id,
             storage_remaining: 0,
+            storage_capacity: 0,
+            active_transfers: 0,
+            active_reads: 0,
+            active_writes: 0,
+            pending_replications: 0,
+            volumes: vec![],
+            recent_disk_errors: 0,
+            scan_progress: ScanDetail::default(),
+            chunk_last_verified: HashMap::new(),
             hearbeat_instant: Instant::now(),
-            state: DatanodeState::Active,
+            registered_at: Instant::now(),
+            state: DatanodeState::Registered,
+            last_report_sequence: None,
+            commands: CommandQueue::default(),
+            disconnected: false,
+        }
+    }
+    // rack name is only unique inside a zone
+    pub fn network_location(&self) -> String {
+        format!("/{}/{}", self.zone, self.rack)
+    }
+    pub fn host(&self) -> &str {
+        let addrs = self
+            .addrs
+            .split_once("://")
+            .map_or(self.addrs.as_str(), |(_, rest)| rest);
+        addrs.split([':', '/']).next().unwrap_or(addrs)
+    }
+    // hdfs style distance, 0 for same node, 2 same rack, 4 same zone and 6 otherwise
+    pub fn distance(&self, other: &DatanodeDetail) -> u8 {
+        if self.id == other.id {
+            0
+        } else if self.network_location() == other.network_location() {
+            2
+        } else if self.zone == other.zone {
+            4
+        } else {
+            6
         }
     }
     pub fn mark_heartbeat(&mut self) {
         self.hearbeat_instant = Instant::now();
+        self.disconnected = false;
     }
-    pub fn sync_state(&mut self, storage_remaining: u64) {
+    pub fn sync_state(&mut self, storage_remaining: u64, storage_capacity: u64) {
         self.storage_remaining = storage_remaining;
+        self.storage_capacity = storage_capacity;
         self.hearbeat_instant = Instant::now();
     }
+    // incremental reports are applied on top of this one
+    pub fn full_report_received(&mut self, sequence: u64) {
+        self.last_report_sequence = Some(sequence);
+    }
+    // true when incremental report follows the last one applied, a missed, repeated or out of
+    // order report means node has to send a full report
+    pub fn accept_report_sequence(&mut self, sequence: u64) -> bool {
+        let in_order = self
+            .last_report_sequence
+            .is_some_and(|last| last + 1 == sequence);
+        // once a report is missed we keep asking for full report till we get one
+        self.last_report_sequence = in_order.then_some(sequence);
+        in_order
+    }
+    pub fn register(&mut self) {
+        self.registered_at = Instant::now();
+        self.last_report_sequence = None;
+        self.commands.clear();
+        self.disconnected = false;
+        self.state = DatanodeState::Registered;
+    }
     pub fn is_active(&self) -> bool {
-        if self.hearbeat_instant.elapsed() > Duration::from_secs(6) {
-            return false;
+        self.state == DatanodeState::Active
+    }
+    pub fn is_stale(&self) -> bool {
+        self.state == DatanodeState::Stale
+    }
+    pub fn is_decommissioning(&self) -> bool {
+        self.state == DatanodeState::Decommissioning
+    }
+    // replicas on these nodes are no longer counted
+    pub fn is_dead(&self) -> bool {
+        matches!(
+            self.state,
+            DatanodeState::Dead | DatanodeState::Decommissioned
+        )
+    }
+    // state the node should move to based on its last heartbeat, none if it should stay as it is
+    pub fn next_state(&self, stale_after: Duration, dead_after: Duration) -> Option<DatanodeState> {
+        let since_heartbeat = self.hearbeat_instant.elapsed();
+        let next_state = match self.state {
+            DatanodeState::Decommissioned => return None,
+            // node died while draining, its replicas are repaired from other nodes
+            DatanodeState::Decommissioning if since_heartbeat > dead_after => {
+                DatanodeState::Decommissioned
+            }
+            DatanodeState::Decommissioning => return None,
+            DatanodeState::Maintenance(expires_at) if expires_at > Instant::now() => return None,
+            DatanodeState::Registered if self.hearbeat_instant <= self.registered_at => {
+                if self.registered_at.elapsed() > dead_after {
+                    DatanodeState::Dead
+                } else {
+                    return None;
+                }
+            }
+            _ if since_heartbeat > dead_after => DatanodeState::Dead,
+            _ if since_heartbeat > stale_after || self.disconnected => DatanodeState::Stale,
+            _ => DatanodeState::Active,
+        };
+        if next_state == self.state {
+            return None;
         }
-        true
+        Some(next_state)
     }
+    // fraction of capacity already in use, none till datanode reports its capacity
+    pub fn utilization(&self) -> Option<f64> {
+        if self.storage_capacity == 0 {
+            return None;
+        }
+        let used = self.storage_capacity.saturating_sub(self.storage_remaining);
+        Some(used as f64 / self.storage_capacity as f64)
+    }
+    // work datanode is doing right now as reported in last heartbeat
+    pub fn load(&self) -> u64 {
+        self.active_transfers + self.pending_replications
+    }
+    pub fn is_overloaded(&self) -> bool {
+        self.load() >= CONFIG.max_datanode_load
+    }
+    pub fn has_disk_errors(&self) -> bool {
+        self.recent_disk_errors >= CONFIG.max_recent_disk_errors
+    }
+    // writes only go to healthy nodes which are heartbeating regularly
     pub fn can_store(&self, chunk_size: u64) -> bool {
-        self.is_active() && self.storage_remaining > chunk_size
+        self.is_active()
+            && !self.is_overloaded()
+            && !self.has_disk_errors()
+            && self.storage_remaining > chunk_size
+    }
+    // node in maintenance is kept out of reads and writes even if it is still sending heartbeats,
+    // a decommissioning node is kept for reads and as a repair source
+    pub fn can_serve(&self) -> bool {
+        self.is_active() || self.is_stale() || self.is_decommissioning()
+    }
+    // node is marked decommissioned by state mantainer once none of its chunks is undereplicated
+    pub fn decommission(&mut self) {
+        if self.state != DatanodeState::Decommissioned {
+            self.state = DatanodeState::Decommissioning;
+        }
+    }
+    pub fn start_maintenance(&mut self, duration: Duration) -> Result<(), String> {
+        if !(self.is_active() || self.is_stale()) {
+            return Err(format!(
+                "Datanode is {}, only active or stale datanode can enter maintenance",
+                self.state.name()
+            ));
+        }
+        self.state = DatanodeState::Maintenance(Instant::now() + duration);
+        Ok(())
+    }
+    // node becomes active again once state mantainer sees its heartbeat
+    pub fn end_maintenance(&mut self) {
+        self.register();
+    }
+    pub fn in_maintenance(&self) -> bool {
+        match self.state {
+            DatanodeState::Maintenance(expires_at) => expires_at > Instant::now(),
+            _ => false,
+        }
+    }
+    pub fn maintenance_remaining(&self) -> Option<Duration> {
+        match self.state {
+            DatanodeState::Maintenance(expires_at) => {
+                Some(expires_at.saturating_duration_since(Instant::now()))
+            }
+            _ => None,
+        }
     }
 }
 
@@ -56,3 +320,102 @@ impl From<&DatanodeDetail> for DataNodeMeta {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::namenode_state::tests::datanode;
+
+    const STALE_AFTER: Duration = Duration::from_secs(10);
+    const DEAD_AFTER: Duration = Duration::from_secs(30);
+
+    fn secs_ago(secs: u64) -> Instant {
+        Instant::now() - Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn next_state_follows_heartbeats() {
+        let mut datanode_details = datanode("n1", "r1", 0, 0);
+        datanode_details.register();
+        datanode_details.hearbeat_instant = secs_ago(60);
+        assert_eq!(datanode_details.next_state(STALE_AFTER, DEAD_AFTER), None);
+        datanode_details.mark_heartbeat();
+        assert_eq!(
+            datanode_details.next_state(STALE_AFTER, DEAD_AFTER),
+            Some(DatanodeState::Active)
+        );
+        datanode_details.state = DatanodeState::Active;
+        datanode_details.hearbeat_instant = secs_ago(15);
+        assert_eq!(
+            datanode_details.next_state(STALE_AFTER, DEAD_AFTER),
+            Some(DatanodeState::Stale)
+        );
+        datanode_details.hearbeat_instant = secs_ago(40);
+        assert_eq!(
+            datanode_details.next_state(STALE_AFTER, DEAD_AFTER),
+            Some(DatanodeState::Dead)
+        );
+        // broken heartbeat stream doesn't wait for heartbeats to be missed
+        datanode_details.mark_heartbeat();
+        datanode_details.disconnected = true;
+        assert_eq!(
+            datanode_details.next_state(STALE_AFTER, DEAD_AFTER),
+            Some(DatanodeState::Stale)
+        );
+        // node registered long ago without ever heartbeating is dead
+        datanode_details.register();
+        datanode_details.registered_at = secs_ago(40);
+        datanode_details.hearbeat_instant = secs_ago(60);
+        assert_eq!(
+            datanode_details.next_state(STALE_AFTER, DEAD_AFTER),
+            Some(DatanodeState::Dead)
+        );
+    }
+
+    #[test]
+    fn decommissioning_node_drains_first() {
+        let mut datanode_details = datanode("n1", "r1", 1000, 1000);
+        datanode_details.decommission();
+        assert!(datanode_details.is_decommissioning());
+        assert!(datanode_details.can_serve());
+        assert!(!datanode_details.can_store(0));
+        datanode_details.hearbeat_instant = secs_ago(15);
+        assert_eq!(datanode_details.next_state(STALE_AFTER, DEAD_AFTER), None);
+        datanode_details.hearbeat_instant = secs_ago(40);
+        assert_eq!(
+            datanode_details.next_state(STALE_AFTER, DEAD_AFTER),
+            Some(DatanodeState::Decommissioned)
+        );
+        // decommissioning again doesn't bring back a decommissioned node
+        datanode_details.state = DatanodeState::Decommissioned;
+        datanode_details.decommission();
+        assert_eq!(datanode_details.state, DatanodeState::Decommissioned);
+        assert!(datanode_details.is_dead());
+    }
+
+    #[test]
+    fn maintenance_only_from_active_or_stale() {
+        let mut datanode_details = datanode("n1", "r1", 0, 0);
+        assert!(datanode_details.start_maintenance(STALE_AFTER).is_ok());
+        assert!(datanode_details.in_maintenance());
+        datanode_details.state = DatanodeState::Dead;
+        assert!(datanode_details.start_maintenance(STALE_AFTER).is_err());
+        assert_eq!(datanode_details.state, DatanodeState::Dead);
+    }
+
+    #[test]
+    fn report_sequence_requires_full_report_after_gap() {
+        let mut datanode_details = datanode("n1", "r1", 0, 0);
+        // nothing to apply deltas on before first full report
+        assert!(!datanode_details.accept_report_sequence(1));
+        datanode_details.full_report_received(1);
+        assert!(datanode_details.accept_report_sequence(2));
+        assert!(!datanode_details.accept_report_sequence(2));
+        // once out of order, reports are refused till next full report
+        assert!(!datanode_details.accept_report_sequence(3));
+        datanode_details.full_report_received(5);
+        assert!(datanode_details.accept_report_sequence(6));
+        datanode_details.register();
+        assert!(!datanode_details.accept_report_sequence(7));
+    }
+}
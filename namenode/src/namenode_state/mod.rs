@@ -1,4 +1,5 @@
 pub mod chunk_details;
+pub mod command_queue;
 pub mod datanode_details;
 pub mod state_mantainer;
 pub mod state_snapshot;
@@ -19,4 +20,67 @@ impl NamenodeState {
             datanode_to_detail_map: HashMap::default(),
         }
     }
+    // datanode on which the node is running, matched by id or by host
+    pub fn locate_node(
+        &self,
+        node_id: &str,
+        host: Option<&str>,
+    ) -> Option<&datanode_details::DatanodeDetail> {
+        self.datanode_to_detail_map.get(node_id).or_else(|| {
+            host.and_then(|host| {
+                self.datanode_to_detail_map
+                    .values()
+                    .find(|datanode_details| datanode_details.host() == host)
+            })
+        })
+    }
+    // true when some datanode is already replicating or deleting the chunk
+    pub fn has_pending_command(&self, chunk_id: &str) -> bool {
+        self.datanode_to_detail_map
+            .values()
+            .any(|datanode_details| datanode_details.commands.touches_chunk(chunk_id))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use chunk_details::ChunkDetails;
+    use datanode_details::{DatanodeDetail, DatanodeState};
+
+    pub fn datanode(id: &str, rack: &str, capacity: u64, remaining: u64) -> DatanodeDetail {
+        let mut datanode_details = DatanodeDetail::new(
+            id.to_owned(),
+            id.to_owned(),
+            format!("http://{id}:7000"),
+            rack.to_owned(),
+            "zone".to_owned(),
+            String::new(),
+        );
+        datanode_details.storage_capacity = capacity;
+        datanode_details.storage_remaining = remaining;
+        datanode_details.state = DatanodeState::Active;
+        datanode_details
+    }
+    pub fn chunk(id: &str, size: u64, locations: &[&str]) -> ChunkDetails {
+        let mut chunk_details = ChunkDetails::new(id.to_owned(), 0, size);
+        for location in locations {
+            chunk_details.add_location(location);
+        }
+        chunk_details
+    }
+    pub fn state(datanodes: Vec<DatanodeDetail>, chunks: Vec<ChunkDetails>) -> NamenodeState {
+        let mut state = NamenodeState::new();
+        for datanode_details in datanodes {
+            state
+                .datanode_to_detail_map
+                .insert(datanode_details.id.clone(), datanode_details);
+        }
+        for chunk_details in chunks {
+            state
+                .chunk_id_to_detail_map
+                .insert(chunk_details.id.clone(), chunk_details);
+        }
+        state
+    }
 }
@@ -2,15 +2,17 @@ use std::collections::HashSet;
 use std::{sync::Arc, time::Duration};
 
 use futures::future::join_all;
+use proto::generated::datanode_namenode::{DeleteCommand, datanode_command::Command};
 use tokio::{sync::Mutex, time::interval};
-use utilities::logger::{Level, debug, error, span};
+use utilities::logger::{Level, debug, error, info, span, warn};
 
 use crate::config::CONFIG;
-use crate::datanode::selection_policy::default_selection_policy::DefaultDatanodeSelectionPolicy;
-use crate::datanode::selection_policy::selection_policy::DatanodeSelectionPolicy;
+use crate::datanode::selection_policy::get_selection_policy;
+use crate::datanode::selection_policy::policy::DatanodeSelectionPolicy;
 use crate::datanode::service::DatanodeService;
 use crate::namenode_state::NamenodeState;
-use crate::namenode_state::chunk_details::ChunkReplicationStatus;
+use crate::namenode_state::chunk_details::{ChunkReplicationStatus, ChunkState};
+use crate::namenode_state::datanode_details::DatanodeState;
 use crate::namenode_state::state_snapshot::{NamenodeStateSnapshot, SnapshotStore};
 use std::path;
 use utilities::state_logger;
@@ -44,9 +46,9 @@ impl StateMantainer {
         .map_err(|e| format!("Error while creating a state logger {e}"))
         .unwrap();
         Self {
-            datanode_service: DatanodeService::new(ticket_mint),
-            datanode_selection_policy: Arc::new(Mutex::new(Box::new(
-                DefaultDatanodeSelectionPolicy::new(namenode_state.clone()),
+            datanode_service: DatanodeService::new(namenode_state.clone(), ticket_mint),
+            datanode_selection_policy: Arc::new(Mutex::new(get_selection_policy(
+                namenode_state.clone(),
             ))),
             namenode_state,
             snapshot_sender: tx,
@@ -57,9 +59,14 @@ impl StateMantainer {
     fn handle_undereplicated_chunk(&self, chunk_id: &str) {
         let datanode_service = self.datanode_service.clone();
         let datanode_selection_policy = self.datanode_selection_policy.clone();
+        let namenode_state = self.namenode_state.clone();
         let chunk_id = chunk_id.to_owned();
 
         tokio::spawn(async move {
+            // a datanode is already working on it, its result shows up in the next report
+            if namenode_state.lock().await.has_pending_command(&chunk_id) {
+                return;
+            }
             let datanode_selection_policy = datanode_selection_policy.lock().await;
             let datanode_pair = match datanode_selection_policy
                 .get_datanodes_to_repair(&chunk_id)
@@ -76,7 +83,7 @@ impl StateMantainer {
                 .replicate_chunk(datanode_pair.0, datanode_pair.1, &chunk_id)
                 .await
             {
-                error!("Error happend while replicting, {e}");
+                error!("Error happend while queueing replication, {e}");
             }
         });
     }
@@ -85,7 +92,11 @@ impl StateMantainer {
         let chunk_id = chunk_id.to_owned();
         let datanode_selection_policy = self.datanode_selection_policy.clone();
         let datanode_service = self.datanode_service.clone();
+        let namenode_state = self.namenode_state.clone();
         tokio::spawn(async move {
+            if namenode_state.lock().await.has_pending_command(&chunk_id) {
+                return;
+            }
             let datanode_selection_policy = datanode_selection_policy.lock().await;
             let datanodes_to_offload = match datanode_selection_policy
                 .get_datanode_to_offload(&chunk_id, count as usize)
@@ -98,11 +109,15 @@ impl StateMantainer {
                 }
             };
             drop(datanode_selection_policy);
-            // we fire send delete request to all of these
+            // we queue delete command for all of these
             let delete_chunk_futures = datanodes_to_offload
                 .iter()
-                .map(|candidate| datanode_service.delete_chunk(&candidate.addrs, &chunk_id));
-            join_all(delete_chunk_futures).await;
+                .map(|candidate| datanode_service.delete_chunk(candidate, &chunk_id));
+            for result in join_all(delete_chunk_futures).await {
+                if let Err(e) = result {
+                    error!("Error happend while queueing delete, {e}");
+                }
+            }
         });
     }
     pub fn start(self) {
@@ -125,24 +140,72 @@ impl StateMantainer {
                     }
                     true
                 });
+                // moving datanodes through their lifecycle based on last heartbeat, once
+                // maintenance window is over node is handled as any other node
+                let stale_after = Duration::from_secs(CONFIG.stale_interval_secs);
+                let dead_after = Duration::from_secs(CONFIG.dead_interval_secs);
+                let command_timeout = Duration::from_secs(CONFIG.command_timeout_secs);
+                state.datanode_to_detail_map.iter_mut().for_each(
+                    |(datanode_id, datanode_details)| {
+                        if let Some(next_state) =
+                            datanode_details.next_state(stale_after, dead_after)
+                        {
+                            info!(
+                                %datanode_id,
+                                from = datanode_details.state.name(),
+                                to = next_state.name(),
+                                "Datanode state changed"
+                            );
+                            datanode_details.state = next_state;
+                        }
+                        for command in datanode_details.commands.expire(command_timeout) {
+                            warn!(%datanode_id, ?command, "Command not acknowledged by datanode in time, dropping it");
+                        }
+                    },
+                );
                 // checking the undereplicated and overreplicated ChunkState
+                // replicas on stale node or node in maintenance still count so we don't trigger
+                // repair for them
                 let inactive_datanodes: HashSet<String> = state
                     .datanode_to_detail_map
                     .iter()
                     .filter_map(|(datanode_id, datanode_details)| {
-                        if !datanode_details.is_active() {
+                        if datanode_details.is_dead() {
                             Some(datanode_id.to_owned())
                         } else {
                             None
                         }
                     })
                     .collect();
+                // replicas on decommissioning nodes are re-created elsewhere, node is kept as a
+                // source till none of its chunks is undereplicated
+                let draining_datanodes: HashSet<String> = state
+                    .datanode_to_detail_map
+                    .iter()
+                    .filter(|(_, datanode_details)| datanode_details.is_decommissioning())
+                    .map(|(datanode_id, _)| datanode_id.to_owned())
+                    .collect();
+                let mut undrained_datanodes = HashSet::new();
+                // (datanode id, chunk id) of corrupt replicas good ones have replaced
+                let mut replaced_corrupt_replicas = vec![];
                 state
                     .chunk_id_to_detail_map
                     .iter_mut()
                     .for_each(|(chunk_id, chunk_details)| {
                         chunk_details.remove_invalid_locations(&inactive_datanodes);
-                        match chunk_details.get_replication_status() {
+                        let replication_status =
+                            chunk_details.replication_status(&draining_datanodes);
+                        if let ChunkReplicationStatus::Undereplicated(_) = replication_status
+                            && !matches!(chunk_details.state, ChunkState::Deleted(_))
+                        {
+                            undrained_datanodes.extend(
+                                chunk_details
+                                    .locations
+                                    .intersection(&draining_datanodes)
+                                    .cloned(),
+                            );
+                        }
+                        match replication_status {
                             ChunkReplicationStatus::Undereplicated(_) => {
                                 // we skipping the count here we will increase replication by 1
                                 self.handle_undereplicated_chunk(chunk_id);
@@ -155,7 +218,31 @@ impl StateMantainer {
                             }
                             ChunkReplicationStatus::Balanced => {}
                         }
+                        for datanode_id in
+                            chunk_details.replaceable_corrupt_locations(&draining_datanodes)
+                        {
+                            chunk_details.remove_location(&datanode_id);
+                            replaced_corrupt_replicas.push((datanode_id, chunk_id.to_owned()));
+                        }
                     });
+                for (datanode_id, chunk_id) in replaced_corrupt_replicas {
+                    if let Some(datanode_details) =
+                        state.datanode_to_detail_map.get_mut(&datanode_id)
+                    {
+                        info!(%datanode_id, %chunk_id, "Chunk repaired, deleting corrupt replica");
+                        datanode_details
+                            .commands
+                            .push(Command::Delete(DeleteCommand { chunk_id }));
+                    }
+                }
+                for datanode_id in draining_datanodes.difference(&undrained_datanodes) {
+                    if let Some(datanode_details) =
+                        state.datanode_to_detail_map.get_mut(datanode_id)
+                    {
+                        info!(%datanode_id, "Datanode drained, marking it decommissioned");
+                        datanode_details.state = DatanodeState::Decommissioned;
+                    }
+                }
                 debug!("Sending state to state logger");
                 self.snapshot_store
                     .update_snapshot(state.clone().into())
@@ -1,20 +1,47 @@
-use crate::namenode_state::{NamenodeState, chunk_details, datanode_details::DatanodeDetail};
+use crate::namenode_state::{
+    NamenodeState, chunk_details,
+    datanode_details::{DatanodeDetail, VolumeDetail},
+};
 use serde::Serialize;
 use std::{collections::HashMap, hash::Hash, time::SystemTime};
 
 #[derive(Clone, Debug, Hash, PartialEq, Serialize)]
 pub struct DatanodeStateSnapshot {
     pub is_active: bool,
+    pub state: String,
+    pub in_maintenance: bool,
     pub storage_remaining: u64,
+    pub storage_capacity: u64,
+    pub active_transfers: u64,
+    pub active_reads: u64,
+    pub active_writes: u64,
+    pub pending_replications: u64,
+    pub volumes: Vec<VolumeDetail>,
+    pub recent_disk_errors: u64,
+    pub overloaded: bool,
     pub addrs: String,
+    pub network_location: String,
+    pub queued_commands: usize,
 }
 
 impl From<DatanodeDetail> for DatanodeStateSnapshot {
     fn from(value: DatanodeDetail) -> Self {
         Self {
             is_active: value.is_active(),
+            state: value.state.name().to_owned(),
+            in_maintenance: value.in_maintenance(),
             storage_remaining: value.storage_remaining,
+            storage_capacity: value.storage_capacity,
+            active_transfers: value.active_transfers,
+            active_reads: value.active_reads,
+            active_writes: value.active_writes,
+            pending_replications: value.pending_replications,
+            recent_disk_errors: value.recent_disk_errors,
+            overloaded: value.is_overloaded(),
+            volumes: value.volumes.clone(),
             addrs: value.addrs.clone(),
+            network_location: value.network_location(),
+            queued_commands: value.commands.count(),
         }
     }
 }
@@ -0,0 +1,33 @@
+use std::path::Path;
+
+use tokio::fs;
+use utilities::{logger::info, result::Result};
+
+/// Identity of the namespace, created when namenode starts for the first time (formatted).
+/// datanodes remember it so they can't join another cluster
+#[derive(Clone, Debug)]
+pub struct NamenodeVersion {
+    pub cluster_id: String,
+}
+
+impl NamenodeVersion {
+    pub async fn load_or_format(path: &Path) -> Result<Self> {
+        if fs::try_exists(path).await? {
+            let content = fs::read_to_string(path).await?;
+            let cluster_id = content
+                .lines()
+                .find_map(|line| line.strip_prefix("cluster_id="))
+                .ok_or(format!("cluster_id missing in version file {path:?}"))?
+                .trim()
+                .to_owned();
+            return Ok(Self { cluster_id });
+        }
+        let cluster_id = format!("CID-{}", uuid::Uuid::new_v4());
+        info!(%cluster_id, ?path, "Formatting namenode with new cluster id");
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, format!("cluster_id={cluster_id}\n")).await?;
+        Ok(Self { cluster_id })
+    }
+}
@@ -15,6 +15,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 "client_namenode.proto",
                 "namenode_datanode.proto",
                 "datanode_namenode.proto",
+                "data_transfer.proto",
             ],
             &["."],
         )?;
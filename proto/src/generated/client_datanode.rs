@@ -20,6 +20,12 @@ pub struct StoreChunkRequest {
 pub struct StoreChunkResponse {
     #[prost(string, tag = "1")]
     pub address: ::prost::alloc::string::String,
+    /// bytes every datanode of the pipeline already has staged, write resumes from here
+    #[prost(uint64, tag = "2")]
+    pub staged_bytes: u64,
+    /// datanode ids the pipeline could be set up to, in order
+    #[prost(string, repeated, tag = "3")]
+    pub pipeline: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FetchChunkRequest {
@@ -35,6 +41,8 @@ pub struct FetchChunkResponse {
 pub struct CommitChunkRequest {
     #[prost(string, tag = "1")]
     pub chunk_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub generation: u64,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct CommitChunkResponse {
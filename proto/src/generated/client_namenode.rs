@@ -20,6 +20,12 @@ pub struct ChunkMeta {
     pub location: ::prost::alloc::vec::Vec<DataNodeMeta>,
     #[prost(string, tag = "5")]
     pub ticket: ::prost::alloc::string::String,
+    /// ticket for every location in same order, only set while fetching
+    #[prost(string, repeated, tag = "6")]
+    pub location_tickets: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// generation stamp replicas have to be committed with
+    #[prost(uint64, tag = "7")]
+    pub generation: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct FetchFileRequest {
@@ -57,6 +63,42 @@ pub struct DeleteFileResponse {
     #[prost(bool, tag = "1")]
     pub file_present: bool,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReportBadChunkRequest {
+    #[prost(string, tag = "1")]
+    pub chunk_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub datanode_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub reason: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ReportBadChunkResponse {
+    /// false when namenode doesn't know the replica, a suspect replica is deleted only after good
+    /// replicas replaced it
+    #[prost(bool, tag = "1")]
+    pub marked_corrupt: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RecoverPipelineRequest {
+    #[prost(string, tag = "1")]
+    pub chunk_id: ::prost::alloc::string::String,
+    /// datanode ids in pipeline order
+    #[prost(string, repeated, tag = "2")]
+    pub surviving: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "3")]
+    pub failed: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RecoverPipelineResponse {
+    #[prost(uint64, tag = "1")]
+    pub generation: u64,
+    #[prost(message, repeated, tag = "2")]
+    pub location: ::prost::alloc::vec::Vec<DataNodeMeta>,
+    /// store ticket for the first surviving datanode
+    #[prost(string, tag = "3")]
+    pub ticket: ::prost::alloc::string::String,
+}
 /// Generated client implementations.
 pub mod client_name_node_client {
     #![allow(
@@ -220,6 +262,60 @@ pub mod client_name_node_client {
                 .insert(GrpcMethod::new("client_namenode.ClientNameNode", "DeleteFile"));
             self.inner.unary(req, path, codec).await
         }
+        /// replica failed checksum verification or could not be read by the client
+        pub async fn report_bad_chunk(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ReportBadChunkRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ReportBadChunkResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/client_namenode.ClientNameNode/ReportBadChunk",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("client_namenode.ClientNameNode", "ReportBadChunk"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        /// datanode of a write pipeline failed, chunk gets a new generation so its replica turns stale
+        pub async fn recover_pipeline(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RecoverPipelineRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RecoverPipelineResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/client_namenode.ClientNameNode/RecoverPipeline",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("client_namenode.ClientNameNode", "RecoverPipeline"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -256,6 +352,22 @@ pub mod client_name_node_server {
             tonic::Response<super::DeleteFileResponse>,
             tonic::Status,
         >;
+        /// replica failed checksum verification or could not be read by the client
+        async fn report_bad_chunk(
+            &self,
+            request: tonic::Request<super::ReportBadChunkRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ReportBadChunkResponse>,
+            tonic::Status,
+        >;
+        /// datanode of a write pipeline failed, chunk gets a new generation so its replica turns stale
+        async fn recover_pipeline(
+            &self,
+            request: tonic::Request<super::RecoverPipelineRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RecoverPipelineResponse>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct ClientNameNodeServer<T> {
@@ -468,6 +580,98 @@ pub mod client_name_node_server {
                     };
                     Box::pin(fut)
                 }
+                "/client_namenode.ClientNameNode/ReportBadChunk" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportBadChunkSvc<T: ClientNameNode>(pub Arc<T>);
+                    impl<
+                        T: ClientNameNode,
+                    > tonic::server::UnaryService<super::ReportBadChunkRequest>
+                    for ReportBadChunkSvc<T> {
+                        type Response = super::ReportBadChunkResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ReportBadChunkRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ClientNameNode>::report_bad_chunk(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportBadChunkSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/client_namenode.ClientNameNode/RecoverPipeline" => {
+                    #[allow(non_camel_case_types)]
+                    struct RecoverPipelineSvc<T: ClientNameNode>(pub Arc<T>);
+                    impl<
+                        T: ClientNameNode,
+                    > tonic::server::UnaryService<super::RecoverPipelineRequest>
+                    for RecoverPipelineSvc<T> {
+                        type Response = super::RecoverPipelineResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RecoverPipelineRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ClientNameNode>::recover_pipeline(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RecoverPipelineSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
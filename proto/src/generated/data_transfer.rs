@@ -0,0 +1,103 @@
+// This file is @generated by prost-build.
+/// first frame of a connection
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TransferRequest {
+    #[prost(enumeration = "TransferMode", tag = "1")]
+    pub mode: i32,
+    #[prost(string, tag = "2")]
+    pub chunk_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub ticket: ::prost::alloc::string::String,
+    /// only for writes, chunk_size is size of the whole chunk and offset is where the write starts
+    #[prost(uint64, tag = "4")]
+    pub chunk_size: u64,
+    #[prost(uint64, tag = "5")]
+    pub offset: u64,
+}
+/// datanode answers every request before any chunk content moves
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TransferResponse {
+    #[prost(enumeration = "TransferStatus", tag = "1")]
+    pub status: i32,
+    /// version used for the rest of the connection
+    #[prost(uint32, tag = "2")]
+    pub version: u32,
+    #[prost(uint32, tag = "3")]
+    pub min_version: u32,
+    #[prost(uint32, tag = "4")]
+    pub max_version: u32,
+    #[prost(string, tag = "5")]
+    pub error: ::prost::alloc::string::String,
+    /// block checksums of the chunk being read, not set for chunks stored without them
+    #[prost(message, optional, tag = "6")]
+    pub checksums: ::core::option::Option<ChunkChecksums>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChunkChecksums {
+    #[prost(uint32, repeated, tag = "1")]
+    pub checksums: ::prost::alloc::vec::Vec<u32>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TransferMode {
+    Unspecified = 0,
+    Read = 1,
+    Write = 2,
+}
+impl TransferMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Unspecified => "TRANSFER_MODE_UNSPECIFIED",
+            Self::Read => "TRANSFER_MODE_READ",
+            Self::Write => "TRANSFER_MODE_WRITE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "TRANSFER_MODE_UNSPECIFIED" => Some(Self::Unspecified),
+            "TRANSFER_MODE_READ" => Some(Self::Read),
+            "TRANSFER_MODE_WRITE" => Some(Self::Write),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum TransferStatus {
+    Ok = 0,
+    UnsupportedVersion = 1,
+    Rejected = 2,
+    TicketExpired = 3,
+    TicketReplayed = 4,
+}
+impl TransferStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Ok => "TRANSFER_STATUS_OK",
+            Self::UnsupportedVersion => "TRANSFER_STATUS_UNSUPPORTED_VERSION",
+            Self::Rejected => "TRANSFER_STATUS_REJECTED",
+            Self::TicketExpired => "TRANSFER_STATUS_TICKET_EXPIRED",
+            Self::TicketReplayed => "TRANSFER_STATUS_TICKET_REPLAYED",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "TRANSFER_STATUS_OK" => Some(Self::Ok),
+            "TRANSFER_STATUS_UNSUPPORTED_VERSION" => Some(Self::UnsupportedVersion),
+            "TRANSFER_STATUS_REJECTED" => Some(Self::Rejected),
+            "TRANSFER_STATUS_TICKET_EXPIRED" => Some(Self::TicketExpired),
+            "TRANSFER_STATUS_TICKET_REPLAYED" => Some(Self::TicketReplayed),
+            _ => None,
+        }
+    }
+}
@@ -10,6 +10,10 @@ pub struct CreatePipelineRequest {
 pub struct CreatePipelineResponse {
     #[prost(string, tag = "1")]
     pub address: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub staged_bytes: u64,
+    #[prost(string, repeated, tag = "3")]
+    pub pipeline: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StoreChunkRequest {
@@ -25,6 +29,8 @@ pub struct StoreChunkResponse {
 pub struct CommitChunkRequest {
     #[prost(string, tag = "1")]
     pub chunk_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub generation: u64,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct CommitChunkResponse {
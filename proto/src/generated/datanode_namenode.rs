@@ -7,6 +7,18 @@ pub struct ConnectionRequest {
     pub addrs: ::prost::alloc::string::String,
     #[prost(string, tag = "3")]
     pub name: ::prost::alloc::string::String,
+    /// topology labels used by rack aware placement
+    #[prost(string, tag = "4")]
+    pub rack: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub zone: ::prost::alloc::string::String,
+    /// from VERSION file in storage root, cluster id is empty for new storage
+    #[prost(string, tag = "6")]
+    pub cluster_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "7")]
+    pub storage_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "8")]
+    pub storage_datanode_id: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ConnectionResponse {
@@ -14,6 +26,8 @@ pub struct ConnectionResponse {
     pub connected: bool,
     #[prost(string, tag = "2")]
     pub msg: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub cluster_id: ::prost::alloc::string::String,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StateSyncRequest {
@@ -21,23 +35,240 @@ pub struct StateSyncRequest {
     pub id: ::prost::alloc::string::String,
     #[prost(uint64, tag = "2")]
     pub availabe_storage: u64,
-    #[prost(string, repeated, tag = "3")]
-    pub available_chunks: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(uint64, tag = "4")]
+    pub storage_capacity: u64,
+    /// available_chunks is only set when this is a full report
+    #[prost(bool, tag = "5")]
+    pub full_report: bool,
+    /// sequence of last chunk report included in this full report
+    #[prost(uint64, tag = "6")]
+    pub report_sequence: u64,
+    /// chunk id to generation of the replica
+    #[prost(map = "string, uint64", tag = "7")]
+    pub available_chunks: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        u64,
+    >,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StateSyncResponse {
     #[prost(string, repeated, tag = "1")]
     pub chunks_to_be_deleted: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(message, repeated, tag = "2")]
+    pub commands: ::prost::alloc::vec::Vec<DatanodeCommand>,
+}
+/// changes to chunks stored on datanode since last report
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChunkReportRequest {
+    #[prost(string, tag = "1")]
+    pub datanode_id: ::prost::alloc::string::String,
+    /// increases by one with every report, gap means a report was lost
+    #[prost(uint64, tag = "2")]
+    pub sequence: u64,
+    /// chunk id to generation of the replica
+    #[prost(map = "string, uint64", tag = "3")]
+    pub received: ::std::collections::HashMap<::prost::alloc::string::String, u64>,
+    #[prost(string, repeated, tag = "4")]
+    pub deleted: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "5")]
+    pub corrupt: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// chunk id to unix secs the block scanner last verified the replica
+    #[prost(map = "string, uint64", tag = "6")]
+    pub verified: ::std::collections::HashMap<::prost::alloc::string::String, u64>,
+}
+/// sent as soon as a chunk is committed so that it can be read right away
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChunkCommittedRequest {
+    #[prost(string, tag = "1")]
+    pub datanode_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub chunk_id: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub generation: u64,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ChunkCommittedResponse {
+    /// chunk is deleted, unknown to namenode or replica is stale
+    #[prost(bool, tag = "1")]
+    pub to_be_deleted: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChunkReportResponse {
+    /// datanode should send full report with next state sync
+    #[prost(bool, tag = "1")]
+    pub full_report_required: bool,
+    #[prost(string, repeated, tag = "2")]
+    pub chunks_to_be_deleted: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HeartBeatRequest {
     #[prost(string, tag = "1")]
     pub datanode_id: ::prost::alloc::string::String,
+    /// number of tcp transfers datanode is serving right now, reads and writes
+    #[prost(uint64, tag = "2")]
+    pub active_transfers: u64,
+    /// results of commands executed since last heartbeat
+    #[prost(message, repeated, tag = "3")]
+    pub acks: ::prost::alloc::vec::Vec<CommandAck>,
+    #[prost(uint64, tag = "4")]
+    pub active_reads: u64,
+    #[prost(uint64, tag = "5")]
+    pub active_writes: u64,
+    /// replicate commands still running
+    #[prost(uint64, tag = "6")]
+    pub pending_replications: u64,
+    #[prost(message, repeated, tag = "7")]
+    pub volumes: ::prost::alloc::vec::Vec<VolumeReport>,
+    /// disk errors seen in last few minutes
+    #[prost(uint64, tag = "8")]
+    pub recent_disk_errors: u64,
+    #[prost(message, optional, tag = "9")]
+    pub scan_progress: ::core::option::Option<ScanProgress>,
+    /// newest ticket key datanode holds, namenode encrypts tickets with it from then on
+    #[prost(uint32, tag = "10")]
+    pub key_id: u32,
 }
+/// progress of the block scanner which verifies stored chunks against their checksums
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ScanProgress {
+    /// chunks verified so far in current pass and chunks in the pass
+    #[prost(uint64, tag = "1")]
+    pub chunks_scanned: u64,
+    #[prost(uint64, tag = "2")]
+    pub chunks_total: u64,
+    /// corrupt replicas found in current pass
+    #[prost(uint64, tag = "3")]
+    pub corrupt_found: u64,
+    /// unix secs, 0 when it hasn't happened yet
+    #[prost(uint64, tag = "4")]
+    pub pass_started_at: u64,
+    #[prost(uint64, tag = "5")]
+    pub last_pass_finished_at: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VolumeReport {
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub capacity: u64,
+    #[prost(uint64, tag = "3")]
+    pub used: u64,
+    #[prost(uint64, tag = "4")]
+    pub available: u64,
+    /// volume stopped working, its chunks were reported lost
+    #[prost(bool, tag = "5")]
+    pub failed: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct HeartBeatResponse {
     #[prost(bool, tag = "1")]
     pub connection_alive: bool,
+    #[prost(message, repeated, tag = "2")]
+    pub commands: ::prost::alloc::vec::Vec<DatanodeCommand>,
+    /// newest ticket key issued to datanode, datanode fetches its keys when it doesn't have it
+    #[prost(uint32, tag = "3")]
+    pub key_id: u32,
+}
+/// work queued by namenode for a datanode, delivered with heartbeat and state sync responses so
+/// namenode never has to dial a datanode
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DatanodeCommand {
+    /// unique per datanode, sent back in CommandAck
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(oneof = "datanode_command::Command", tags = "2, 3, 4, 5")]
+    pub command: ::core::option::Option<datanode_command::Command>,
+}
+/// Nested message and enum types in `DatanodeCommand`.
+pub mod datanode_command {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Command {
+        #[prost(message, tag = "2")]
+        Replicate(super::ReplicateCommand),
+        #[prost(message, tag = "3")]
+        Delete(super::DeleteCommand),
+        #[prost(message, tag = "4")]
+        Reregister(super::ReregisterCommand),
+        #[prost(message, tag = "5")]
+        Shutdown(super::ShutdownCommand),
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReplicateCommand {
+    #[prost(string, tag = "1")]
+    pub chunk_id: ::prost::alloc::string::String,
+    /// grpc address of datanode which will receive the replica
+    #[prost(string, tag = "2")]
+    pub target_data_node: ::prost::alloc::string::String,
+    /// ticket to store the chunk on target
+    #[prost(string, tag = "3")]
+    pub ticket: ::prost::alloc::string::String,
+    /// target datanode certificate is checked against it on the data plane
+    #[prost(string, tag = "4")]
+    pub target_data_node_id: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteCommand {
+    #[prost(string, tag = "1")]
+    pub chunk_id: ::prost::alloc::string::String,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ReregisterCommand {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ShutdownCommand {
+    #[prost(string, tag = "1")]
+    pub reason: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommandAck {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(bool, tag = "2")]
+    pub success: bool,
+    #[prost(string, tag = "3")]
+    pub error: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DatanodeMessage {
+    #[prost(oneof = "datanode_message::Message", tags = "1, 2, 3")]
+    pub message: ::core::option::Option<datanode_message::Message>,
+}
+/// Nested message and enum types in `DatanodeMessage`.
+pub mod datanode_message {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Message {
+        #[prost(message, tag = "1")]
+        HeartBeat(super::HeartBeatRequest),
+        #[prost(message, tag = "2")]
+        ChunkReport(super::ChunkReportRequest),
+        #[prost(message, tag = "3")]
+        StateSync(super::StateSyncRequest),
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NamenodeMessage {
+    #[prost(oneof = "namenode_message::Message", tags = "1, 2, 3, 4")]
+    pub message: ::core::option::Option<namenode_message::Message>,
+}
+/// Nested message and enum types in `NamenodeMessage`.
+pub mod namenode_message {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Message {
+        #[prost(message, tag = "1")]
+        HeartBeat(super::HeartBeatResponse),
+        #[prost(message, tag = "2")]
+        ChunkReport(super::ChunkReportResponse),
+        #[prost(message, tag = "3")]
+        StateSync(super::StateSyncResponse),
+        /// pushed as soon as they are queued
+        #[prost(message, tag = "4")]
+        Commands(super::DatanodeCommands),
+    }
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DatanodeCommands {
+    #[prost(message, repeated, tag = "1")]
+    pub commands: ::prost::alloc::vec::Vec<DatanodeCommand>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StoreChunkTicketRequest {
@@ -53,6 +284,24 @@ pub struct StoreChunkTicketResponse {
     #[prost(string, tag = "1")]
     pub ticket: ::prost::alloc::string::String,
 }
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct NodeKeysRequest {}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NodeKeysResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub keys: ::prost::alloc::vec::Vec<NodeKey>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NodeKey {
+    #[prost(uint32, tag = "1")]
+    pub key_id: u32,
+    /// base64 encoded AES-256 key
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    /// unix secs after which the key is no longer accepted, 0 when it doesn't expire
+    #[prost(uint64, tag = "3")]
+    pub expires_at_secs: u64,
+}
 /// Generated client implementations.
 pub mod datanode_namenode_client {
     #![allow(
@@ -196,6 +445,37 @@ pub mod datanode_namenode_client {
                 );
             self.inner.unary(req, path, codec).await
         }
+        /// one long lived stream per datanode carrying heartbeats, reports and commands, namenode
+        /// knows datanode is gone as soon as the stream breaks
+        pub async fn heart_beat_stream(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::DatanodeMessage>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::NamenodeMessage>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/datanode_namenode.DatanodeNamenode/HeartBeatStream",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "datanode_namenode.DatanodeNamenode",
+                        "HeartBeatStream",
+                    ),
+                );
+            self.inner.streaming(req, path, codec).await
+        }
         pub async fn state_sync(
             &mut self,
             request: impl tonic::IntoRequest<super::StateSyncRequest>,
@@ -222,6 +502,61 @@ pub mod datanode_namenode_client {
                 );
             self.inner.unary(req, path, codec).await
         }
+        pub async fn chunk_report(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ChunkReportRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ChunkReportResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/datanode_namenode.DatanodeNamenode/ChunkReport",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("datanode_namenode.DatanodeNamenode", "ChunkReport"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn chunk_committed(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ChunkCommittedRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ChunkCommittedResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/datanode_namenode.DatanodeNamenode/ChunkCommitted",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "datanode_namenode.DatanodeNamenode",
+                        "ChunkCommitted",
+                    ),
+                );
+            self.inner.unary(req, path, codec).await
+        }
         pub async fn store_chunk_ticket(
             &mut self,
             request: impl tonic::IntoRequest<super::StoreChunkTicketRequest>,
@@ -251,6 +586,33 @@ pub mod datanode_namenode_client {
                 );
             self.inner.unary(req, path, codec).await
         }
+        /// keys of the calling datanode, caller is known from its certificate
+        pub async fn node_keys(
+            &mut self,
+            request: impl tonic::IntoRequest<super::NodeKeysRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::NodeKeysResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/datanode_namenode.DatanodeNamenode/NodeKeys",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("datanode_namenode.DatanodeNamenode", "NodeKeys"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -280,6 +642,21 @@ pub mod datanode_namenode_server {
             tonic::Response<super::HeartBeatResponse>,
             tonic::Status,
         >;
+        /// Server streaming response type for the HeartBeatStream method.
+        type HeartBeatStreamStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::NamenodeMessage, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// one long lived stream per datanode carrying heartbeats, reports and commands, namenode
+        /// knows datanode is gone as soon as the stream breaks
+        async fn heart_beat_stream(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::DatanodeMessage>>,
+        ) -> std::result::Result<
+            tonic::Response<Self::HeartBeatStreamStream>,
+            tonic::Status,
+        >;
         async fn state_sync(
             &self,
             request: tonic::Request<super::StateSyncRequest>,
@@ -287,6 +664,20 @@ pub mod datanode_namenode_server {
             tonic::Response<super::StateSyncResponse>,
             tonic::Status,
         >;
+        async fn chunk_report(
+            &self,
+            request: tonic::Request<super::ChunkReportRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ChunkReportResponse>,
+            tonic::Status,
+        >;
+        async fn chunk_committed(
+            &self,
+            request: tonic::Request<super::ChunkCommittedRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ChunkCommittedResponse>,
+            tonic::Status,
+        >;
         async fn store_chunk_ticket(
             &self,
             request: tonic::Request<super::StoreChunkTicketRequest>,
@@ -294,6 +685,14 @@ pub mod datanode_namenode_server {
             tonic::Response<super::StoreChunkTicketResponse>,
             tonic::Status,
         >;
+        /// keys of the calling datanode, caller is known from its certificate
+        async fn node_keys(
+            &self,
+            request: tonic::Request<super::NodeKeysRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::NodeKeysResponse>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct DatanodeNamenodeServer<T> {
@@ -461,6 +860,55 @@ pub mod datanode_namenode_server {
                     };
                     Box::pin(fut)
                 }
+                "/datanode_namenode.DatanodeNamenode/HeartBeatStream" => {
+                    #[allow(non_camel_case_types)]
+                    struct HeartBeatStreamSvc<T: DatanodeNamenode>(pub Arc<T>);
+                    impl<
+                        T: DatanodeNamenode,
+                    > tonic::server::StreamingService<super::DatanodeMessage>
+                    for HeartBeatStreamSvc<T> {
+                        type Response = super::NamenodeMessage;
+                        type ResponseStream = T::HeartBeatStreamStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::DatanodeMessage>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as DatanodeNamenode>::heart_beat_stream(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = HeartBeatStreamSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/datanode_namenode.DatanodeNamenode/StateSync" => {
                     #[allow(non_camel_case_types)]
                     struct StateSyncSvc<T: DatanodeNamenode>(pub Arc<T>);
@@ -506,6 +954,97 @@ pub mod datanode_namenode_server {
                     };
                     Box::pin(fut)
                 }
+                "/datanode_namenode.DatanodeNamenode/ChunkReport" => {
+                    #[allow(non_camel_case_types)]
+                    struct ChunkReportSvc<T: DatanodeNamenode>(pub Arc<T>);
+                    impl<
+                        T: DatanodeNamenode,
+                    > tonic::server::UnaryService<super::ChunkReportRequest>
+                    for ChunkReportSvc<T> {
+                        type Response = super::ChunkReportResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ChunkReportRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as DatanodeNamenode>::chunk_report(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ChunkReportSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/datanode_namenode.DatanodeNamenode/ChunkCommitted" => {
+                    #[allow(non_camel_case_types)]
+                    struct ChunkCommittedSvc<T: DatanodeNamenode>(pub Arc<T>);
+                    impl<
+                        T: DatanodeNamenode,
+                    > tonic::server::UnaryService<super::ChunkCommittedRequest>
+                    for ChunkCommittedSvc<T> {
+                        type Response = super::ChunkCommittedResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ChunkCommittedRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as DatanodeNamenode>::chunk_committed(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ChunkCommittedSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/datanode_namenode.DatanodeNamenode/StoreChunkTicket" => {
                     #[allow(non_camel_case_types)]
                     struct StoreChunkTicketSvc<T: DatanodeNamenode>(pub Arc<T>);
@@ -552,6 +1091,51 @@ pub mod datanode_namenode_server {
                     };
                     Box::pin(fut)
                 }
+                "/datanode_namenode.DatanodeNamenode/NodeKeys" => {
+                    #[allow(non_camel_case_types)]
+                    struct NodeKeysSvc<T: DatanodeNamenode>(pub Arc<T>);
+                    impl<
+                        T: DatanodeNamenode,
+                    > tonic::server::UnaryService<super::NodeKeysRequest>
+                    for NodeKeysSvc<T> {
+                        type Response = super::NodeKeysResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::NodeKeysRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as DatanodeNamenode>::node_keys(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = NodeKeysSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -7,6 +7,8 @@ pub struct ReplicateChunkRequest {
     pub chunk_id: ::prost::alloc::string::String,
     #[prost(string, tag = "3")]
     pub ticket: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub target_data_node_id: ::prost::alloc::string::String,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct ReplicateChunkResponse {}
@@ -14,4 +14,7 @@ pub mod generated {
     pub mod namenode_datanode {
         include!("./generated/namenode_datanode.rs");
     }
+    pub mod data_transfer {
+        include!("./generated/data_transfer.rs");
+    }
 }
@@ -86,6 +86,8 @@ for ((i = 0; i < DATANODE_COUNT; i++)); do
 
   CERT=$(echo "$CERT_RESPONSE" | jq -r '.cert')
   SECKEY=$(echo "$CERT_RESPONSE" | jq -r '.key')
+  CERTKEY=$(echo "$CERT_RESPONSE" | jq -r '.cert_key')
+  ROOTCERT=$(echo "$CERT_RESPONSE" | jq -r '.root_cert')
   if [[ "$CERT" == "null" || -z "$CERT" ]]; then
    echo "Failed to extract cert from response"
    echo "$CERT_RESPONSE"
@@ -99,6 +101,8 @@ echo "Appending certificate"
 cat <<EOF >> "$(pwd)/cluster_configs/datanode/datanode${i}.yaml"
 namenode_cert: $(echo "$CERT" | sed 's/^/  /')
 secret_key: $(echo "$SECKEY" | sed 's/^/  /')
+cert_key: $(echo "$CERTKEY" | sed 's/^/  /')
+root_cert: $(echo "$ROOTCERT" | sed 's/^/  /')
 EOF
 
 echo "Certificate appended successfully."
@@ -127,6 +131,8 @@ CERT_RESPONSE=$(curl -s -X POST "$NAMENODE_URL/cert/issue" \
 
   CERT=$(echo "$CERT_RESPONSE" | jq -r '.cert')
   SECKEY=$(echo "$CERT_RESPONSE" | jq -r '.key')
+  CERTKEY=$(echo "$CERT_RESPONSE" | jq -r '.cert_key')
+  ROOTCERT=$(echo "$CERT_RESPONSE" | jq -r '.root_cert')
 
   if [[ "$CERT" == "null" || -z "$CERT" ]]; then
    echo "Failed to extract cert from response"
@@ -141,6 +147,8 @@ echo " Appending certificate"
 cat <<EOF >> "$(pwd)/client/config/default.yaml"
 namenode_cert: $(echo "$CERT" | sed 's/^/  /')
 secret_key: $(echo "$SECKEY" | sed 's/^/  /')
+cert_key: $(echo "$CERTKEY" | sed 's/^/  /')
+root_cert: $(echo "$ROOTCERT" | sed 's/^/  /')
 EOF
 
 echo "Certificate appended successfully."
@@ -66,6 +66,8 @@ echo "Requesting certificate for node '$NODE_ID'..."
 
 CERT=$(echo "$CERT_RESPONSE" | jq -r '.cert')
 SECKEY=$(echo "$CERT_RESPONSE" | jq -r '.key')
+CERTKEY=$(echo "$CERT_RESPONSE" | jq -r '.cert_key')
+ROOTCERT=$(echo "$CERT_RESPONSE" | jq -r '.root_cert')
 if [[ "$CERT" == "null" || -z "$CERT" ]]; then
   echo "Failed to extract cert from response"
   echo "$CERT_RESPONSE"
@@ -79,6 +81,8 @@ echo "Appending certificate"
 cat <<EOF >> "$(pwd)/datanode/config/default.yaml"
 namenode_cert: $(echo "$CERT" | sed 's/^/  /')
 secret_key: $(echo "$SECKEY" | sed 's/^/  /')
+cert_key: $(echo "$CERTKEY" | sed 's/^/  /')
+root_cert: $(echo "$ROOTCERT" | sed 's/^/  /')
 EOF
 
 echo "Certificate appended successfully."
@@ -99,6 +103,8 @@ CERT_RESPONSE=$(curl -s -X POST "$NAMENODE_URL/cert/issue" \
 
   CERT=$(echo "$CERT_RESPONSE" | jq -r '.cert')
   SECKEY=$(echo "$CERT_RESPONSE" | jq -r '.key')
+  CERTKEY=$(echo "$CERT_RESPONSE" | jq -r '.cert_key')
+  ROOTCERT=$(echo "$CERT_RESPONSE" | jq -r '.root_cert')
 
   if [[ "$CERT" == "null" || -z "$CERT" ]]; then
    echo "Failed to extract cert from response"
@@ -113,6 +119,8 @@ echo " Appending client certificate"
 cat <<EOF >> "$(pwd)/client/config/default.yaml"
 namenode_cert: $(echo "$CERT" | sed 's/^/  /')
 secret_key: $(echo "$SECKEY" | sed 's/^/  /')
+cert_key: $(echo "$CERTKEY" | sed 's/^/  /')
+root_cert: $(echo "$ROOTCERT" | sed 's/^/  /')
 EOF
 
 echo "Client Certificate appended successfully."
@@ -1,17 +1,22 @@
 mod platform_utility;
 use std::{
+    io::SeekFrom,
     os::unix::fs::MetadataExt,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use tracing::{error, info, instrument};
+use utilities::checksum::{self, ChecksumReader};
 
 use crate::{
-    file_storage::platform_utility::{available_storage, create_mount, detach_device},
+    file_storage::platform_utility::{
+        available_storage, create_mount, detach_device, total_storage,
+    },
     storage::{Result, Storage},
 };
 use tokio::{
-    fs::{self, File},
-    io::copy,
+    fs::{self, File, OpenOptions},
+    io::{AsyncSeekExt, AsyncWriteExt, copy, sink},
 };
 
 #[derive(Clone)]
@@ -26,31 +31,58 @@ pub struct FileStorageConfig {
 }
 impl FileStorage {
     pub async fn new(config: FileStorageConfig) -> Self {
-        let root: &str = config.root.as_ref();
-        let device_id = match create_mount(&config).await {
-            Ok(v) => {
-                info!(%root,"Created root for storage");
-                v
-            }
-            Err(e) => {
-                error!(%root,error=%e,"Error while creating the root for storage");
-                panic!("Error during creating directory")
-            }
-        };
-        match std::fs::create_dir_all(format!("{root}/staged")) {
-            Ok(_v) => {
-                info!(%root,"Created staging dir for storage");
-            }
+        let root = config.root.clone();
+        match Self::try_new(config).await {
+            Ok(v) => v,
             Err(e) => {
-                error!(%root,error=%e,"Error while creating the staging for storage");
-                panic!("Error during staging directory")
+                error!(%root,error=%e,"Error while creating the storage");
+                panic!("Error during creating storage")
             }
         }
-        FileStorage {
+    }
+    // volume which can't be set up is reported as failed instead of stopping the datanode
+    pub async fn try_new(config: FileStorageConfig) -> Result<Self> {
+        let root: &str = config.root.as_ref();
+        let device_id = create_mount(&config)
+            .await
+            .map_err(|e| format!("Error while creating the root for storage {e}"))?;
+        info!(%root,"Created root for storage");
+        for dir in ["staged", "generation", "checksum"] {
+            std::fs::create_dir_all(format!("{root}/{dir}"))
+                .map_err(|e| format!("Error while creating the {dir} dir for storage {e}"))?;
+        }
+        Ok(FileStorage {
             root: root.to_owned(),
             device_id,
+        })
+    }
+    // stands in for a volume which failed while being set up
+    pub(crate) fn failed(root: String) -> Self {
+        FileStorage {
+            root,
+            device_id: None,
         }
     }
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+    // true when chunk is staged or committed on this volume
+    pub async fn contains(&self, chunk_id: &str) -> bool {
+        fs::try_exists(self.get_committed_path(chunk_id))
+            .await
+            .unwrap_or(false)
+            || fs::try_exists(self.get_staged_path(chunk_id))
+                .await
+                .unwrap_or(false)
+    }
+    // volume is healthy when its root can be listed and a file can be written to it
+    pub async fn check_health(&self) -> Result<()> {
+        fs::read_dir(&self.root).await?.next_entry().await?;
+        let probe_path = Path::new(&self.root).join("staged").join(".volume_check");
+        fs::write(&probe_path, b"ok").await?;
+        fs::remove_file(&probe_path).await?;
+        Ok(())
+    }
     // not implementing drop trait because we need data to persist in crash
     pub fn cleanup(&self) {
         if let Some(device_id) = &self.device_id {
@@ -71,25 +103,73 @@ impl FileStorage {
     fn get_staged_path(&self, chunk_id: &str) -> PathBuf {
         Path::new(&self.root).join("staged").join(chunk_id)
     }
+    fn get_generation_path(&self, chunk_id: &str) -> PathBuf {
+        Path::new(&self.root).join("generation").join(chunk_id)
+    }
+    fn get_checksum_path(&self, chunk_id: &str) -> PathBuf {
+        Path::new(&self.root).join("checksum").join(chunk_id)
+    }
 }
 impl Storage for FileStorage {
     #[instrument(name = "file_storage_write", skip(self, chunk_stream))]
     async fn write(
         &self,
         chunk_id: String,
+        offset: u64,
         chunk_stream: &mut (impl tokio::io::AsyncRead + Unpin),
     ) -> Result<u64> {
         let chunk_path = self.get_staged_path(&chunk_id);
-        let mut chunk_file = File::create_new(chunk_path).await?;
-        let writer_byte_count = copy(chunk_stream, &mut chunk_file).await?;
-        info!(%chunk_id,"data copied successfully");
+        let mut chunk_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&chunk_path)
+            .await?;
+        let staged_size = chunk_file.metadata().await?.len();
+        if offset > staged_size {
+            return Err(format!(
+                "Chunk {chunk_id} has {staged_size} bytes staged, can't resume from {offset}"
+            )
+            .into());
+        }
+        // anything after offset is from a write which was not acknowledged
+        chunk_file.set_len(offset).await?;
+        chunk_file.seek(SeekFrom::Start(offset)).await?;
+        // checksums are computed while writing and kept in a sidecar file next to generation
+        let mut checksum_stream = ChecksumReader::new(chunk_stream);
+        let writer_byte_count = copy(&mut checksum_stream, &mut chunk_file).await?;
+        chunk_file.flush().await?;
+        let checksums = if offset == 0 {
+            checksum_stream.into_checksums()
+        } else {
+            // checksums cover the whole chunk so the part kept from before is read again
+            let mut staged_stream = ChecksumReader::new(File::open(&chunk_path).await?);
+            copy(&mut staged_stream, &mut sink()).await?;
+            staged_stream.into_checksums()
+        };
+        fs::write(
+            self.get_checksum_path(&chunk_id),
+            checksum::encode(&checksums),
+        )
+        .await?;
+        info!(%chunk_id, offset, "data copied successfully");
         Ok(writer_byte_count)
     }
+    #[instrument(name = "file_storage_staged_size", skip(self))]
+    async fn get_staged_size(&self, chunk_id: &str) -> Result<u64> {
+        match fs::metadata(self.get_staged_path(chunk_id)).await {
+            Ok(metadata) => Ok(metadata.size()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
     #[instrument(name = "file_storage_commit", skip(self))]
-    async fn commit(&self, chunk_id: String) -> Result<bool> {
+    async fn commit(&self, chunk_id: String, generation: u64) -> Result<bool> {
         // check if file is in staged area
         let staged_path = self.get_staged_path(&chunk_id);
         let committed_path = self.get_committed_path(&chunk_id);
+        // generation is written first so a committed chunk always has one
+        fs::write(self.get_generation_path(&chunk_id), generation.to_string()).await?;
         if fs::metadata(staged_path.clone()).await.is_ok() {
             // move file from staged area to commited area
             tokio::fs::rename(staged_path, committed_path).await?;
@@ -116,14 +196,94 @@ impl Storage for FileStorage {
         if exists {
             fs::remove_file(self.get_committed_path(&chunk_id)).await?;
         }
+        // chunk may have been committed before generations and checksums were stored
+        let _ = fs::remove_file(self.get_generation_path(&chunk_id)).await;
+        let _ = fs::remove_file(self.get_checksum_path(&chunk_id)).await;
         Ok(exists)
     }
+    // staged chunk is dropped when its content does not match checksums computed by the writer
+    #[instrument(name = "file_storage_verify_checksums", skip(self, expected))]
+    async fn verify_checksums(&self, chunk_id: &str, expected: &[u32]) -> Result<()> {
+        let checksums = self.get_checksums(chunk_id).await?.unwrap_or_default();
+        if checksums == expected {
+            return Ok(());
+        }
+        let _ = self.discard_staged(chunk_id).await;
+        Err(format!(
+            "Checksum mismatch for chunk {chunk_id}, expected {} blocks got {}",
+            expected.len(),
+            checksums.len()
+        )
+        .into())
+    }
+    #[instrument(name = "file_storage_staged_chunks", skip(self))]
+    async fn staged_chunks(&self) -> Result<Vec<(String, Duration)>> {
+        let mut dir_enteries = fs::read_dir(Path::new(&self.root).join("staged")).await?;
+        let mut staged = vec![];
+        while let Some(chunk) = dir_enteries.next_entry().await? {
+            let chunk_id = chunk
+                .file_name()
+                .into_string()
+                .map_err(|_| "Invalid file name")?;
+            // volume health probe lives here as well
+            if chunk_id.starts_with('.') {
+                continue;
+            }
+            let age = chunk
+                .metadata()
+                .await?
+                .modified()?
+                .elapsed()
+                .unwrap_or_default();
+            staged.push((chunk_id, age));
+        }
+        Ok(staged)
+    }
+    #[instrument(name = "file_storage_discard_staged", skip(self))]
+    async fn discard_staged(&self, chunk_id: &str) -> Result<bool> {
+        let exists = fs::try_exists(self.get_staged_path(chunk_id))
+            .await
+            .unwrap_or(false);
+        if exists {
+            fs::remove_file(self.get_staged_path(chunk_id)).await?;
+        }
+        // sidecar belongs to committed copy when there is one
+        if !fs::try_exists(self.get_committed_path(chunk_id))
+            .await
+            .unwrap_or(false)
+        {
+            let _ = fs::remove_file(self.get_checksum_path(chunk_id)).await;
+        }
+        Ok(exists)
+    }
+    // chunks written before checksums were stored don't have any
+    #[instrument(name = "file_storage_get_checksums", skip(self))]
+    async fn get_checksums(&self, chunk_id: &str) -> Result<Option<Vec<u32>>> {
+        match fs::read_to_string(self.get_checksum_path(chunk_id)).await {
+            Ok(checksums) => Ok(Some(checksum::decode(&checksums)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    // chunks committed before generations were stored are treated as first generation
+    #[instrument(name = "file_storage_get_generation", skip(self))]
+    async fn get_generation(&self, chunk_id: &str) -> Result<u64> {
+        match fs::read_to_string(self.get_generation_path(chunk_id)).await {
+            Ok(generation) => Ok(generation.trim().parse()?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(1),
+            Err(e) => Err(e.into()),
+        }
+    }
     #[instrument(name = "file_storage_available_chunk", skip(self))]
     async fn available_chunks(&self) -> Result<Vec<String>> {
         info!(root=%self.root,"Reading the dir to get available chunks");
         let mut dir_enteries = fs::read_dir(&self.root).await?;
         let mut chunk_ids = vec![];
         while let Some(chunk) = dir_enteries.next_entry().await? {
+            // storage identity of the datanode lives in root as well
+            if chunk.file_name() == "VERSION" {
+                continue;
+            }
             if !chunk
                 .file_type()
                 .await
@@ -150,6 +310,9 @@ impl Storage for FileStorage {
     fn available_storage(&self) -> Result<usize> {
         available_storage(&self.root)
     }
+    fn total_storage(&self) -> Result<usize> {
+        total_storage(&self.root)
+    }
 }
 #[cfg(test)]
 impl Drop for FileStorage {
@@ -173,4 +336,46 @@ mod tests {
         // fs::create_dir_all(&storage.root).await?;
         storage_test(storage).await // we have to await here because other wise
     }
+    #[tokio::test]
+    async fn write_resumes_from_offset() -> Result<()> {
+        let root = std::env::temp_dir()
+            .join(format!("file_storage_resume_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let storage = FileStorage::try_new(FileStorageConfig {
+            root: root.clone(),
+            create_mount: false,
+            mount_size_in_mega_byte: 0,
+        })
+        .await?;
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let chunk_id = "chunk".to_owned();
+        storage
+            .write(chunk_id.clone(), 0, &mut &data[..70_000])
+            .await?;
+        // only 40000 bytes were acknowledged, the rest of the staged part is written again
+        storage
+            .write(chunk_id.clone(), 40_000, &mut &data[40_000..])
+            .await?;
+        assert_eq!(storage.get_staged_size(&chunk_id).await?, 100_000);
+        let mut expected = utilities::checksum::BlockChecksums::default();
+        expected.update(&data);
+        assert_eq!(
+            storage.get_checksums(&chunk_id).await?,
+            Some(expected.finish())
+        );
+        // can't resume past what is staged
+        assert!(
+            storage
+                .write(chunk_id.clone(), 200_000, &mut &data[..])
+                .await
+                .is_err()
+        );
+        storage.commit(chunk_id.clone(), 1).await?;
+        let mut content = vec![];
+        tokio::io::copy(&mut storage.read(chunk_id).await?, &mut content).await?;
+        assert_eq!(content, data);
+        fs::remove_dir_all(root).await?;
+        Ok(())
+    }
 }
@@ -92,3 +92,12 @@ pub(crate) fn available_storage(path: &str) -> Result<usize, Box<dyn Error + Sen
     println!("available storage {}", available_bytes);
     return Ok(available_bytes);
 }
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+pub(crate) fn total_storage(path: &str) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    use nix::sys::statvfs;
+    use std::path::Path;
+
+    let stats = statvfs::statvfs(Path::new(path))?;
+    Ok(stats.blocks() as usize * stats.fragment_size() as usize)
+}
@@ -1,2 +1,3 @@
 pub mod file_storage;
 pub mod storage;
+pub mod volume_set;
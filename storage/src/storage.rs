@@ -2,19 +2,33 @@ use std::{error::Error, usize};
 
 pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
 
+use std::time::Duration;
 use tokio::io;
 pub trait Storage {
+    // staged copy is cut at offset and continued from there so an interrupted write can resume
     async fn write(
         &self,
         chunk_id: String,
+        offset: u64,
         chunk_stream: &mut (impl io::AsyncRead + Unpin),
     ) -> Result<u64>;
-    async fn commit(&self, chunk_id: String) -> Result<bool>;
+    // bytes of the chunk staged so far, 0 when nothing is staged
+    async fn get_staged_size(&self, chunk_id: &str) -> Result<u64>;
+    // generation is stored alongside the chunk so stale replicas can be detected
+    async fn commit(&self, chunk_id: String, generation: u64) -> Result<bool>;
+    async fn get_generation(&self, chunk_id: &str) -> Result<u64>;
     async fn read(&self, chunk_id: String) -> Result<Box<dyn io::AsyncRead + Unpin + Send>>;
     async fn delete(&self, chunk_id: String) -> Result<bool>;
+    // block checksums are persisted by write, verify compares them with what the writer sent
+    async fn verify_checksums(&self, chunk_id: &str, expected: &[u32]) -> Result<()>;
+    async fn get_checksums(&self, chunk_id: &str) -> Result<Option<Vec<u32>>>;
+    // chunks written but not committed yet, with time since they were last written to
+    async fn staged_chunks(&self) -> Result<Vec<(String, Duration)>>;
+    async fn discard_staged(&self, chunk_id: &str) -> Result<bool>;
     async fn available_chunks(&self) -> Result<Vec<String>>;
     async fn get_chunk_size(&self, chunk_id: &str) -> Result<u64>;
     fn available_storage(&self) -> Result<usize>;
+    fn total_storage(&self) -> Result<usize>;
 }
 
 #[cfg(test)]
@@ -22,6 +36,7 @@ pub mod tests {
     use std::io::Cursor;
     use tokio::io::AsyncReadExt;
     use tokio::io::BufReader;
+    use utilities::checksum::BlockChecksums;
 
     use super::*;
     pub async fn storage_test(storage: impl Storage) -> Result<()> {
@@ -38,12 +53,30 @@ pub mod tests {
 
         // Write test data
         let mut input_stream = Cursor::new(original_data);
-        let written = storage.write(chunk_id.clone(), &mut input_stream).await?;
+        let written = storage
+            .write(chunk_id.clone(), 0, &mut input_stream)
+            .await?;
         assert_eq!(written as usize, original_data.len());
+        // interrupted write is resumed from what was staged
+        let mut input_stream = Cursor::new(&original_data[5..]);
+        let written = storage
+            .write(chunk_id.clone(), 5, &mut input_stream)
+            .await?;
+        assert_eq!(written as usize, original_data.len() - 5);
+        assert_eq!(
+            storage.get_staged_size(&chunk_id).await? as usize,
+            original_data.len()
+        );
+        let mut block_checksums = BlockChecksums::default();
+        block_checksums.update(original_data);
+        let checksums = block_checksums.finish();
+        storage.verify_checksums(&chunk_id, &checksums).await?;
         // testing availbale chunks
-        storage.commit(chunk_id.clone()).await?;
+        storage.commit(chunk_id.clone(), 3).await?;
         let available_chunks = storage.available_chunks().await?;
         assert_eq!(available_chunks, vec!["test_chunk.bin".to_string()]);
+        assert_eq!(storage.get_generation(&chunk_id).await?, 3);
+        assert_eq!(storage.get_checksums(&chunk_id).await?, Some(checksums));
 
         // Read and verify data
         let reader = storage.read(chunk_id.clone()).await?;
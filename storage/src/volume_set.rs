@@ -0,0 +1,464 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tracing::{error, info, instrument, warn};
+
+use crate::{
+    file_storage::{FileStorage, FileStorageConfig},
+    storage::{Result, Storage},
+};
+
+// how the volume for a new chunk is picked
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VolumeChoosingPolicy {
+    RoundRobin,
+    MostAvailable,
+}
+
+struct Volume {
+    storage: FileStorage,
+    failed: AtomicBool,
+}
+
+#[derive(Debug, Clone)]
+pub struct VolumeInfo {
+    pub root: String,
+    pub capacity: usize,
+    pub available: usize,
+    pub failed: bool,
+}
+
+/// Spreads chunks over several storage directories (one per disk). A volume that fails is taken
+/// out and chunks it held are handed out by `take_lost_chunks` so they can be reported
+#[derive(Clone)]
+pub struct VolumeSet {
+    volumes: Arc<Vec<Volume>>,
+    policy: VolumeChoosingPolicy,
+    next_volume: Arc<AtomicUsize>,
+    // volume index of every chunk we know about, staged or committed
+    chunk_to_volume: Arc<Mutex<HashMap<String, usize>>>,
+    // chunks of failed volumes which are not reported yet
+    lost_chunks: Arc<Mutex<Vec<String>>>,
+}
+
+impl VolumeSet {
+    pub async fn new(configs: Vec<FileStorageConfig>, policy: VolumeChoosingPolicy) -> Self {
+        let mut volumes = vec![];
+        for config in configs {
+            let root = config.root.clone();
+            let volume = match FileStorage::try_new(config).await {
+                Ok(storage) => Volume {
+                    storage,
+                    failed: AtomicBool::new(false),
+                },
+                Err(e) => {
+                    error!(%root, error = %e, "Volume could not be set up, marking it failed");
+                    Volume {
+                        storage: FileStorage::failed(root),
+                        failed: AtomicBool::new(true),
+                    }
+                }
+            };
+            volumes.push(volume);
+        }
+        info!(volumes = volumes.len(), ?policy, "Created volume set");
+        Self {
+            volumes: Arc::new(volumes),
+            policy,
+            next_volume: Arc::new(AtomicUsize::new(0)),
+            chunk_to_volume: Arc::new(Mutex::new(HashMap::new())),
+            lost_chunks: Arc::new(Mutex::new(vec![])),
+        }
+    }
+    pub fn failed_volumes(&self) -> usize {
+        self.volumes
+            .iter()
+            .filter(|volume| volume.failed.load(Ordering::Relaxed))
+            .count()
+    }
+    pub fn volume_infos(&self) -> Vec<VolumeInfo> {
+        self.volumes
+            .iter()
+            .map(|volume| {
+                let failed = volume.failed.load(Ordering::Relaxed);
+                VolumeInfo {
+                    root: volume.storage.root().to_owned(),
+                    capacity: if failed {
+                        0
+                    } else {
+                        volume.storage.total_storage().unwrap_or(0)
+                    },
+                    available: if failed {
+                        0
+                    } else {
+                        volume.storage.available_storage().unwrap_or(0)
+                    },
+                    failed,
+                }
+            })
+            .collect()
+    }
+    pub fn take_lost_chunks(&self) -> Vec<String> {
+        std::mem::take(&mut *self.lost_chunks.lock().unwrap())
+    }
+    // probes every healthy volume, returns number of failed volumes
+    pub async fn check_volumes(&self) -> usize {
+        for index in self.healthy_volumes() {
+            self.check_volume(index).await;
+        }
+        self.failed_volumes()
+    }
+    fn healthy_volumes(&self) -> Vec<usize> {
+        (0..self.volumes.len())
+            .filter(|index| !self.volumes[*index].failed.load(Ordering::Relaxed))
+            .collect()
+    }
+    // called after an operation on the volume failed, a single bad file doesn't fail the volume
+    async fn check_volume(&self, index: usize) {
+        let volume = &self.volumes[index];
+        if let Err(e) = volume.storage.check_health().await {
+            self.fail_volume(index, &e.to_string());
+        }
+    }
+    // takes out a healthy volume which must not be used, like one of another datanode
+    pub fn refuse_volume(&self, root: &str, reason: &str) {
+        if let Some(index) = self
+            .volumes
+            .iter()
+            .position(|volume| volume.storage.root() == root)
+        {
+            self.fail_volume(index, reason);
+        }
+    }
+    fn fail_volume(&self, index: usize, reason: &str) {
+        let volume = &self.volumes[index];
+        if volume.failed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        error!(root = %volume.storage.root(), %reason, "Volume failed");
+        let mut chunk_to_volume = self.chunk_to_volume.lock().unwrap();
+        let lost: Vec<String> = chunk_to_volume
+            .iter()
+            .filter(|(_, volume_index)| **volume_index == index)
+            .map(|(chunk_id, _)| chunk_id.clone())
+            .collect();
+        for chunk_id in &lost {
+            chunk_to_volume.remove(chunk_id);
+        }
+        self.lost_chunks.lock().unwrap().extend(lost);
+    }
+    fn choose_volume(&self) -> Result<usize> {
+        let healthy = self.healthy_volumes();
+        if healthy.is_empty() {
+            return Err("No healthy volume left".into());
+        }
+        let index = match self.policy {
+            VolumeChoosingPolicy::RoundRobin => {
+                healthy[self.next_volume.fetch_add(1, Ordering::Relaxed) % healthy.len()]
+            }
+            VolumeChoosingPolicy::MostAvailable => *healthy
+                .iter()
+                .max_by_key(|index| {
+                    self.volumes[**index]
+                        .storage
+                        .available_storage()
+                        .unwrap_or(0)
+                })
+                .unwrap(),
+        };
+        Ok(index)
+    }
+    async fn locate(&self, chunk_id: &str) -> Result<usize> {
+        if let Some(index) = self.chunk_to_volume.lock().unwrap().get(chunk_id) {
+            return Ok(*index);
+        }
+        for index in self.healthy_volumes() {
+            if self.volumes[index].storage.contains(chunk_id).await {
+                self.chunk_to_volume
+                    .lock()
+                    .unwrap()
+                    .insert(chunk_id.to_owned(), index);
+                return Ok(index);
+            }
+        }
+        Err(format!("Chunk {chunk_id} not found on any volume").into())
+    }
+    // health of the volume is checked whenever an operation on it fails
+    async fn on_volume<T>(&self, index: usize, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.check_volume(index).await;
+        }
+        result
+    }
+}
+
+impl Storage for VolumeSet {
+    #[instrument(name = "volume_set_write", skip(self, chunk_stream))]
+    async fn write(
+        &self,
+        chunk_id: String,
+        offset: u64,
+        chunk_stream: &mut (impl tokio::io::AsyncRead + Unpin),
+    ) -> Result<u64> {
+        // a resumed write has to go to the volume which has the staged part
+        let index = match self.locate(&chunk_id).await {
+            Ok(index) => index,
+            Err(_) if offset == 0 => self.choose_volume()?,
+            Err(e) => return Err(e),
+        };
+        self.chunk_to_volume
+            .lock()
+            .unwrap()
+            .insert(chunk_id.clone(), index);
+        let result = self.volumes[index]
+            .storage
+            .write(chunk_id.clone(), offset, chunk_stream)
+            .await;
+        if result.is_err() {
+            self.chunk_to_volume.lock().unwrap().remove(&chunk_id);
+        }
+        self.on_volume(index, result).await
+    }
+    async fn get_staged_size(&self, chunk_id: &str) -> Result<u64> {
+        let Ok(index) = self.locate(chunk_id).await else {
+            return Ok(0);
+        };
+        let result = self.volumes[index].storage.get_staged_size(chunk_id).await;
+        self.on_volume(index, result).await
+    }
+    async fn commit(&self, chunk_id: String, generation: u64) -> Result<bool> {
+        let index = self.locate(&chunk_id).await?;
+        let result = self.volumes[index]
+            .storage
+            .commit(chunk_id, generation)
+            .await;
+        self.on_volume(index, result).await
+    }
+    async fn get_generation(&self, chunk_id: &str) -> Result<u64> {
+        let index = self.locate(chunk_id).await?;
+        let result = self.volumes[index].storage.get_generation(chunk_id).await;
+        self.on_volume(index, result).await
+    }
+    async fn read(&self, chunk_id: String) -> Result<Box<dyn tokio::io::AsyncRead + Unpin + Send>> {
+        let index = self.locate(&chunk_id).await?;
+        let result = self.volumes[index].storage.read(chunk_id).await;
+        self.on_volume(index, result).await
+    }
+    async fn delete(&self, chunk_id: String) -> Result<bool> {
+        let Ok(index) = self.locate(&chunk_id).await else {
+            return Ok(false);
+        };
+        let result = self.volumes[index].storage.delete(chunk_id.clone()).await;
+        if result.is_ok() {
+            self.chunk_to_volume.lock().unwrap().remove(&chunk_id);
+        }
+        self.on_volume(index, result).await
+    }
+    async fn verify_checksums(&self, chunk_id: &str, expected: &[u32]) -> Result<()> {
+        let index = self.locate(chunk_id).await?;
+        self.volumes[index]
+            .storage
+            .verify_checksums(chunk_id, expected)
+            .await
+    }
+    async fn get_checksums(&self, chunk_id: &str) -> Result<Option<Vec<u32>>> {
+        let index = self.locate(chunk_id).await?;
+        let result = self.volumes[index].storage.get_checksums(chunk_id).await;
+        self.on_volume(index, result).await
+    }
+    async fn staged_chunks(&self) -> Result<Vec<(String, Duration)>> {
+        let mut staged = vec![];
+        for index in self.healthy_volumes() {
+            let result = self.volumes[index].storage.staged_chunks().await;
+            staged.extend(self.on_volume(index, result).await?);
+        }
+        Ok(staged)
+    }
+    async fn discard_staged(&self, chunk_id: &str) -> Result<bool> {
+        let Ok(index) = self.locate(chunk_id).await else {
+            return Ok(false);
+        };
+        let result = self.volumes[index].storage.discard_staged(chunk_id).await;
+        if result.is_ok() {
+            self.chunk_to_volume.lock().unwrap().remove(chunk_id);
+        }
+        self.on_volume(index, result).await
+    }
+    // chunks of every healthy volume, a volume which can't be listed is checked and skipped
+    #[instrument(name = "volume_set_available_chunks", skip(self))]
+    async fn available_chunks(&self) -> Result<Vec<String>> {
+        let mut chunk_ids = vec![];
+        for index in self.healthy_volumes() {
+            match self.volumes[index].storage.available_chunks().await {
+                Ok(chunks) => {
+                    let mut chunk_to_volume = self.chunk_to_volume.lock().unwrap();
+                    for chunk_id in &chunks {
+                        chunk_to_volume.insert(chunk_id.clone(), index);
+                    }
+                    chunk_ids.extend(chunks);
+                }
+                Err(e) => {
+                    warn!(root = %self.volumes[index].storage.root(), error = %e, "Error while listing chunks of volume");
+                    self.check_volume(index).await;
+                    if !self.volumes[index].failed.load(Ordering::Relaxed) {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(chunk_ids)
+    }
+    async fn get_chunk_size(&self, chunk_id: &str) -> Result<u64> {
+        let index = self.locate(chunk_id).await?;
+        let result = self.volumes[index].storage.get_chunk_size(chunk_id).await;
+        self.on_volume(index, result).await
+    }
+    fn available_storage(&self) -> Result<usize> {
+        let mut available = 0;
+        for index in self.healthy_volumes() {
+            available += self.volumes[index].storage.available_storage()?;
+        }
+        Ok(available)
+    }
+    fn total_storage(&self) -> Result<usize> {
+        let mut total = 0;
+        for index in self.healthy_volumes() {
+            total += self.volumes[index].storage.total_storage()?;
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // plain directories, volume set doesn't need a mount of its own
+    fn configs(name: &str, count: usize) -> (String, Vec<FileStorageConfig>) {
+        let base = std::env::temp_dir()
+            .join(format!("volume_set_{name}_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let _ = std::fs::remove_dir_all(&base);
+        let configs = (0..count)
+            .map(|index| FileStorageConfig {
+                root: format!("{base}/volume_{index}"),
+                create_mount: false,
+                mount_size_in_mega_byte: 0,
+            })
+            .collect();
+        (base, configs)
+    }
+
+    async fn write(
+        volume_set: &VolumeSet,
+        chunk_id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<u64> {
+        volume_set
+            .write(chunk_id.to_owned(), offset, &mut Cursor::new(data))
+            .await
+    }
+
+    fn volume_of(volume_set: &VolumeSet, chunk_id: &str) -> Option<usize> {
+        volume_set
+            .chunk_to_volume
+            .lock()
+            .unwrap()
+            .get(chunk_id)
+            .copied()
+    }
+
+    #[tokio::test]
+    async fn round_robin_spreads_chunks() -> Result<()> {
+        let (base, configs) = configs("round_robin", 3);
+        let volume_set = VolumeSet::new(configs, VolumeChoosingPolicy::RoundRobin).await;
+        for index in 0..6 {
+            write(&volume_set, &format!("chunk_{index}"), 0, b"hello").await?;
+        }
+        let mut per_volume = [0; 3];
+        for index in 0..6 {
+            per_volume[volume_of(&volume_set, &format!("chunk_{index}")).unwrap()] += 1;
+        }
+        assert_eq!(per_volume, [2, 2, 2]);
+        // resumed write goes to the volume holding the staged part
+        let volume = volume_of(&volume_set, "chunk_0");
+        write(&volume_set, "chunk_0", 5, b" world").await?;
+        assert_eq!(volume_of(&volume_set, "chunk_0"), volume);
+        assert_eq!(volume_set.get_staged_size("chunk_0").await?, 11);
+        // resuming a chunk we never saw is refused
+        assert!(write(&volume_set, "unknown", 5, b"world").await.is_err());
+        std::fs::remove_dir_all(base)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn volume_failing_setup_is_skipped() -> Result<()> {
+        let (base, configs) = configs("failed_setup", 2);
+        // root of the first volume is a file so it can't be set up
+        std::fs::create_dir_all(&base)?;
+        std::fs::write(&configs[0].root, b"not a dir")?;
+        let volume_set = VolumeSet::new(configs, VolumeChoosingPolicy::RoundRobin).await;
+        assert_eq!(volume_set.failed_volumes(), 1);
+        let infos = volume_set.volume_infos();
+        assert!(infos[0].failed);
+        assert_eq!(infos[0].capacity, 0);
+        assert!(!infos[1].failed);
+        for index in 0..3 {
+            let chunk_id = format!("chunk_{index}");
+            write(&volume_set, &chunk_id, 0, b"hello").await?;
+            assert_eq!(volume_of(&volume_set, &chunk_id), Some(1));
+        }
+        volume_set.refuse_volume(&infos[1].root, "belongs to another datanode");
+        assert_eq!(volume_set.failed_volumes(), 2);
+        std::fs::remove_dir_all(base)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_volume_reports_its_chunks() -> Result<()> {
+        let (base, configs) = configs("failed_runtime", 2);
+        let volume_set = VolumeSet::new(configs, VolumeChoosingPolicy::RoundRobin).await;
+        for index in 0..4 {
+            let chunk_id = format!("chunk_{index}");
+            write(&volume_set, &chunk_id, 0, b"hello").await?;
+            volume_set.commit(chunk_id, 1).await?;
+        }
+        assert_eq!(volume_set.check_volumes().await, 0);
+        assert!(volume_set.take_lost_chunks().is_empty());
+
+        let failed_root = volume_set.volume_infos()[0].root.clone();
+        let mut expected_lost: Vec<String> = (0..4)
+            .map(|index| format!("chunk_{index}"))
+            .filter(|chunk_id| volume_of(&volume_set, chunk_id) == Some(0))
+            .collect();
+        std::fs::remove_dir_all(&failed_root)?;
+        assert_eq!(volume_set.check_volumes().await, 1);
+
+        let mut lost = volume_set.take_lost_chunks();
+        lost.sort();
+        expected_lost.sort();
+        assert_eq!(lost, expected_lost);
+        // lost chunks are handed out once
+        assert!(volume_set.take_lost_chunks().is_empty());
+        let available = volume_set.available_chunks().await?;
+        assert_eq!(available.len(), 4 - expected_lost.len());
+        assert!(available.iter().all(|chunk_id| !lost.contains(chunk_id)));
+        // new chunks only go to the healthy volume
+        write(&volume_set, "chunk_new", 0, b"hello").await?;
+        assert_eq!(volume_of(&volume_set, "chunk_new"), Some(1));
+
+        std::fs::remove_dir_all(volume_set.volume_infos()[1].root.clone())?;
+        assert_eq!(volume_set.check_volumes().await, 2);
+        assert!(write(&volume_set, "chunk_last", 0, b"hello").await.is_err());
+        std::fs::remove_dir_all(base)?;
+        Ok(())
+    }
+}
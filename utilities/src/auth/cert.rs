@@ -34,23 +34,7 @@ impl Authenticator for CertAuthenticator {
                 .map_err(|_| AuthenticationError::InvalidCredentials)?;
             let cert = EndEntityCert::try_from(&cert_der[..])
                 .map_err(|_| AuthenticationError::InvalidCredentials)?;
-            let (_, x509) = X509Certificate::from_der(&cert_der).map_err(|_| {
-                AuthenticationError::Internal(
-                    "Failed to parse certificate with x509-parser".to_string(),
-                )
-            })?;
-            let node_id = x509
-                .subject()
-                .iter_common_name()
-                .next()
-                .map(|cn| cn.as_str().unwrap_or("Unknown").to_string())
-                .unwrap_or("Unknown".to_string());
-            let node_type = x509
-                .subject()
-                .iter_organizational_unit()
-                .next()
-                .map(|ou| ou.as_str().unwrap_or("Unknown").into())
-                .unwrap_or(NodeType::Unknown);
+            let node_metadata = node_metadata_from_cert(&cert_der)?;
 
             let trust_anchors =
                 TlsClientTrustAnchors(&[
@@ -68,13 +52,34 @@ impl Authenticator for CertAuthenticator {
                     AuthenticationError::Internal(format!("Cert verification failed: {e}"))
                 })?;
 
-            Ok(NodeMetadata {
-                node_type,
-                authenticated_using: AuthType::CertAuth,
-                id: node_id,
-            })
+            Ok(node_metadata)
         } else {
             Err(AuthenticationError::InvalidCredentials)
         }
     }
 }
+
+// node id is the common name and node type the organizational unit of a certificate issued by
+// the namenode CA, certificate must already be verified
+pub fn node_metadata_from_cert(cert_der: &[u8]) -> Result<NodeMetadata, AuthenticationError> {
+    let (_, x509) = X509Certificate::from_der(cert_der).map_err(|_| {
+        AuthenticationError::Internal("Failed to parse certificate with x509-parser".to_string())
+    })?;
+    let node_id = x509
+        .subject()
+        .iter_common_name()
+        .next()
+        .map(|cn| cn.as_str().unwrap_or("Unknown").to_string())
+        .unwrap_or("Unknown".to_string());
+    let node_type = x509
+        .subject()
+        .iter_organizational_unit()
+        .next()
+        .map(|ou| ou.as_str().unwrap_or("Unknown").into())
+        .unwrap_or(NodeType::Unknown);
+    Ok(NodeMetadata {
+        node_type,
+        authenticated_using: AuthType::CertAuth,
+        id: node_id,
+    })
+}
@@ -0,0 +1,303 @@
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::result::Result;
+
+// crc32c is computed for every block of a chunk, last block can be shorter
+pub const CHECKSUM_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Checksums of a chunk computed block by block while its bytes are streamed
+#[derive(Debug, Default, Clone)]
+pub struct BlockChecksums {
+    checksums: Vec<u32>,
+    current: u32,
+    current_len: usize,
+}
+
+impl BlockChecksums {
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let n = std::cmp::min(CHECKSUM_BLOCK_SIZE - self.current_len, data.len());
+            self.current = crc32c::crc32c_append(self.current, &data[..n]);
+            self.current_len += n;
+            data = &data[n..];
+            if self.current_len == CHECKSUM_BLOCK_SIZE {
+                self.checksums.push(self.current);
+                self.current = 0;
+                self.current_len = 0;
+            }
+        }
+    }
+    // checksums of the blocks which are complete so far
+    pub fn completed(&self) -> &[u32] {
+        &self.checksums
+    }
+    pub fn finish(mut self) -> Vec<u32> {
+        if self.current_len > 0 {
+            self.checksums.push(self.current);
+        }
+        self.checksums
+    }
+}
+
+// checksums travel in data packets and sidecar files as comma separated hex
+pub fn encode(checksums: &[u32]) -> String {
+    checksums
+        .iter()
+        .map(|checksum| format!("{checksum:08x}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+pub fn decode(checksums: &str) -> Result<Vec<u32>> {
+    let checksums = checksums.trim();
+    if checksums.is_empty() {
+        return Ok(vec![]);
+    }
+    checksums
+        .split(',')
+        .map(|checksum| {
+            u32::from_str_radix(checksum, 16)
+                .map_err(|e| format!("Invalid checksum {checksum}, {e}").into())
+        })
+        .collect()
+}
+
+/// Error inside an InvalidData io error when a block doesn't match its checksum. Other
+/// InvalidData errors, like a chunk cut short, don't say the stored replica is bad
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub block: usize,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Checksum mismatch in block {}", self.block)
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+pub fn is_checksum_mismatch(e: &io::Error) -> bool {
+    e.get_ref()
+        .is_some_and(|inner| inner.is::<ChecksumMismatch>())
+}
+
+/// Computes block checksums of everything read through it
+pub struct ChecksumReader<R> {
+    inner: R,
+    checksums: BlockChecksums,
+}
+
+impl<R: AsyncRead + Unpin> ChecksumReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            checksums: BlockChecksums::default(),
+        }
+    }
+    pub fn into_checksums(self) -> Vec<u32> {
+        self.checksums.finish()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ChecksumReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            self.checksums.update(&buf.filled()[filled..]);
+        }
+        poll
+    }
+}
+
+/// Fails the read with InvalidData as soon as a block does not match its expected checksum.
+/// Nothing is verified when there are no expected checksums, chunks written before checksums
+/// were introduced don't have any
+pub struct VerifyingReader<R> {
+    inner: R,
+    expected: Option<Vec<u32>>,
+    checksums: BlockChecksums,
+}
+
+impl<R: AsyncRead + Unpin> VerifyingReader<R> {
+    pub fn new(inner: R, expected: Option<Vec<u32>>) -> Self {
+        Self {
+            inner,
+            expected,
+            checksums: BlockChecksums::default(),
+        }
+    }
+    fn verify(&self, eof: bool) -> io::Result<()> {
+        let Some(expected) = &self.expected else {
+            return Ok(());
+        };
+        let actual = if eof {
+            self.checksums.clone().finish()
+        } else {
+            self.checksums.completed().to_vec()
+        };
+        let mismatch = actual
+            .iter()
+            .zip(expected)
+            .position(|(actual, expected)| actual != expected);
+        if let Some(block) = mismatch {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                ChecksumMismatch { block },
+            ));
+        }
+        if actual.len() > expected.len() || (eof && actual.len() != expected.len()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Chunk has {} blocks but {} checksums",
+                    actual.len(),
+                    expected.len()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for VerifyingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            let read = &buf.filled()[filled..];
+            let eof = read.is_empty();
+            self.checksums.update(read);
+            if let Err(e) = self.verify(eof) {
+                // bytes of a failed read must not be handed out
+                buf.set_filled(filled);
+                return Poll::Ready(Err(e));
+            }
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    // one and a half block, last block is partial
+    fn data() -> Vec<u8> {
+        (0..CHECKSUM_BLOCK_SIZE + CHECKSUM_BLOCK_SIZE / 2)
+            .map(|i| (i % 251) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn partial_final_block_has_its_own_checksum() {
+        let data = data();
+        let mut checksums = BlockChecksums::default();
+        // uneven writes must not change the block boundaries
+        for piece in data.chunks(1000) {
+            checksums.update(piece);
+        }
+        assert_eq!(checksums.completed().len(), 1);
+        let checksums = checksums.finish();
+        assert_eq!(
+            checksums,
+            vec![
+                crc32c::crc32c(&data[..CHECKSUM_BLOCK_SIZE]),
+                crc32c::crc32c(&data[CHECKSUM_BLOCK_SIZE..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn exact_blocks_have_no_trailing_checksum() {
+        let mut checksums = BlockChecksums::default();
+        checksums.update(&vec![1u8; 2 * CHECKSUM_BLOCK_SIZE]);
+        assert_eq!(checksums.finish().len(), 2);
+        assert!(BlockChecksums::default().finish().is_empty());
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let checksums = vec![0, 0xdeadbeef, 42];
+        let encoded = encode(&checksums);
+        assert_eq!(encoded, "00000000,deadbeef,0000002a");
+        assert_eq!(decode(&encoded).unwrap(), checksums);
+        assert!(decode("").unwrap().is_empty());
+        assert!(decode("xyz").is_err());
+    }
+
+    #[tokio::test]
+    async fn verifying_reader_accepts_matching_chunk() {
+        let data = data();
+        let mut checksum_reader = ChecksumReader::new(data.as_slice());
+        let mut read = vec![];
+        checksum_reader.read_to_end(&mut read).await.unwrap();
+        let expected = checksum_reader.into_checksums();
+
+        let mut reader = VerifyingReader::new(data.as_slice(), Some(expected));
+        let mut read = vec![];
+        reader.read_to_end(&mut read).await.unwrap();
+        assert_eq!(read, data);
+    }
+
+    #[tokio::test]
+    async fn verifying_reader_refuses_corrupt_partial_block() {
+        let mut data = data();
+        let mut checksums = BlockChecksums::default();
+        checksums.update(&data);
+        let expected = checksums.finish();
+        *data.last_mut().unwrap() ^= 1;
+
+        let mut reader = VerifyingReader::new(data.as_slice(), Some(expected));
+        let mut read = vec![];
+        let e = reader.read_to_end(&mut read).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(is_checksum_mismatch(&e));
+    }
+
+    #[tokio::test]
+    async fn verifying_reader_refuses_missing_block() {
+        let data = data();
+        let mut checksums = BlockChecksums::default();
+        checksums.update(&data);
+        let mut expected = checksums.finish();
+        expected.pop();
+
+        let mut reader = VerifyingReader::new(data.as_slice(), Some(expected));
+        let mut read = vec![];
+        assert!(reader.read_to_end(&mut read).await.is_err());
+        // chunks without checksums are not verified
+        let mut reader = VerifyingReader::new(data.as_slice(), None);
+        assert!(reader.read_to_end(&mut read).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn short_chunk_is_not_a_checksum_mismatch() {
+        let data = data();
+        let mut checksums = BlockChecksums::default();
+        checksums.update(&data);
+        let expected = checksums.finish();
+
+        // transfer stopped right after the first block
+        let mut reader = VerifyingReader::new(&data[..CHECKSUM_BLOCK_SIZE], Some(expected));
+        let mut read = vec![];
+        let e = reader.read_to_end(&mut read).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(!is_checksum_mismatch(&e));
+    }
+}
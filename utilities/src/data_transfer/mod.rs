@@ -0,0 +1,170 @@
+use crate::result::Result;
+use prost::Message;
+use proto::generated::data_transfer::{
+    ChunkChecksums, TransferRequest, TransferResponse, TransferStatus,
+};
+use std::ops::RangeInclusive;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// "CHNK", a connection starting with anything else is not speaking the data transfer protocol
+pub const MAGIC: u32 = 0x4348_4e4b;
+pub const PROTOCOL_VERSION: u16 = 1;
+// versions a datanode accepts from its peers, older ones are rejected
+pub const SUPPORTED_VERSIONS: RangeInclusive<u16> = 1..=PROTOCOL_VERSION;
+// headers are small, a bigger length is garbage and is never allocated
+pub const MAX_HEADER_SIZE: usize = 64 * 1024;
+
+/// Frame read from the data plane, message is decoded once the version is known to be fine
+pub struct Frame {
+    pub version: u16,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn decode<M: Message + Default>(&self) -> Result<M> {
+        Ok(M::decode(self.payload.as_slice())?)
+    }
+}
+
+// magic, version and length of the protobuf encoded message which follows
+pub async fn write_frame(
+    stream: &mut (impl AsyncWrite + Unpin),
+    version: u16,
+    message: &impl Message,
+) -> Result<()> {
+    let len = message.encoded_len();
+    if len > MAX_HEADER_SIZE {
+        return Err(format!("Header of {len} bytes is over the limit of {MAX_HEADER_SIZE}").into());
+    }
+    let mut buf = Vec::with_capacity(10 + len);
+    buf.extend_from_slice(&MAGIC.to_le_bytes());
+    buf.extend_from_slice(&version.to_le_bytes());
+    buf.extend_from_slice(&(len as u32).to_le_bytes());
+    message.encode(&mut buf)?;
+    stream.write_all(&buf).await?;
+    Ok(())
+}
+
+pub async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> Result<Frame> {
+    let magic = stream.read_u32_le().await?;
+    if magic != MAGIC {
+        return Err(format!("Invalid frame magic {magic:#x}").into());
+    }
+    let version = stream.read_u16_le().await?;
+    let len = stream.read_u32_le().await? as usize;
+    if len > MAX_HEADER_SIZE {
+        return Err(format!("Header of {len} bytes is over the limit of {MAX_HEADER_SIZE}").into());
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+    Ok(Frame { version, payload })
+}
+
+// sends the request and waits for the datanode to take it, returns the response which has the
+// version to use for the rest of the connection
+pub async fn open_transfer(
+    stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    request: &TransferRequest,
+) -> Result<TransferResponse> {
+    write_frame(stream, PROTOCOL_VERSION, request).await?;
+    let response: TransferResponse = read_frame(stream).await?.decode()?;
+    match response.status() {
+        TransferStatus::Ok if SUPPORTED_VERSIONS.contains(&(response.version as u16)) => {
+            Ok(response)
+        }
+        TransferStatus::Ok => Err(format!(
+            "Datanode picked unsupported protocol version {}",
+            response.version
+        )
+        .into()),
+        TransferStatus::UnsupportedVersion => Err(format!(
+            "Datanode supports protocol versions {} to {}, we speak {PROTOCOL_VERSION}",
+            response.min_version, response.max_version
+        )
+        .into()),
+        TransferStatus::Rejected => {
+            Err(format!("Datanode rejected the transfer, {}", response.error).into())
+        }
+        TransferStatus::TicketExpired => {
+            Err(format!("Datanode refused expired ticket, {}", response.error).into())
+        }
+        TransferStatus::TicketReplayed => {
+            Err(format!("Datanode refused used ticket, {}", response.error).into())
+        }
+    }
+}
+
+// reads the request of a new connection and agrees on the version, newest both sides speak
+// is used. A request with a version we can't speak is answered with what we support
+pub async fn accept_transfer(
+    stream: &mut (impl AsyncRead + AsyncWrite + Unpin),
+) -> Result<(u16, TransferRequest)> {
+    let frame = read_frame(stream).await?;
+    let version = std::cmp::min(frame.version, PROTOCOL_VERSION);
+    if !SUPPORTED_VERSIONS.contains(&version) {
+        let error = format!("Protocol version {} is not supported", frame.version);
+        reject(stream, version, TransferStatus::UnsupportedVersion, &error).await?;
+        return Err(error.into());
+    }
+    Ok((version, frame.decode()?))
+}
+
+// checksums are only sent to readers
+pub async fn accept(
+    stream: &mut (impl AsyncWrite + Unpin),
+    version: u16,
+    checksums: Option<Vec<u32>>,
+) -> Result<()> {
+    let mut response = response(TransferStatus::Ok, version, "");
+    response.checksums = checksums.map(|checksums| ChunkChecksums { checksums });
+    write_frame(stream, version, &response).await
+}
+
+pub async fn reject(
+    stream: &mut (impl AsyncWrite + Unpin),
+    version: u16,
+    status: TransferStatus,
+    error: &str,
+) -> Result<()> {
+    write_frame(stream, version, &response(status, version, error)).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+fn response(status: TransferStatus, version: u16, error: &str) -> TransferResponse {
+    TransferResponse {
+        status: status as i32,
+        version: version as u32,
+        min_version: *SUPPORTED_VERSIONS.start() as u32,
+        max_version: *SUPPORTED_VERSIONS.end() as u32,
+        error: error.to_owned(),
+        checksums: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proto::generated::data_transfer::TransferMode;
+    use std::io::Cursor;
+    #[tokio::test]
+    async fn frame_test() {
+        let request = TransferRequest {
+            mode: TransferMode::Write as i32,
+            chunk_id: "chunk".to_owned(),
+            ticket: "ticket".to_owned(),
+            chunk_size: 10,
+            offset: 4,
+        };
+        let mut buf = Vec::new();
+        write_frame(&mut buf, PROTOCOL_VERSION, &request)
+            .await
+            .unwrap();
+        let frame = read_frame(&mut Cursor::new(buf.clone())).await.unwrap();
+        assert_eq!(frame.version, PROTOCOL_VERSION);
+        assert_eq!(frame.decode::<TransferRequest>().unwrap(), request);
+        // length over the limit is refused before anything is allocated
+        buf[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_frame(&mut Cursor::new(buf)).await.is_err());
+    }
+}
@@ -2,29 +2,47 @@ use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
 
 use crate::result::Result;
 use crate::retry_policy::retry_with_backoff;
+use once_cell::sync::OnceCell;
 use tokio::sync::Mutex;
-use tonic::transport::{Channel, Endpoint};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
 use tracing::{Instrument, Span, instrument, trace};
 
 #[derive(Clone, Debug)]
 pub struct GrpcChannelPool {
     store: Arc<Mutex<HashMap<String, Channel>>>,
+    // set once at startup, channels are plaintext until then
+    tls: Arc<OnceCell<ClientTlsConfig>>,
 }
 impl GrpcChannelPool {
     fn new() -> Self {
         Self {
             store: Arc::default(),
+            tls: Arc::default(),
         }
     }
+    // every channel from now on presents the node certificate and checks the server against the
+    // namenode CA
+    pub fn enable_tls(&self, cert: &str, cert_key: &str, root_cert: &str) -> Result<()> {
+        let config = crate::tls::grpc_client_config(cert, cert_key, root_cert)?;
+        self.tls
+            .set(config)
+            .map_err(|_| "TLS is already enabled for grpc channels".into())
+    }
+    // server_name is the id the server certificate has to be issued for
     #[instrument(name = "grpc_pool_get_channel", skip(self))]
-    pub async fn get_channel(&self, addrs: &str) -> Result<Channel> {
+    pub async fn get_channel(&self, addrs: &str, server_name: &str) -> Result<Channel> {
         if let Some(chnl) = self.store.lock().await.get(addrs) {
             return Ok(chnl.clone());
         }
         trace!("Creating endpoint for channel since channel is not present already");
-        let endpoint = Endpoint::from_str(addrs)
-            .map_err(|e| format!("Error while creating an endpoint {e} for location {addrs}"))?
-            .connect_timeout(Duration::from_secs(5));
+        let endpoint = match self.tls.get() {
+            // tonic only does TLS for https uris, addresses are configured as http
+            Some(tls) => Endpoint::from_str(&addrs.replacen("http://", "https://", 1))
+                .and_then(|endpoint| endpoint.tls_config(tls.clone().domain_name(server_name))),
+            None => Endpoint::from_str(addrs),
+        }
+        .map_err(|e| format!("Error while creating an endpoint {e} for location {addrs}"))?
+        .connect_timeout(Duration::from_secs(5));
         let chnl = retry_with_backoff(
             || {
                 async {
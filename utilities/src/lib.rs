@@ -1,9 +1,12 @@
 pub mod auth;
-pub mod data_packet;
+pub mod checksum;
+pub mod data_transfer;
 pub mod grpc_channel_pool;
 pub mod logger;
+pub mod packet;
 pub mod result;
 pub mod retry_policy;
 pub mod state_logger;
 pub mod tcp_pool;
 pub mod ticket;
+pub mod tls;
@@ -0,0 +1,286 @@
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, WriteHalf},
+    sync::Semaphore,
+    task::JoinHandle,
+};
+use tracing::{Instrument, warn};
+
+use proto::generated::data_transfer::ChunkChecksums;
+
+use crate::{data_transfer, result::Result, tls::DataStream};
+
+// chunk content travels in packets of at most this size, same as a checksum block
+pub const PACKET_SIZE: usize = 64 * 1024;
+// packets a writer may have sent without an ack before it waits
+pub const PACKET_WINDOW: usize = 16;
+
+/// Piece of a chunk write. Offset is position of the data in the chunk and checksum is crc32c
+/// of the data so every datanode of the pipeline can check it before forwarding. Last packet is
+/// empty and is followed by the checksum trailer of the whole chunk
+#[derive(Debug, Clone)]
+pub struct Packet {
+    pub seqno: u64,
+    pub offset: u64,
+    pub last: bool,
+    pub checksum: u32,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    pub fn new(seqno: u64, offset: u64, data: Vec<u8>, last: bool) -> Self {
+        Self {
+            seqno,
+            offset,
+            last,
+            checksum: crc32c::crc32c(&data),
+            data,
+        }
+    }
+    pub fn verify(&self) -> Result<()> {
+        let checksum = crc32c::crc32c(&self.data);
+        if checksum != self.checksum {
+            return Err(format!(
+                "Checksum mismatch in packet {} at offset {}",
+                self.seqno, self.offset
+            )
+            .into());
+        }
+        Ok(())
+    }
+    // seqno, offset, last flag, checksum and data length followed by the data
+    pub async fn write_to(&self, stream: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        let mut buf = Vec::with_capacity(25 + self.data.len());
+        buf.extend_from_slice(&self.seqno.to_le_bytes());
+        buf.extend_from_slice(&self.offset.to_le_bytes());
+        buf.push(self.last as u8);
+        buf.extend_from_slice(&self.checksum.to_le_bytes());
+        buf.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&self.data);
+        stream.write_all(&buf).await?;
+        Ok(())
+    }
+    pub async fn read_from(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self> {
+        let seqno = stream.read_u64_le().await?;
+        let offset = stream.read_u64_le().await?;
+        let last = stream.read_u8().await? != 0;
+        let checksum = stream.read_u32_le().await?;
+        let len = stream.read_u32_le().await? as usize;
+        if len > PACKET_SIZE {
+            return Err(format!("Packet {seqno} has {len} bytes, limit is {PACKET_SIZE}").into());
+        }
+        let mut data = vec![0u8; len];
+        stream.read_exact(&mut data).await?;
+        Ok(Self {
+            seqno,
+            offset,
+            last,
+            checksum,
+            data,
+        })
+    }
+}
+
+/// Ack of a packet flowing back from the tail of the pipeline, every datanode puts its own
+/// status in front of the ones it got from the next replica. Replicas past a failed one are
+/// missing so status of a datanode is at its position in the pipeline
+#[derive(Debug, Clone)]
+pub struct Ack {
+    pub seqno: u64,
+    pub statuses: Vec<bool>,
+}
+
+impl Ack {
+    pub async fn write_to(&self, stream: &mut (impl AsyncWrite + Unpin)) -> Result<()> {
+        let mut buf = Vec::with_capacity(9 + self.statuses.len());
+        buf.extend_from_slice(&self.seqno.to_le_bytes());
+        buf.push(self.statuses.len() as u8);
+        buf.extend(self.statuses.iter().map(|ok| *ok as u8));
+        stream.write_all(&buf).await?;
+        Ok(())
+    }
+    pub async fn read_from(stream: &mut (impl AsyncRead + Unpin)) -> Result<Self> {
+        let seqno = stream.read_u64_le().await?;
+        let count = stream.read_u8().await? as usize;
+        let mut statuses = vec![0u8; count];
+        stream.read_exact(&mut statuses).await?;
+        Ok(Self {
+            seqno,
+            statuses: statuses.into_iter().map(|status| status != 0).collect(),
+        })
+    }
+}
+
+/// Writes a chunk to the first datanode of a pipeline as packets, at most PACKET_WINDOW of them
+/// are unacknowledged at any time. Acks are read in the background
+pub struct PacketSender {
+    writer: WriteHalf<DataStream>,
+    window: Arc<Semaphore>,
+    acks: JoinHandle<Option<Ack>>,
+    seqno: u64,
+    offset: u64,
+}
+
+impl PacketSender {
+    // stream is right after the accepted transfer request, offset is where in the chunk the write starts
+    pub fn new(stream: DataStream, offset: u64) -> Self {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let window = Arc::new(Semaphore::new(PACKET_WINDOW));
+        let acks = {
+            let window = window.clone();
+            tokio::spawn(
+                async move {
+                    let mut last_ack = None;
+                    while let Ok(ack) = Ack::read_from(&mut reader).await {
+                        if ack.statuses.contains(&false) {
+                            warn!(seqno = ack.seqno, statuses = ?ack.statuses, "Datanode of pipeline failed");
+                        }
+                        window.add_permits(1);
+                        last_ack = Some(ack);
+                    }
+                    // sender must not wait for acks which will never come
+                    window.close();
+                    last_ack
+                }
+                .in_current_span(),
+            )
+        };
+        Self {
+            writer,
+            window,
+            acks,
+            seqno: 0,
+            offset,
+        }
+    }
+    // sends everything reader has, returns number of bytes sent
+    pub async fn send(&mut self, reader: &mut (impl AsyncRead + Unpin)) -> Result<u64> {
+        let mut sent = 0;
+        let mut buf = vec![0u8; PACKET_SIZE];
+        loop {
+            // packets are filled up so only the last data packet is short
+            let mut filled = 0;
+            while filled < PACKET_SIZE {
+                let n = reader.read(&mut buf[filled..]).await?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+            if filled == 0 {
+                break;
+            }
+            self.send_packet(buf[..filled].to_vec(), false).await?;
+            sent += filled as u64;
+        }
+        Ok(sent)
+    }
+    async fn send_packet(&mut self, data: Vec<u8>, last: bool) -> Result<()> {
+        self.window
+            .acquire()
+            .await
+            .map_err(|_| "Pipeline closed before all packets were acknowledged")?
+            .forget();
+        let packet = Packet::new(self.seqno, self.offset, data, last);
+        packet.write_to(&mut self.writer).await?;
+        self.seqno += 1;
+        self.offset += packet.data.len() as u64;
+        Ok(())
+    }
+    // ends the write with the empty last packet and checksum trailer framed in the negotiated
+    // version, returns ack of the last packet which tells which datanodes stored the chunk
+    pub async fn finish(mut self, version: u16, checksums: Vec<u32>) -> Result<Ack> {
+        let last_seqno = self.seqno;
+        self.send_packet(vec![], true).await?;
+        data_transfer::write_frame(&mut self.writer, version, &ChunkChecksums { checksums })
+            .await?;
+        self.writer.flush().await?;
+        match self.acks.await? {
+            Some(ack) if ack.seqno == last_seqno => Ok(ack),
+            _ => Err("Pipeline closed before last packet was acknowledged".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn packet_round_trip() {
+        let packets = [
+            Packet::new(0, 0, vec![1u8; PACKET_SIZE], false),
+            Packet::new(1, PACKET_SIZE as u64, b"tail".to_vec(), false),
+            Packet::new(2, PACKET_SIZE as u64 + 4, vec![], true),
+        ];
+        let mut buf = vec![];
+        for packet in &packets {
+            packet.write_to(&mut buf).await.unwrap();
+        }
+        let mut stream = buf.as_slice();
+        for packet in &packets {
+            let read = Packet::read_from(&mut stream).await.unwrap();
+            assert_eq!(read.seqno, packet.seqno);
+            assert_eq!(read.offset, packet.offset);
+            assert_eq!(read.last, packet.last);
+            assert_eq!(read.checksum, packet.checksum);
+            assert_eq!(read.data, packet.data);
+            read.verify().unwrap();
+        }
+        assert!(stream.is_empty());
+    }
+
+    #[tokio::test]
+    async fn oversized_packet_is_refused() {
+        let mut packet = Packet::new(7, 0, vec![0u8; PACKET_SIZE], false);
+        packet.data.push(0);
+        let mut buf = vec![];
+        packet.write_to(&mut buf).await.unwrap();
+        let e = Packet::read_from(&mut buf.as_slice()).await.unwrap_err();
+        assert!(e.to_string().contains("limit"));
+    }
+
+    #[tokio::test]
+    async fn truncated_packet_is_refused() {
+        let mut buf = vec![];
+        Packet::new(0, 0, b"hello".to_vec(), false)
+            .write_to(&mut buf)
+            .await
+            .unwrap();
+        buf.pop();
+        assert!(Packet::read_from(&mut buf.as_slice()).await.is_err());
+    }
+
+    #[test]
+    fn corrupt_packet_fails_verification() {
+        let mut packet = Packet::new(3, 10, b"hello".to_vec(), false);
+        packet.verify().unwrap();
+        packet.data[0] ^= 1;
+        assert!(packet.verify().is_err());
+    }
+
+    #[tokio::test]
+    async fn ack_round_trip() {
+        let acks = [
+            Ack {
+                seqno: 5,
+                statuses: vec![true, true, false],
+            },
+            Ack {
+                seqno: 6,
+                statuses: vec![],
+            },
+        ];
+        let mut buf = vec![];
+        for ack in &acks {
+            ack.write_to(&mut buf).await.unwrap();
+        }
+        let mut stream = buf.as_slice();
+        for ack in &acks {
+            let read = Ack::read_from(&mut stream).await.unwrap();
+            assert_eq!(read.seqno, ack.seqno);
+            assert_eq!(read.statuses, ack.statuses);
+        }
+        assert!(Ack::read_from(&mut stream).await.is_err());
+    }
+}
@@ -1,17 +1,40 @@
-use crate::result::Result;
+use crate::{result::Result, tls::DataStream};
+use once_cell::sync::OnceCell;
+use rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
 
-#[derive(Debug, Default)]
-pub struct TcpPool {}
+#[derive(Default)]
+pub struct TcpPool {
+    // set once at startup, connections are plaintext until then
+    tls: OnceCell<TlsConnector>,
+}
 impl TcpPool {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            tls: OnceCell::new(),
+        }
+    }
+    // datanodes are verified against the namenode CA from now on
+    pub fn enable_tls(&self, root_cert: &str) -> Result<()> {
+        let connector = TlsConnector::from(crate::tls::client_config(root_cert)?);
+        self.tls
+            .set(connector)
+            .map_err(|_| "TLS is already enabled for tcp connections".into())
     }
-    pub async fn get_connection(&self, tcp_address: &str) -> Result<tokio::net::TcpStream> {
-        tokio::net::TcpStream::connect(tcp_address)
+    // datanode_id is the name the datanode certificate has to be issued for
+    pub async fn get_connection(&self, tcp_address: &str, datanode_id: &str) -> Result<DataStream> {
+        let stream = tokio::net::TcpStream::connect(tcp_address)
+            .await
+            .map_err(|e| format!("Error while connecting to stream at {tcp_address:?} {e:?}"))?;
+        let Some(connector) = self.tls.get() else {
+            return Ok(DataStream::Plain(stream));
+        };
+        let server_name = ServerName::try_from(datanode_id.to_owned())?;
+        let stream = connector
+            .connect(server_name, stream)
             .await
-            .map_err(|e| {
-                format!("Error while connecting to stream at {tcp_address:?} {e:?}").into()
-            })
+            .map_err(|e| format!("TLS handshake with {datanode_id} at {tcp_address} failed {e}"))?;
+        Ok(DataStream::Tls(Box::new(stream.into())))
     }
 }
 
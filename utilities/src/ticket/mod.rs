@@ -1,4 +1,5 @@
 pub mod ticket_decrypter;
 pub mod ticket_generator;
 pub mod ticket_mint;
+pub mod ticket_validator;
 pub mod types;
@@ -1,38 +1,80 @@
 use crate::result::Result;
-use crate::ticket::types::{ClientTicket, ServerTicket};
+use crate::ticket::ticket_generator::FIRST_KEY_ID;
+use crate::ticket::types::{ClientTicket, NodeKey, ServerTicket};
 use aes_gcm::aead::Aead;
 use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub trait TicketDecrypter: Send + Sync {
     fn decrypt_server_ticket(&self, encoded_ticket: &str) -> Result<ServerTicket>;
     fn decrypt_client_ticket(&self, encoded_ticket: &str) -> Result<ClientTicket>;
+    // replaces keys with the ones namenode handed out, keys missing from the list are dropped
+    fn set_keys(&self, keys: &[NodeKey]) -> Result<()>;
+    fn newest_key_id(&self) -> u32;
 }
 
-pub struct DefaultTicketDecrypter {
+struct HeldKey {
     key: Key<Aes256Gcm>,
+    // 0 when it doesn't expire
+    expires_at_secs: u64,
+}
+
+pub struct DefaultTicketDecrypter {
+    keys: RwLock<HashMap<u32, HeldKey>>,
 }
 
 impl DefaultTicketDecrypter {
+    // key from config is the one issued with the certificate
     pub fn new(encoded_key: &str) -> Result<Self> {
-        let decoded_key = BASE64_STANDARD.decode(encoded_key)?;
-        if decoded_key.len() != 32 {
-            return Err("Invalid key size expcted key of len 32".into());
-        }
-        let key = Key::<Aes256Gcm>::from_slice(&decoded_key);
-        Ok(Self { key: *key })
+        let key = decode_key(encoded_key)?;
+        let keys = HashMap::from([(
+            FIRST_KEY_ID,
+            HeldKey {
+                key,
+                expires_at_secs: 0,
+            },
+        )]);
+        Ok(Self {
+            keys: RwLock::new(keys),
+        })
     }
     fn decrypt(&self, encoded_ticket: &str) -> Result<Vec<u8>> {
         let decoded_ticket = BASE64_STANDARD.decode(encoded_ticket)?;
-        let (nonce_str, cipher_text) = decoded_ticket.split_at(12);
-        let cipher = Aes256Gcm::new(&self.key);
+        if decoded_ticket.len() < 16 {
+            return Err("Ticket is too short".into());
+        }
+        let (key_id, rest) = decoded_ticket.split_at(4);
+        let key_id = u32::from_be_bytes(key_id.try_into()?);
+        let (nonce_str, cipher_text) = rest.split_at(12);
+        let keys = self.keys.read().unwrap();
+        let held_key = keys
+            .get(&key_id)
+            .ok_or(format!("Ticket is encrypted with unknown key {key_id}"))?;
+        if held_key.expires_at_secs != 0 {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            if now > held_key.expires_at_secs {
+                return Err(format!("Ticket is encrypted with retired key {key_id}").into());
+            }
+        }
+        let cipher = Aes256Gcm::new(&held_key.key);
         cipher
             .decrypt(Nonce::from_slice(nonce_str), cipher_text)
             .map_err(|_e| "Error while decrypting the ticket".into())
     }
 }
 
+fn decode_key(encoded_key: &str) -> Result<Key<Aes256Gcm>> {
+    let decoded_key = BASE64_STANDARD.decode(encoded_key)?;
+    if decoded_key.len() != 32 {
+        return Err("Invalid key size expcted key of len 32".into());
+    }
+    Ok(*Key::<Aes256Gcm>::from_slice(&decoded_key))
+}
+
 impl TicketDecrypter for DefaultTicketDecrypter {
     fn decrypt_server_ticket(&self, encoded_ticket: &str) -> Result<ServerTicket> {
         let decrypt_u8 = self.decrypt(encoded_ticket)?;
@@ -44,4 +86,114 @@ impl TicketDecrypter for DefaultTicketDecrypter {
         let ct: ClientTicket = serde_json::from_slice(&decrypt_u8)?;
         Ok(ct)
     }
+    fn set_keys(&self, keys: &[NodeKey]) -> Result<()> {
+        if keys.is_empty() {
+            return Err("Namenode handed out no keys".into());
+        }
+        let mut held_keys = HashMap::new();
+        for node_key in keys {
+            held_keys.insert(
+                node_key.key_id,
+                HeldKey {
+                    key: decode_key(&node_key.key)?,
+                    expires_at_secs: node_key.expires_at_secs,
+                },
+            );
+        }
+        *self.keys.write().unwrap() = held_keys;
+        Ok(())
+    }
+    fn newest_key_id(&self) -> u32 {
+        self.keys
+            .read()
+            .unwrap()
+            .keys()
+            .max()
+            .copied()
+            .unwrap_or(FIRST_KEY_ID)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ticket::ticket_generator::{DefaultTicketGenerator, TicketGenerator};
+    use crate::ticket::types::Operation;
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn encrypt(generator: &mut DefaultTicketGenerator) -> String {
+        let st = ServerTicket {
+            target_node_id: "datanode-1".to_owned(),
+            operation: Operation::FetchChunk {
+                chunk_id: "chunk-1".to_owned(),
+            },
+            ttl_secs: 60,
+            minted_at_secs: now_secs(),
+            nonce: String::new(),
+        };
+        BASE64_STANDARD.encode(generator.encrypt_server_ticket(&st).unwrap())
+    }
+
+    fn node_key(key_id: u32, key: &str, expires_at_secs: u64) -> NodeKey {
+        NodeKey {
+            key_id,
+            key: key.to_owned(),
+            expires_at_secs,
+        }
+    }
+
+    #[test]
+    fn retired_key_is_refused_after_its_expiry() {
+        let mut generator = DefaultTicketGenerator::new();
+        let old_key = generator.upsert_node_key("datanode-1").unwrap();
+        let decrypter = DefaultTicketDecrypter::new(&old_key).unwrap();
+        let old_ticket = encrypt(&mut generator);
+        let new_key = BASE64_STANDARD.encode([7u8; 32]);
+
+        decrypter
+            .set_keys(&[
+                node_key(FIRST_KEY_ID, &old_key, now_secs() + 60),
+                node_key(FIRST_KEY_ID + 1, &new_key, 0),
+            ])
+            .unwrap();
+        assert!(decrypter.decrypt_server_ticket(&old_ticket).is_ok());
+
+        decrypter
+            .set_keys(&[
+                node_key(FIRST_KEY_ID, &old_key, now_secs() - 1),
+                node_key(FIRST_KEY_ID + 1, &new_key, 0),
+            ])
+            .unwrap();
+        assert!(decrypter.decrypt_server_ticket(&old_ticket).is_err());
+    }
+
+    #[test]
+    fn dropped_key_is_refused() {
+        let mut generator = DefaultTicketGenerator::new();
+        let old_key = generator.upsert_node_key("datanode-1").unwrap();
+        let decrypter = DefaultTicketDecrypter::new(&old_key).unwrap();
+        let old_ticket = encrypt(&mut generator);
+        let new_key = BASE64_STANDARD.encode([7u8; 32]);
+        decrypter
+            .set_keys(&[node_key(FIRST_KEY_ID + 1, &new_key, 0)])
+            .unwrap();
+        assert_eq!(decrypter.newest_key_id(), FIRST_KEY_ID + 1);
+        assert!(decrypter.decrypt_server_ticket(&old_ticket).is_err());
+    }
+
+    #[test]
+    fn bad_keys_are_refused() {
+        let decrypter = DefaultTicketDecrypter::new(&BASE64_STANDARD.encode([1u8; 32])).unwrap();
+        assert!(decrypter.set_keys(&[]).is_err());
+        let short_key = BASE64_STANDARD.encode([1u8; 16]);
+        assert!(decrypter.set_keys(&[node_key(2, &short_key, 0)]).is_err());
+        assert!(DefaultTicketDecrypter::new(&short_key).is_err());
+        assert!(decrypter.decrypt_server_ticket("c2hvcnQ=").is_err());
+    }
 }
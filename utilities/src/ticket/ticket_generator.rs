@@ -1,21 +1,90 @@
-use crate::ticket::types::ServerTicket;
+use crate::ticket::types::{NodeKey, ServerTicket};
 use crate::{result::Result, ticket::types::ClientTicket};
 use aes_gcm::aead::{Aead, OsRng};
 use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit};
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
 use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, info};
 
+// key handed out with the node certificate, rotated keys get the next ids
+pub const FIRST_KEY_ID: u32 = 1;
+
 pub trait TicketGenerator {
     fn upsert_node_key(&mut self, node_id: &str) -> Result<String>;
     fn upsert_node_key_with_key(&mut self, node_id: &str, encoded_key: &str) -> Result<()>;
+    // issues a new key for the node, tickets are encrypted with it once the node has it
+    fn rotate_node_key(&mut self, node_id: &str) -> Result<(u32, String)>;
+    fn rotate_node_key_with_key(
+        &mut self,
+        node_id: &str,
+        key_id: u32,
+        encoded_key: &str,
+    ) -> Result<()>;
+    // node told us the newest key it holds, returns true when tickets switch to that key
+    fn activate_node_key(&mut self, node_id: &str, key_id: u32) -> bool;
+    fn newest_node_key_id(&self, node_id: &str) -> Option<u32>;
+    // keys the node has to accept tickets with, retired keys stay for grace_secs
+    fn node_keys(&mut self, node_id: &str, grace_secs: u64) -> Result<Vec<NodeKey>>;
     fn encrypt_server_ticket(&mut self, st: &ServerTicket) -> Result<Vec<u8>>;
     fn encrypt_client_ticket(&mut self, st: &ClientTicket) -> Result<Vec<u8>>;
 }
 
+struct IssuedKey {
+    key_id: u32,
+    key: Key<Aes256Gcm>,
+    retired_at_secs: Option<u64>,
+}
+
+// keys of one node oldest first, the newest one is pending till the node reports it has it
+struct NodeKeys {
+    keys: Vec<IssuedKey>,
+    active_key_id: u32,
+}
+
+impl NodeKeys {
+    fn new(key: Key<Aes256Gcm>) -> Self {
+        Self {
+            keys: vec![IssuedKey {
+                key_id: FIRST_KEY_ID,
+                key,
+                retired_at_secs: None,
+            }],
+            active_key_id: FIRST_KEY_ID,
+        }
+    }
+    fn newest_key_id(&self) -> u32 {
+        self.keys.last().map(|k| k.key_id).unwrap_or(FIRST_KEY_ID)
+    }
+    fn active_key(&self) -> Option<&IssuedKey> {
+        self.keys.iter().find(|k| k.key_id == self.active_key_id)
+    }
+    fn prune(&mut self, now: u64, grace_secs: u64) {
+        self.keys.retain(|k| match k.retired_at_secs {
+            Some(retired_at) => retired_at.saturating_add(grace_secs) >= now,
+            None => true,
+        });
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn decode_key(encoded_key: &str) -> Result<Key<Aes256Gcm>> {
+    let decoded = BASE64_STANDARD.decode(encoded_key)?;
+    if decoded.len() != 32 {
+        return Err("Invalid key length: expected 32 bytes for AES-256".into());
+    }
+    Ok(*Key::<Aes256Gcm>::from_slice(&decoded))
+}
+
 pub struct DefaultTicketGenerator {
-    node_to_key: HashMap<String, Key<Aes256Gcm>>,
+    node_to_key: HashMap<String, NodeKeys>,
 }
 
 impl DefaultTicketGenerator {
@@ -24,14 +93,20 @@ impl DefaultTicketGenerator {
             node_to_key: HashMap::default(),
         }
     }
+    // encrypted ticket is key id (4 bytes big endian) followed by nonce and cipher text
     fn encrypt_with_node_key(&mut self, encoded_ticket: Vec<u8>, node_id: &str) -> Result<Vec<u8>> {
-        debug!("encrypting the ticket : {:?}", self.node_to_key);
-        if let Some(node_key) = self.node_to_key.get(node_id) {
-            let cipher = Aes256Gcm::new(node_key);
+        debug!("encrypting the ticket for node : {}", node_id);
+        if let Some(node_key) = self
+            .node_to_key
+            .get(node_id)
+            .and_then(|node_keys| node_keys.active_key())
+        {
+            let cipher = Aes256Gcm::new(&node_key.key);
             let nonce = Aes256Gcm::generate_nonce(&mut OsRng); // 96-bits; unique per message
             match cipher.encrypt(&nonce, encoded_ticket.as_ref()) {
                 Ok(mut v) => {
-                    let mut merged = nonce.to_vec();
+                    let mut merged = node_key.key_id.to_be_bytes().to_vec();
+                    merged.extend_from_slice(&nonce);
                     merged.append(&mut v);
                     Ok(merged)
                 }
@@ -41,6 +116,26 @@ impl DefaultTicketGenerator {
             Err("Invalid node id in server ticket".into())
         }
     }
+    fn push_node_key(&mut self, node_id: &str, key_id: u32, key: Key<Aes256Gcm>) -> Result<()> {
+        let node_keys = self
+            .node_to_key
+            .get_mut(node_id)
+            .ok_or(format!("No key issued for node {node_id}"))?;
+        if key_id <= node_keys.newest_key_id() {
+            return Err(format!(
+                "Key {key_id} of node {node_id} is not newer than {}",
+                node_keys.newest_key_id()
+            )
+            .into());
+        }
+        // tickets go on with the key node has till it reports the new one
+        node_keys.keys.push(IssuedKey {
+            key_id,
+            key,
+            retired_at_secs: None,
+        });
+        Ok(())
+    }
 }
 impl TicketGenerator for DefaultTicketGenerator {
     fn encrypt_server_ticket(&mut self, st: &ServerTicket) -> Result<Vec<u8>> {
@@ -53,16 +148,208 @@ impl TicketGenerator for DefaultTicketGenerator {
     }
     fn upsert_node_key(&mut self, node_id: &str) -> Result<String> {
         let key = Aes256Gcm::generate_key(&mut OsRng);
-        self.node_to_key.insert(node_id.to_string(), key);
+        self.node_to_key
+            .insert(node_id.to_string(), NodeKeys::new(key));
         Ok(BASE64_STANDARD.encode(key))
     }
     fn upsert_node_key_with_key(&mut self, node_id: &str, encoded_key: &str) -> Result<()> {
-        let decoded = BASE64_STANDARD.decode(encoded_key)?;
-        if decoded.len() != 32 {
-            return Err("Invalid key length: expected 32 bytes for AES-256".into());
-        }
-        let key = Key::<Aes256Gcm>::from_slice(&decoded);
-        self.node_to_key.insert(node_id.to_string(), *key);
+        let key = decode_key(encoded_key)?;
+        self.node_to_key
+            .insert(node_id.to_string(), NodeKeys::new(key));
         Ok(())
     }
+    fn rotate_node_key(&mut self, node_id: &str) -> Result<(u32, String)> {
+        let node_keys = self
+            .node_to_key
+            .get(node_id)
+            .ok_or(format!("No key issued for node {node_id}"))?;
+        if node_keys.newest_key_id() != node_keys.active_key_id {
+            return Err(format!(
+                "Node {node_id} has not picked up key {} yet",
+                node_keys.newest_key_id()
+            )
+            .into());
+        }
+        let key_id = node_keys.newest_key_id() + 1;
+        let key = Aes256Gcm::generate_key(&mut OsRng);
+        self.push_node_key(node_id, key_id, key)?;
+        info!(%node_id, key_id, "Issued new node key");
+        Ok((key_id, BASE64_STANDARD.encode(key)))
+    }
+    fn rotate_node_key_with_key(
+        &mut self,
+        node_id: &str,
+        key_id: u32,
+        encoded_key: &str,
+    ) -> Result<()> {
+        // a rotation is only issued once the one before is picked up, so the key that was newest
+        // is the one tickets were encrypted with
+        if let Some(node_keys) = self.node_to_key.get_mut(node_id) {
+            let newest_key_id = node_keys.newest_key_id();
+            let now = now_secs();
+            for key in &mut node_keys.keys {
+                if key.key_id != newest_key_id && key.retired_at_secs.is_none() {
+                    key.retired_at_secs = Some(now);
+                }
+            }
+            node_keys.active_key_id = newest_key_id;
+        }
+        self.push_node_key(node_id, key_id, decode_key(encoded_key)?)
+    }
+    fn activate_node_key(&mut self, node_id: &str, key_id: u32) -> bool {
+        let Some(node_keys) = self.node_to_key.get_mut(node_id) else {
+            return false;
+        };
+        if key_id != node_keys.newest_key_id() || key_id == node_keys.active_key_id {
+            return false;
+        }
+        let now = now_secs();
+        for key in &mut node_keys.keys {
+            if key.key_id == node_keys.active_key_id {
+                key.retired_at_secs = Some(now);
+            }
+        }
+        node_keys.active_key_id = key_id;
+        info!(%node_id, key_id, "Node picked up new key");
+        true
+    }
+    fn newest_node_key_id(&self, node_id: &str) -> Option<u32> {
+        self.node_to_key
+            .get(node_id)
+            .map(|node_keys| node_keys.newest_key_id())
+    }
+    fn node_keys(&mut self, node_id: &str, grace_secs: u64) -> Result<Vec<NodeKey>> {
+        let node_keys = self
+            .node_to_key
+            .get_mut(node_id)
+            .ok_or(format!("No key issued for node {node_id}"))?;
+        let now = now_secs();
+        node_keys.prune(now, grace_secs);
+        let newest_key_id = node_keys.newest_key_id();
+        Ok(node_keys
+            .keys
+            .iter()
+            .map(|k| {
+                let expires_at_secs = match k.retired_at_secs {
+                    Some(retired_at) => retired_at.saturating_add(grace_secs),
+                    // still in use till node reports the newest key, which is about to happen
+                    None if k.key_id != newest_key_id => now.saturating_add(grace_secs),
+                    None => 0,
+                };
+                NodeKey {
+                    key_id: k.key_id,
+                    key: BASE64_STANDARD.encode(k.key),
+                    expires_at_secs,
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ticket::ticket_decrypter::{DefaultTicketDecrypter, TicketDecrypter};
+    use crate::ticket::types::Operation;
+
+    const NODE_ID: &str = "datanode-1";
+
+    fn ticket() -> ServerTicket {
+        ServerTicket {
+            target_node_id: NODE_ID.to_owned(),
+            operation: Operation::FetchChunk {
+                chunk_id: "chunk-1".to_owned(),
+            },
+            ttl_secs: 60,
+            minted_at_secs: now_secs(),
+            nonce: String::new(),
+        }
+    }
+
+    fn encrypt(generator: &mut DefaultTicketGenerator) -> String {
+        BASE64_STANDARD.encode(generator.encrypt_server_ticket(&ticket()).unwrap())
+    }
+
+    fn key_id(encoded_ticket: &str) -> u32 {
+        let decoded = BASE64_STANDARD.decode(encoded_ticket).unwrap();
+        u32::from_be_bytes(decoded[..4].try_into().unwrap())
+    }
+
+    #[test]
+    fn rotated_key_round_trip() {
+        let mut generator = DefaultTicketGenerator::new();
+        let key = generator.upsert_node_key(NODE_ID).unwrap();
+        let decrypter = DefaultTicketDecrypter::new(&key).unwrap();
+
+        let (key_id_2, _) = generator.rotate_node_key(NODE_ID).unwrap();
+        assert_eq!(key_id_2, FIRST_KEY_ID + 1);
+        // node has not fetched the new key yet so tickets go on with the old one
+        let encoded = encrypt(&mut generator);
+        assert_eq!(key_id(&encoded), FIRST_KEY_ID);
+        assert!(decrypter.decrypt_server_ticket(&encoded).is_ok());
+
+        decrypter
+            .set_keys(&generator.node_keys(NODE_ID, 60).unwrap())
+            .unwrap();
+        assert_eq!(decrypter.newest_key_id(), key_id_2);
+        assert!(generator.activate_node_key(NODE_ID, key_id_2));
+        let encoded = encrypt(&mut generator);
+        assert_eq!(key_id(&encoded), key_id_2);
+        assert!(decrypter.decrypt_server_ticket(&encoded).is_ok());
+        // reporting the same key again changes nothing
+        assert!(!generator.activate_node_key(NODE_ID, key_id_2));
+    }
+
+    #[test]
+    fn unknown_key_is_not_activated() {
+        let mut generator = DefaultTicketGenerator::new();
+        generator.upsert_node_key(NODE_ID).unwrap();
+        assert!(!generator.activate_node_key(NODE_ID, FIRST_KEY_ID + 1));
+        assert!(!generator.activate_node_key("datanode-2", FIRST_KEY_ID));
+    }
+
+    #[test]
+    fn rotation_is_refused_while_one_is_pending() {
+        let mut generator = DefaultTicketGenerator::new();
+        generator.upsert_node_key(NODE_ID).unwrap();
+        let (key_id, _) = generator.rotate_node_key(NODE_ID).unwrap();
+        assert!(generator.rotate_node_key(NODE_ID).is_err());
+        assert!(generator.activate_node_key(NODE_ID, key_id));
+        let (next_key_id, _) = generator.rotate_node_key(NODE_ID).unwrap();
+        assert_eq!(next_key_id, key_id + 1);
+    }
+
+    #[test]
+    fn retired_key_is_dropped_after_grace() {
+        let mut generator = DefaultTicketGenerator::new();
+        let key = generator.upsert_node_key(NODE_ID).unwrap();
+        let decrypter = DefaultTicketDecrypter::new(&key).unwrap();
+        let old_ticket = encrypt(&mut generator);
+        let (key_id, _) = generator.rotate_node_key(NODE_ID).unwrap();
+        assert!(generator.activate_node_key(NODE_ID, key_id));
+
+        // within grace node holds both keys
+        let node_keys = generator.node_keys(NODE_ID, 60).unwrap();
+        assert_eq!(node_keys.len(), 2);
+        decrypter.set_keys(&node_keys).unwrap();
+        assert!(decrypter.decrypt_server_ticket(&old_ticket).is_ok());
+
+        // key was retired long ago, it is no longer handed out
+        generator
+            .node_to_key
+            .get_mut(NODE_ID)
+            .unwrap()
+            .keys
+            .iter_mut()
+            .for_each(|k| {
+                if k.key_id == FIRST_KEY_ID {
+                    k.retired_at_secs = Some(now_secs() - 120);
+                }
+            });
+        let node_keys = generator.node_keys(NODE_ID, 60).unwrap();
+        assert_eq!(node_keys.len(), 1);
+        assert_eq!(node_keys[0].key_id, key_id);
+        decrypter.set_keys(&node_keys).unwrap();
+        assert!(decrypter.decrypt_server_ticket(&old_ticket).is_err());
+    }
 }
@@ -1,8 +1,10 @@
 use crate::result::Result;
 use crate::ticket::{
     ticket_generator::TicketGenerator,
-    types::{ClientTicket, Operation, ServerTicket},
+    types::{ClientTicket, NodeKey, Operation, ServerTicket},
 };
+use aes_gcm::aead::OsRng;
+use aes_gcm::{AeadCore, Aes256Gcm};
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -37,6 +39,7 @@ impl TicketMint {
             minted_at_secs,
             ttl_secs,
             operation: op,
+            nonce: new_nonce(),
         };
         let encrypted_st = self.ticket_generator.encrypt_server_ticket(&st)?;
         let ct = ClientTicket {
@@ -60,6 +63,7 @@ impl TicketMint {
             minted_at_secs,
             ttl_secs,
             operation: op,
+            nonce: new_nonce(),
         };
         let encrypted_st = self.ticket_generator.encrypt_server_ticket(&st)?;
         Ok(BASE64_STANDARD.encode(encrypted_st))
@@ -72,4 +76,30 @@ impl TicketMint {
         self.ticket_generator
             .upsert_node_key_with_key(node_id, encoded_key)
     }
+    pub fn rotate_node_key(&mut self, node_id: &str) -> Result<(u32, String)> {
+        self.ticket_generator.rotate_node_key(node_id)
+    }
+    pub fn rotate_node_key_with_key(
+        &mut self,
+        node_id: &str,
+        key_id: u32,
+        encoded_key: &str,
+    ) -> Result<()> {
+        self.ticket_generator
+            .rotate_node_key_with_key(node_id, key_id, encoded_key)
+    }
+    pub fn activate_node_key(&mut self, node_id: &str, key_id: u32) -> bool {
+        self.ticket_generator.activate_node_key(node_id, key_id)
+    }
+    pub fn newest_node_key_id(&self, node_id: &str) -> Option<u32> {
+        self.ticket_generator.newest_node_key_id(node_id)
+    }
+    pub fn node_keys(&mut self, node_id: &str, grace_secs: u64) -> Result<Vec<NodeKey>> {
+        self.ticket_generator.node_keys(node_id, grace_secs)
+    }
+}
+
+// 96 random bits, same as the nonce tickets are encrypted with
+fn new_nonce() -> String {
+    BASE64_STANDARD.encode(Aes256Gcm::generate_nonce(&mut OsRng))
 }
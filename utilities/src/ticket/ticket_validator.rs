@@ -0,0 +1,236 @@
+use crate::ticket::{ticket_decrypter::TicketDecrypter, types::ServerTicket};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Why a server ticket was refused, each one is reported with its own status code
+#[derive(Debug)]
+pub enum TicketError {
+    Invalid(String),
+    Expired,
+    Replayed,
+}
+
+impl fmt::Display for TicketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TicketError::Invalid(reason) => write!(f, "Ticket is not valid, {reason}"),
+            TicketError::Expired => write!(f, "Ticket has expired"),
+            TicketError::Replayed => write!(f, "Ticket was already used"),
+        }
+    }
+}
+
+impl std::error::Error for TicketError {}
+
+/// Checks server tickets presented to a datanode. A ticket is good from minted_at_secs for
+/// ttl_secs, both ends stretched by the clock skew allowance as namenode clock is not ours.
+/// Nonces of single use tickets are kept until their ticket expires, after that the ticket is
+/// refused as expired anyway
+pub struct TicketValidator {
+    ticket_decrypter: Arc<Box<dyn TicketDecrypter>>,
+    clock_skew_secs: u64,
+    // nonce to the time its ticket expires
+    seen_nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl TicketValidator {
+    pub fn new(ticket_decrypter: Arc<Box<dyn TicketDecrypter>>, clock_skew_secs: u64) -> Self {
+        Self {
+            ticket_decrypter,
+            clock_skew_secs,
+            seen_nonces: Mutex::default(),
+        }
+    }
+    pub fn validate(&self, encoded_ticket: &str) -> Result<ServerTicket, TicketError> {
+        let ticket = self
+            .ticket_decrypter
+            .decrypt_server_ticket(encoded_ticket)
+            .map_err(|e| TicketError::Invalid(format!("error while decrypting/decoding {e}")))?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        if ticket.minted_at_secs > now + self.clock_skew_secs {
+            return Err(TicketError::Invalid(format!(
+                "minted {} secs in the future",
+                ticket.minted_at_secs - now
+            )));
+        }
+        let expires_at = ticket
+            .minted_at_secs
+            .saturating_add(ticket.ttl_secs)
+            .saturating_add(self.clock_skew_secs);
+        if now > expires_at {
+            return Err(TicketError::Expired);
+        }
+        if ticket.operation.is_single_use() {
+            if ticket.nonce.is_empty() {
+                return Err(TicketError::Invalid(
+                    "single use ticket has no nonce".into(),
+                ));
+            }
+            let mut seen_nonces = self.seen_nonces.lock().unwrap();
+            seen_nonces.retain(|_, expires_at| *expires_at >= now);
+            if seen_nonces
+                .insert(ticket.nonce.clone(), expires_at)
+                .is_some()
+            {
+                return Err(TicketError::Replayed);
+            }
+        }
+        Ok(ticket)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ticket::{
+        ticket_decrypter::DefaultTicketDecrypter,
+        ticket_generator::{DefaultTicketGenerator, TicketGenerator},
+        types::Operation,
+    };
+    use base64::{Engine, prelude::BASE64_STANDARD};
+
+    const SKEW: u64 = 30;
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn setup() -> (DefaultTicketGenerator, TicketValidator) {
+        let mut generator = DefaultTicketGenerator::new();
+        let key = generator.upsert_node_key("datanode-1").unwrap();
+        let decrypter: Box<dyn TicketDecrypter> =
+            Box::new(DefaultTicketDecrypter::new(&key).unwrap());
+        (generator, TicketValidator::new(Arc::new(decrypter), SKEW))
+    }
+
+    fn ticket(
+        operation: Operation,
+        minted_at_secs: u64,
+        ttl_secs: u64,
+        nonce: &str,
+    ) -> ServerTicket {
+        ServerTicket {
+            target_node_id: "datanode-1".to_owned(),
+            operation,
+            ttl_secs,
+            minted_at_secs,
+            nonce: nonce.to_owned(),
+        }
+    }
+
+    fn encrypt(generator: &mut DefaultTicketGenerator, ticket: &ServerTicket) -> String {
+        BASE64_STANDARD.encode(generator.encrypt_server_ticket(ticket).unwrap())
+    }
+
+    fn delete(chunk_id: &str) -> Operation {
+        Operation::DeleteChunk {
+            chunk_id: chunk_id.to_owned(),
+        }
+    }
+
+    #[test]
+    fn fresh_ticket_is_accepted() {
+        let (mut generator, validator) = setup();
+        let st = ticket(delete("chunk-1"), now_secs(), 60, "nonce-1");
+        let validated = validator.validate(&encrypt(&mut generator, &st)).unwrap();
+        assert_eq!(validated.nonce, "nonce-1");
+    }
+
+    #[test]
+    fn expired_ticket_is_refused() {
+        let (mut generator, validator) = setup();
+        // expired 10 secs past the skew allowance
+        let st = ticket(
+            delete("chunk-1"),
+            now_secs() - 60 - SKEW - 10,
+            60,
+            "nonce-1",
+        );
+        let result = validator.validate(&encrypt(&mut generator, &st));
+        assert!(matches!(result, Err(TicketError::Expired)));
+    }
+
+    #[test]
+    fn ticket_minted_in_future_is_refused() {
+        let (mut generator, validator) = setup();
+        let st = ticket(delete("chunk-1"), now_secs() + SKEW + 60, 60, "nonce-1");
+        let result = validator.validate(&encrypt(&mut generator, &st));
+        assert!(matches!(result, Err(TicketError::Invalid(_))));
+        // within the skew namenode clock is just ahead of ours
+        let st = ticket(delete("chunk-1"), now_secs() + SKEW - 10, 60, "nonce-1");
+        assert!(validator.validate(&encrypt(&mut generator, &st)).is_ok());
+    }
+
+    #[test]
+    fn single_use_nonce_is_refused_on_replay() {
+        let (mut generator, validator) = setup();
+        let st = ticket(delete("chunk-1"), now_secs(), 60, "nonce-1");
+        let encoded = encrypt(&mut generator, &st);
+        assert!(validator.validate(&encoded).is_ok());
+        assert!(matches!(
+            validator.validate(&encoded),
+            Err(TicketError::Replayed)
+        ));
+        // same nonce in a new ticket is a replay as well
+        let st = ticket(delete("chunk-2"), now_secs(), 60, "nonce-1");
+        assert!(matches!(
+            validator.validate(&encrypt(&mut generator, &st)),
+            Err(TicketError::Replayed)
+        ));
+    }
+
+    #[test]
+    fn multi_use_ticket_is_accepted_again() {
+        let (mut generator, validator) = setup();
+        let operation = Operation::StoreChunk {
+            chunk_id: "chunk-1".to_owned(),
+        };
+        let encoded = encrypt(&mut generator, &ticket(operation, now_secs(), 60, ""));
+        assert!(validator.validate(&encoded).is_ok());
+        assert!(validator.validate(&encoded).is_ok());
+    }
+
+    #[test]
+    fn single_use_ticket_without_nonce_is_refused() {
+        let (mut generator, validator) = setup();
+        let st = ticket(delete("chunk-1"), now_secs(), 60, "");
+        let result = validator.validate(&encrypt(&mut generator, &st));
+        assert!(matches!(result, Err(TicketError::Invalid(_))));
+    }
+
+    #[test]
+    fn nonce_is_evicted_after_its_ticket_expires() {
+        let (mut generator, validator) = setup();
+        let now = now_secs();
+        validator.seen_nonces.lock().unwrap().extend([
+            ("expired".to_owned(), now - 1),
+            ("live".to_owned(), now + 60),
+        ]);
+        let st = ticket(delete("chunk-1"), now, 60, "expired");
+        assert!(validator.validate(&encrypt(&mut generator, &st)).is_ok());
+        let seen_nonces = validator.seen_nonces.lock().unwrap();
+        assert!(seen_nonces.contains_key("live"));
+        // evicted and then stored again with the expiry of the new ticket
+        assert_eq!(seen_nonces.get("expired"), Some(&(now + 60 + SKEW)));
+    }
+
+    #[test]
+    fn ticket_for_other_key_is_refused() {
+        let (_, validator) = setup();
+        let mut other = DefaultTicketGenerator::new();
+        other.upsert_node_key("datanode-1").unwrap();
+        let st = ticket(delete("chunk-1"), now_secs(), 60, "nonce-1");
+        let result = validator.validate(&encrypt(&mut other, &st));
+        assert!(matches!(result, Err(TicketError::Invalid(_))));
+    }
+}
@@ -9,12 +9,25 @@ pub enum Operation {
     ReplicateChunk { chunk_id: String },
 }
 
+impl Operation {
+    // deleting or replicating a chunk is done once per ticket, other tickets are used for every
+    // step of their operation like store, transfer and commit
+    pub fn is_single_use(&self) -> bool {
+        matches!(
+            self,
+            Operation::DeleteChunk { .. } | Operation::ReplicateChunk { .. }
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ServerTicket {
     pub target_node_id: String,
     pub operation: Operation,
     pub ttl_secs: u64,
     pub minted_at_secs: u64,
+    // random per ticket, datanode remembers it for single use operations
+    pub nonce: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -24,3 +37,13 @@ pub struct ClientTicket {
     pub ttl_secs: u64,
     pub minted_at_secs: u64,
 }
+
+// key of a node as namenode hands it out, tickets carry the id of the key they are encrypted with
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NodeKey {
+    pub key_id: u32,
+    // base64 encoded AES-256 key
+    pub key: String,
+    // unix secs after which tickets encrypted with this key are refused, 0 when it doesn't expire
+    pub expires_at_secs: u64,
+}
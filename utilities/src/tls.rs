@@ -0,0 +1,151 @@
+use crate::result::Result;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use rustls::{
+    ClientConfig, RootCertStore, ServerConfig,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+};
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tokio_rustls::{TlsAcceptor, TlsStream};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+
+// name the namenode certificate is issued for, nodes reach namenode by address
+pub const NAMENODE_SERVER_NAME: &str = "namenode";
+
+// certificates and keys are base64 encoded der as handed out by namenode on /cert/issue
+
+// grpc servers take only callers with a certificate issued by the namenode CA
+pub fn grpc_server_config(cert: &str, cert_key: &str, root_cert: &str) -> Result<ServerTlsConfig> {
+    // tonic builds its server config with the process wide provider
+    let _ = ring::default_provider().install_default();
+    Ok(ServerTlsConfig::new()
+        .identity(identity(cert, cert_key)?)
+        .client_ca_root(Certificate::from_pem(to_pem("CERTIFICATE", root_cert)?)))
+}
+
+// server name is set per connection, it is the id of the node being called
+pub fn grpc_client_config(cert: &str, cert_key: &str, root_cert: &str) -> Result<ClientTlsConfig> {
+    Ok(ClientTlsConfig::new()
+        .identity(identity(cert, cert_key)?)
+        .ca_certificate(Certificate::from_pem(to_pem("CERTIFICATE", root_cert)?)))
+}
+
+fn identity(cert: &str, cert_key: &str) -> Result<Identity> {
+    Ok(Identity::from_pem(
+        to_pem("CERTIFICATE", cert)?,
+        to_pem("PRIVATE KEY", cert_key)?,
+    ))
+}
+
+fn to_pem(tag: &str, der: &str) -> Result<String> {
+    Ok(pem::encode(&pem::Pem::new(
+        tag,
+        BASE64_STANDARD.decode(der)?,
+    )))
+}
+
+// datanode serves its node certificate, the one namenode issued it
+pub fn server_config(cert: &str, cert_key: &str) -> Result<Arc<ServerConfig>> {
+    let cert = CertificateDer::from(BASE64_STANDARD.decode(cert)?);
+    let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(BASE64_STANDARD.decode(cert_key)?));
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)?;
+    Ok(Arc::new(config))
+}
+
+// only certificates issued by the namenode CA are trusted
+pub fn client_config(root_cert: &str) -> Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(BASE64_STANDARD.decode(root_cert)?))?;
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Accepts data plane connections of a datanode, TLS handshake is done per connection so a
+/// slow peer never holds up the accept loop
+#[derive(Clone)]
+pub struct DataAcceptor {
+    tls: Option<TlsAcceptor>,
+}
+
+impl DataAcceptor {
+    pub fn plaintext() -> Self {
+        Self { tls: None }
+    }
+    pub fn tls(cert: &str, cert_key: &str) -> Result<Self> {
+        Ok(Self {
+            tls: Some(TlsAcceptor::from(server_config(cert, cert_key)?)),
+        })
+    }
+    pub async fn accept(&self, stream: TcpStream) -> Result<DataStream> {
+        match &self.tls {
+            Some(acceptor) => {
+                let stream = acceptor
+                    .accept(stream)
+                    .await
+                    .map_err(|e| format!("TLS handshake failed {e}"))?;
+                Ok(DataStream::Tls(Box::new(stream.into())))
+            }
+            None => Ok(DataStream::Plain(stream)),
+        }
+    }
+}
+
+/// Connection of the data plane, plaintext only when TLS is turned off
+#[derive(Debug)]
+pub enum DataStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl AsyncRead for DataStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DataStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            DataStream::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for DataStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            DataStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            DataStream::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DataStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            DataStream::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            DataStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            DataStream::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}